@@ -0,0 +1,60 @@
+//! Benchmarks of listing workspaces, which runs on every shell completion
+//!
+//! Runs `wsctl` against a config directory of 5000 workspaces in 50 groups, like completion does.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Number of workspace groups
+const GROUPS: usize = 50;
+
+/// Number of workspaces in each group
+const WORKSPACES: usize = 100;
+
+/// Creates a home directory with [`GROUPS`] times [`WORKSPACES`] workspace definitions
+fn home() -> PathBuf {
+    let home = std::env::temp_dir().join(format!("wsctl-bench-{}", std::process::id()));
+    let dir = home.join("config/workspaces");
+    for group in 0..GROUPS {
+        let group = dir.join(format!("group-{group:02}"));
+        fs::create_dir_all(&group).unwrap();
+        for workspace in 0..WORKSPACES {
+            let path = group.join(format!("ws-{workspace:03}.toml"));
+            fs::write(path, "dir = \"/tmp\"\n").unwrap();
+        }
+    }
+    home
+}
+
+/// Runs `wsctl list` with `args` in `home`
+fn list(home: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_wsctl"))
+        .arg("list")
+        .args(args)
+        .env("HOME", home)
+        .env("WORKSPACECTL_CONFIG_DIR", home.join("config"))
+        .env("WORKSPACECTL_CACHE_DIR", home.join("cache"))
+        .env("WORKSPACECTL_STATE_DIR", home.join("state"))
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn bench(c: &mut Criterion) {
+    let home = home();
+    c.bench_function("list 5k", |b| b.iter(|| list(&home, &[])));
+    c.bench_function("list 5k in one group", |b| {
+        b.iter(|| list(&home, &["group-07/*"]))
+    });
+    c.bench_function("list 5k one workspace", |b| {
+        b.iter(|| list(&home, &["group-07/ws-04?"]))
+    });
+    fs::remove_dir_all(&home).unwrap();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
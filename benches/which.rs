@@ -0,0 +1,54 @@
+//! Benchmarks of finding the workspace of the working directory, which prompts run on every render
+//!
+//! Runs `wsctl which` in one of 500 workspace directories, with the directory index already built.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Number of workspaces
+const WORKSPACES: usize = 500;
+
+/// Creates a home directory with [`WORKSPACES`] workspaces, each with its own directory
+fn home() -> PathBuf {
+    let home = std::env::temp_dir().join(format!("wsctl-bench-which-{}", std::process::id()));
+    let dir = home.join("config/workspaces");
+    fs::create_dir_all(&dir).unwrap();
+    for workspace in 0..WORKSPACES {
+        fs::create_dir_all(home.join(format!("src/ws-{workspace:03}/nested"))).unwrap();
+        let path = dir.join(format!("ws-{workspace:03}.toml"));
+        fs::write(path, format!("dir = \"src/ws-{workspace:03}\"\n")).unwrap();
+    }
+    home
+}
+
+/// Runs `wsctl which` in directory `cwd` of `home`
+fn which(home: &Path, cwd: &str) {
+    let status = Command::new(env!("CARGO_BIN_EXE_wsctl"))
+        .arg("which")
+        .current_dir(home.join(cwd))
+        .env("HOME", home)
+        .env("WORKSPACECTL_CONFIG_DIR", home.join("config"))
+        .env("WORKSPACECTL_CACHE_DIR", home.join("cache"))
+        .env("WORKSPACECTL_STATE_DIR", home.join("state"))
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn bench(c: &mut Criterion) {
+    let home = home();
+    // The first run builds the index.
+    which(&home, "src/ws-042");
+    c.bench_function("which 500", |b| b.iter(|| which(&home, "src/ws-042")));
+    c.bench_function("which 500 nested", |b| {
+        b.iter(|| which(&home, "src/ws-042/nested"))
+    });
+    fs::remove_dir_all(&home).unwrap();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
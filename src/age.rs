@@ -0,0 +1,85 @@
+//! Encryption of workspace definitions with [age](https://age-encryption.org)
+//!
+//! Encrypted definitions are stored with an additional `.age` extension, for example
+//! `secret.toml.age`. Files are encrypted to the recipient of the configured identity so the same
+//! identity decrypts them again. The `age` command line tool does the actual work.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, ensure, Context, Result};
+
+use crate::config;
+
+/// Extension added to the names of encrypted files
+pub const EXTENSION: &str = "age";
+
+/// Returns the path to the identity used to encrypt and decrypt workspace definitions
+///
+/// Configured by `age.identity` in the global config, relative paths are relative to the home
+/// directory. Defaults to `age/keys.txt` in the user config directory.
+fn identity() -> Result<PathBuf> {
+    let configured = config::read()?
+        .and_then(|config| config.age)
+        .and_then(|age| age.identity);
+    let path = match configured {
+        Some(path) => dirs::home_dir()
+            .context("could not determine user home directory")?
+            .join(path),
+        None => dirs::config_dir()
+            .context("could not determine user config directory")?
+            .join("age/keys.txt"),
+    };
+    ensure!(
+        path.is_file(),
+        "age identity {path:?} is not available, set `age.identity` in the config",
+    );
+    Ok(path)
+}
+
+/// Decrypts the file at `path`
+pub fn decrypt(path: &Path) -> Result<String> {
+    let identity = identity()?;
+    let output = Command::new("age")
+        .arg("--decrypt")
+        .arg("--identity")
+        .arg(&identity)
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .context("spawn age")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("{}", stderr.trim())).with_context(|| format!("decrypting {path:?}"));
+    }
+    String::from_utf8(output.stdout).with_context(|| format!("decrypted {path:?} is not utf-8"))
+}
+
+/// Encrypts `buf` to the recipient of the configured identity
+pub fn encrypt(buf: &str) -> Result<Vec<u8>> {
+    let identity = identity()?;
+    let mut child = Command::new("age")
+        .arg("--encrypt")
+        .arg("--identity")
+        .arg(&identity)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("spawn age")?;
+    // Write from a separate thread so a full stdout pipe can't deadlock us.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let buf = buf.to_owned();
+    let writer = std::thread::spawn(move || stdin.write_all(buf.as_bytes()));
+    let output = child.wait_with_output().context("wait for age")?;
+    writer
+        .join()
+        .expect("writer thread doesn't panic")
+        .context("writing to age")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("{}", stderr.trim())).context("encrypting workspace definition");
+    }
+    Ok(output.stdout)
+}
@@ -4,45 +4,226 @@
 //! (`~/.cache/workspacectl`), each key maps to a file name and the value is the
 //! file's contents stripped of whitespace. Values must always be valid utf-8
 //! and cannot contain newlines.
+//!
+//! Log keys like [`Key::History`] instead hold one value per line and are
+//! appended to.
+//!
+//! Keys like [`Key::Host`] are a family of keys, one per host, stored in a
+//! subdirectory. Their values expire, see [`read_fresh`].
+//!
+//! Keys which have to change together are changed in a [`Transaction`].
+//!
+//! Files which can't have been written this way, after a crash or a full disk, are moved into the
+//! `corrupt` subdirectory when they're read, see [`quarantine`]. A corrupt value reads as missing,
+//! corrupt lines of a log are dropped.
 
-use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use atomicwrites::AtomicFile;
+use serde_derive::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{paths, runner};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Key {
     /// Currently open workspace
     Current,
+
+    /// Log of opened workspaces
+    History,
+
+    /// Log of windows opened by `start`
+    Windows,
+
+    /// Log of events for subscribers
+    Events,
+
+    /// Detected properties of a remote host
+    Host(String),
+
+    /// Socket of the Neovim instance last opened for a workspace
+    Nvim(String),
+
+    /// Directory of the scratch workspace
+    Scratch,
+
+    /// Reverse index from directories to workspaces, see [`crate::index`]
+    Dirs,
+
+    /// Token changed with every change to the workspace definitions, see
+    /// [`crate::workspace::generation`]
+    Generation,
+
+    /// Git status and reachability of a workspace, see [`crate::status`]
+    Status(String),
+
+    /// Project config of a remote workspace, see [`crate::project`]
+    Project(String),
+
+    /// Currently open workspace of a terminal session, see [`crate::session`]
+    Session(String),
 }
 
+/// Subdirectory holding the [`Key::Host`] keys
+const HOSTS: &str = "hosts";
+
+/// Subdirectory holding the [`Key::Nvim`] keys
+const NVIM: &str = "nvim";
+
+/// Subdirectory holding the [`Key::Status`] keys
+const STATUS: &str = "status";
+
+/// Subdirectory holding the [`Key::Project`] keys
+const PROJECTS: &str = "projects";
+
+/// Subdirectory holding the [`Key::Session`] keys
+const TERMINALS: &str = "terminals";
+
 impl Key {
-    fn filename(&self) -> &'static str {
+    fn filename(&self) -> PathBuf {
         match self {
-            Key::Current => "current",
+            Key::Current => "current".into(),
+            Key::History => "history".into(),
+            Key::Windows => "windows".into(),
+            Key::Events => "events".into(),
+            Key::Scratch => "scratch".into(),
+            Key::Dirs => "dirs".into(),
+            Key::Generation => "generation".into(),
+            Key::Host(host) => Path::new(HOSTS).join(encode_file_name(host)),
+            // Workspace names are valid relative paths, groups become directories.
+            Key::Nvim(name) => Path::new(NVIM).join(name),
+            Key::Status(name) => Path::new(STATUS).join(name),
+            Key::Project(name) => Path::new(PROJECTS).join(name),
+            Key::Session(session) => Path::new(TERMINALS).join(session),
+        }
+    }
+}
+
+/// Returns `name` as a file name which can't escape its directory or collide with another name
+///
+/// `%`, path separators and a leading `.` are percent-encoded, the empty name becomes `%`. See
+/// [`decode_file_name`].
+pub fn encode_file_name(name: &str) -> String {
+    if name.is_empty() {
+        return "%".to_owned();
+    }
+    let mut encoded = String::with_capacity(name.len());
+    for (index, ch) in name.char_indices() {
+        match ch {
+            '%' | '/' | '\\' => encoded.push_str(&format!("%{:02X}", ch as u8)),
+            '.' if index == 0 => encoded.push_str("%2E"),
+            ch => encoded.push(ch),
+        }
+    }
+    encoded
+}
+
+/// Returns the name encoded as `file_name` by [`encode_file_name`]
+pub fn decode_file_name(file_name: &str) -> String {
+    let bytes = file_name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                // The empty name is a lone `%`, which decodes to nothing.
+                if bytes[index] != b'%' {
+                    decoded.push(bytes[index]);
+                }
+                index += 1;
+            }
         }
     }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Returns path to the cache directory
+pub fn dir_path() -> Result<PathBuf> {
+    paths::cache_dir()
 }
 
-fn dir_path() -> Result<PathBuf> {
-    let cache_dir = dirs::cache_dir().context("could not determine user cache directory")?;
-    Ok(cache_dir.join("workspacectl"))
+/// Returns path to the directory of state which is kept when the cache is cleared
+pub fn state_dir_path() -> Result<PathBuf> {
+    paths::state_dir()
+}
+
+/// Returns the path of the file storing `key`
+pub fn path(key: &Key) -> Result<PathBuf> {
+    Ok(dir_path()?.join(key.filename()))
+}
+
+/// Returns the path of the file storing `key`, creating the directory it's in
+fn create_path(key: &Key) -> Result<PathBuf> {
+    let path = path(key)?;
+    let dir = path.parent().expect("cache file is in a directory");
+    fs::create_dir_all(dir).with_context(|| format!("could not cache directory at {dir:?}"))?;
+    Ok(path)
 }
 
 pub fn read(key: Key) -> Result<String> {
+    recover()?;
+    let dir = dir_path()?;
+    let path = dir.join(key.filename());
+    let buf = fs::read(&path).with_context(|| format!("reading cache file at {path:?}"))?;
+    let problem = match String::from_utf8(buf) {
+        Ok(buf) if buf.is_empty() => "is empty",
+        Ok(buf) if buf.contains('\0') => "contains NUL bytes",
+        Ok(buf) if buf.trim().contains('\n') => "has more than one line",
+        Ok(buf) => return Ok(buf.trim().to_owned()),
+        Err(_) => "is not valid utf-8",
+    };
+    quarantine(&dir, &path, problem)?;
+    Err(anyhow!("cache file at {path:?} was corrupt"))
+}
+
+/// Read the value of `key` if it was written less than `ttl` ago
+///
+/// A missing key is `None` like an expired one.
+pub fn read_fresh(key: Key, ttl: Duration) -> Result<Option<String>> {
+    let path = dir_path()?.join(key.filename());
+    let modified = match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("reading cache file at {path:?}")),
+    };
+    // A modification time in the future counts as fresh.
+    if modified.elapsed().is_ok_and(|age| age >= ttl) {
+        return Ok(None);
+    }
+    read(key).map(Some)
+}
+
+/// Read the value of `key` if it was written at or after `since`, or at all without `since`
+///
+/// A missing key is `None` like an older one.
+pub fn read_since(key: Key, since: Option<SystemTime>) -> Result<Option<String>> {
     let path = dir_path()?.join(key.filename());
-    Ok(fs::read_to_string(&path)
-        .with_context(|| format!("reading cache file at {path:?}"))?
-        .trim()
-        .to_owned())
+    let modified = match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("reading cache file at {path:?}")),
+    };
+    if since.is_some_and(|since| modified < since) {
+        return Ok(None);
+    }
+    read(key).map(Some)
 }
 
 pub fn write(key: Key, value: String) -> Result<()> {
-    let path = dir_path()?;
-    fs::create_dir_all(&path).with_context(|| format!("could not cache directory at {path:?}"))?;
-    let path = path.join(key.filename());
+    let path = create_path(&key)?;
     AtomicFile::new(&path, atomicwrites::AllowOverwrite)
         .write(|file| {
             file.write_all(value.trim().as_bytes())?;
@@ -50,3 +231,390 @@ pub fn write(key: Key, value: String) -> Result<()> {
         })
         .with_context(|| format!("atomically write cache file at {path:?}"))
 }
+
+/// Remove a key, removing a missing key is not an error
+pub fn remove(key: Key) -> Result<()> {
+    let path = dir_path()?.join(key.filename());
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("removing cache file at {path:?}")),
+    }
+}
+
+/// Remove all [`Key::Host`] keys
+pub fn remove_hosts() -> Result<()> {
+    remove_family(HOSTS)
+}
+
+/// Remove all [`Key::Project`] keys
+pub fn remove_projects() -> Result<()> {
+    remove_family(PROJECTS)
+}
+
+/// Remove all keys stored in subdirectory `subdir`
+fn remove_family(subdir: &str) -> Result<()> {
+    let path = dir_path()?.join(subdir);
+    match fs::remove_dir_all(&path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("removing cache directory at {path:?}")),
+    }
+}
+
+/// Returns the hosts which have a [`Key::Host`] key
+pub fn hosts() -> Result<Vec<String>> {
+    let hosts = family(HOSTS)?;
+    Ok(hosts.iter().map(|host| decode_file_name(host)).collect())
+}
+
+/// Returns the sessions which have a [`Key::Session`] key
+pub fn sessions() -> Result<Vec<String>> {
+    family(TERMINALS)
+}
+
+/// Returns the workspaces which have a [`Key::Nvim`] key
+pub fn nvim_names() -> Result<Vec<String>> {
+    family(NVIM)
+}
+
+/// Returns the workspaces which have a [`Key::Status`] key
+pub fn status_names() -> Result<Vec<String>> {
+    family(STATUS)
+}
+
+/// Returns the workspaces which have a [`Key::Project`] key
+pub fn project_names() -> Result<Vec<String>> {
+    family(PROJECTS)
+}
+
+/// Returns the names of the keys stored in subdirectory `subdir`, as paths relative to it
+fn family(subdir: &str) -> Result<Vec<String>> {
+    let dir = dir_path()?.join(subdir);
+    let mut names = Vec::new();
+    for entry in WalkDir::new(&dir).min_depth(1) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err)
+                if err
+                    .io_error()
+                    .is_some_and(|err| err.kind() == ErrorKind::NotFound) =>
+            {
+                break;
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("listing cache directory {dir:?}"))
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let name = entry
+            .path()
+            .strip_prefix(&dir)
+            .expect("walked path is in the directory");
+        let name = name
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy());
+        names.push(name.collect::<Vec<_>>().join("/"));
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Append a line to a log key
+pub fn append(key: Key, line: &str) -> Result<()> {
+    let path = create_path(&key)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("opening cache file at {path:?}"))?;
+    // A single write so concurrent appends don't interleave.
+    file.write_all(format!("{}\n", line.trim()).as_bytes())
+        .with_context(|| format!("appending to cache file at {path:?}"))
+}
+
+/// Read all lines of a log key
+///
+/// A missing key is an empty log.
+pub fn read_lines(key: Key) -> Result<Vec<String>> {
+    recover()?;
+    let dir = dir_path()?;
+    let path = dir.join(key.filename());
+    let buf = match fs::read(&path) {
+        Ok(buf) => buf,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("reading cache file at {path:?}")),
+    };
+    if let Ok(buf) = std::str::from_utf8(&buf) {
+        if !buf.contains('\0') {
+            return Ok(buf.lines().map(|line| line.to_owned()).collect());
+        }
+    }
+
+    let mut lines = Vec::new();
+    for line in buf.split(|byte| *byte == b'\n') {
+        match std::str::from_utf8(line) {
+            Ok(line) if !line.contains('\0') && !line.is_empty() => lines.push(line.to_owned()),
+            _ => {}
+        }
+    }
+    // Other processes could be appending, the cache is only locked against transactions.
+    let _lock = lock(&dir)?;
+    quarantine(&dir, &path, "has corrupt lines")?;
+    write_lines(key, &lines)?;
+    Ok(lines)
+}
+
+/// Replace all lines of a log key
+pub fn write_lines(key: Key, lines: &[String]) -> Result<()> {
+    let path = create_path(&key)?;
+    AtomicFile::new(&path, atomicwrites::AllowOverwrite)
+        .write(|file| {
+            lines.iter().try_for_each(|line| {
+                file.write_all(line.trim().as_bytes())?;
+                file.write_all(b"\n")
+            })
+        })
+        .with_context(|| format!("atomically write cache file at {path:?}"))
+}
+
+/// Directory holding the changes of a [`Transaction`] until they're applied
+const JOURNAL: &str = "journal";
+
+/// File listing the staged changes of a committed [`Transaction`]
+const MANIFEST: &str = "manifest.json";
+
+/// Lock file serializing transactions between processes
+const LOCK: &str = "lock";
+
+/// A change to one key, as listed in the manifest
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Staged {
+    /// Replace the file at `target` with the staged file `staged`, paths relative to the cache
+    Replace { staged: String, target: PathBuf },
+
+    /// Remove the file at `target`
+    Remove { target: PathBuf },
+
+    /// Append the lines in the staged file `staged` to the log at `target`
+    Append { staged: String, target: PathBuf },
+}
+
+/// Change to one key made by a [`Transaction`]
+#[derive(Debug)]
+enum Change {
+    /// Replace the value
+    Write(String),
+
+    /// Append lines to the log
+    Append(String),
+
+    /// Remove the key
+    Remove,
+}
+
+/// Changes to several keys which are applied all together or not at all
+///
+/// New values are written to files in [`JOURNAL`] first, then a manifest listing them is written
+/// atomically, then they're moved into place. A crash before the manifest is written loses the
+/// whole transaction, a crash after it is finished by the next process using the cache.
+///
+/// Lines appended to logs are added to whatever the log holds when the transaction is committed,
+/// so appends of concurrent processes aren't lost.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    changes: Vec<(Key, Change)>,
+}
+
+impl Transaction {
+    /// Sets `key` to `value`
+    pub fn write(&mut self, key: Key, value: &str) -> &mut Transaction {
+        self.changes
+            .push((key, Change::Write(format!("{}\n", value.trim()))));
+        self
+    }
+
+    /// Replaces all lines of log `key` with `lines`
+    pub fn write_lines(&mut self, key: Key, lines: &[String]) -> &mut Transaction {
+        let value = lines
+            .iter()
+            .map(|line| format!("{}\n", line.trim()))
+            .collect();
+        self.changes.push((key, Change::Write(value)));
+        self
+    }
+
+    /// Appends `line` to log `key`
+    pub fn append(&mut self, key: Key, line: &str) -> &mut Transaction {
+        self.changes
+            .push((key, Change::Append(format!("{}\n", line.trim()))));
+        self
+    }
+
+    /// Removes `key`
+    pub fn remove(&mut self, key: Key) -> &mut Transaction {
+        self.changes.push((key, Change::Remove));
+        self
+    }
+
+    /// Applies all changes
+    ///
+    /// Nothing is applied while commands are only printed, see [`runner::is_dry_run`].
+    pub fn commit(self) -> Result<()> {
+        if runner::is_dry_run() {
+            tracing::debug!(changes = self.changes.len(), "dry run, not committing");
+            return Ok(());
+        }
+        let dir = dir_path()?;
+        let _lock = lock(&dir)?;
+        recover_locked(&dir)?;
+
+        let journal = dir.join(JOURNAL);
+        fs::create_dir_all(&journal)
+            .with_context(|| format!("could not create journal directory at {journal:?}"))?;
+        let mut manifest = Vec::new();
+        for (index, (key, change)) in self.changes.into_iter().enumerate() {
+            let target = key.filename();
+            let value = match &change {
+                Change::Write(value) | Change::Append(value) => value,
+                Change::Remove => {
+                    manifest.push(Staged::Remove { target });
+                    continue;
+                }
+            };
+            let staged = index.to_string();
+            let path = journal.join(&staged);
+            let mut file =
+                fs::File::create(&path).with_context(|| format!("staging {target:?}"))?;
+            file.write_all(value.as_bytes())
+                .and_then(|()| file.sync_all())
+                .with_context(|| format!("staging {target:?}"))?;
+            manifest.push(match change {
+                Change::Append(_) => Staged::Append { staged, target },
+                _ => Staged::Replace { staged, target },
+            });
+        }
+        let buf = serde_json::to_vec(&manifest).context("serializing transaction manifest")?;
+        let path = journal.join(MANIFEST);
+        AtomicFile::new(&path, atomicwrites::AllowOverwrite)
+            .write(|file| file.write_all(&buf))
+            .with_context(|| format!("writing transaction manifest {path:?}"))?;
+        apply(&dir)?;
+        // Clean up the journal.
+        recover_locked(&dir)
+    }
+}
+
+/// Locks the cache in `dir` against transactions of other processes until the file is dropped
+fn lock(dir: &Path) -> Result<fs::File> {
+    fs::create_dir_all(dir).with_context(|| format!("could not cache directory at {dir:?}"))?;
+    let path = dir.join(LOCK);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("opening lock file {path:?}"))?;
+    file.lock().with_context(|| format!("locking {path:?}"))?;
+    Ok(file)
+}
+
+/// Subdirectory corrupt cache files are moved to
+const CORRUPT: &str = "corrupt";
+
+/// Moves corrupt cache file `path` into [`CORRUPT`] in cache `dir`, reporting `problem`
+///
+/// The file is kept for inspection, with the time it was moved appended to its name. A file moved
+/// away by another process in the meantime is left alone.
+fn quarantine(dir: &Path, path: &Path, problem: &str) -> Result<()> {
+    let corrupt = dir.join(CORRUPT);
+    fs::create_dir_all(&corrupt)
+        .with_context(|| format!("could not create directory at {corrupt:?}"))?;
+    let name = path.strip_prefix(dir).unwrap_or(path).to_string_lossy();
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let target = corrupt.join(format!("{}.{time}", name.replace(['/', '\\'], "_")));
+    match fs::rename(path, &target) {
+        Ok(()) => {
+            eprintln!("WARN cache file {path:?} {problem}, moved it to {target:?}");
+            Ok(())
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("moving corrupt cache file {path:?}")),
+    }
+}
+
+/// Appends the lines of staged file `staged` to log `target` and removes the staged file
+fn append_staged(staged: &Path, target: &Path) -> io::Result<()> {
+    let lines = fs::read(staged)?;
+    let parent = target.parent().expect("cache file is in a directory");
+    fs::create_dir_all(parent)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(target)?;
+    // A single write so concurrent appends don't interleave.
+    file.write_all(&lines)?;
+    fs::remove_file(staged)
+}
+
+/// Finishes a transaction interrupted after it was committed
+///
+/// Called before reading, so a crash never leaves half of a transaction visible for long.
+fn recover() -> Result<()> {
+    let dir = dir_path()?;
+    if !dir.join(JOURNAL).join(MANIFEST).exists() {
+        return Ok(());
+    }
+    let _lock = lock(&dir)?;
+    recover_locked(&dir)
+}
+
+/// Finishes an interrupted transaction and removes changes staged by uncommitted ones
+fn recover_locked(dir: &Path) -> Result<()> {
+    let journal = dir.join(JOURNAL);
+    if journal.join(MANIFEST).exists() {
+        eprintln!("INFO finishing an interrupted update of the cache");
+        apply(dir)?;
+    }
+    match fs::remove_dir_all(&journal) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("removing journal {journal:?}")),
+    }
+}
+
+/// Moves the changes listed in the manifest into place and removes the manifest
+///
+/// Changes already moved into place are skipped, so an interrupted apply can be repeated. Only
+/// lines appended right before the interruption can end up appended twice.
+fn apply(dir: &Path) -> Result<()> {
+    let journal = dir.join(JOURNAL);
+    let path = journal.join(MANIFEST);
+    let buf = fs::read(&path).with_context(|| format!("reading transaction manifest {path:?}"))?;
+    let Ok(manifest) = serde_json::from_slice::<Vec<Staged>>(&buf) else {
+        // The manifest is written atomically, nothing of an unreadable one can be trusted.
+        return quarantine(dir, &path, "is not a valid transaction manifest");
+    };
+    for staged in manifest {
+        let result = match &staged {
+            Staged::Replace { staged, target } => {
+                let target = dir.join(target);
+                let parent = target.parent().expect("cache file is in a directory");
+                fs::create_dir_all(parent).and_then(|()| fs::rename(journal.join(staged), &target))
+            }
+            Staged::Remove { target } => fs::remove_file(dir.join(target)),
+            Staged::Append { staged, target } => {
+                append_staged(&journal.join(staged), &dir.join(target))
+            }
+        };
+        match result {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err).with_context(|| format!("applying {staged:?}")),
+        }
+    }
+    fs::remove_file(&path).with_context(|| format!("removing transaction manifest {path:?}"))
+}
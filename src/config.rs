@@ -1,26 +1,49 @@
 use std::fs;
-use std::io::ErrorKind;
-use std::path::PathBuf;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
 
 pub mod data;
 use anyhow::{Context, Result};
+use atomicwrites::AtomicFile;
 pub use data::*;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use toml::map::Entry;
 use toml::{Table, Value};
+use toml_edit::Document;
+
+use crate::{migrate, parse, paths, prompt, workspace};
+
+impl parse::Schema for Config {
+    fn known_keys(path: &[String]) -> &'static [&'static str] {
+        match path {
+            [] => parse::fields::<Config>(),
+            [table] if table == "editor" => parse::fields::<workspace::Editor>(),
+            [table] if table == "shell" => parse::fields::<workspace::Shell>(),
+            [table] if table == "list" => parse::fields::<List>(),
+            [table] if table == "age" => parse::fields::<Age>(),
+            [table] if table == "ssh" => parse::fields::<Ssh>(),
+            [table] if table == "terminal" => parse::fields::<Terminal>(),
+            [table] if table == "hooks" => parse::fields::<workspace::Hooks>(),
+            [table] if table == "git" => parse::fields::<Git>(),
+            _ => &[],
+        }
+    }
+}
 
 /// Returns path to the config directory
-fn dir_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir().context("could not determine user config directory")?;
-    Ok(config_dir.join("workspacectl"))
+pub fn dir_path() -> Result<PathBuf> {
+    paths::config_dir()
 }
 
 /// Returns path to the config file
-fn config_path() -> Result<PathBuf> {
+pub fn config_path() -> Result<PathBuf> {
     Ok(dir_path()?.join("config.toml"))
 }
 
+/// Config keys which provide defaults for workspace definitions
+const WORKSPACE_DEFAULTS: &[&str] = &["editor", "shell", "multiplexer"];
+
 pub fn read() -> Result<Option<Config>> {
     let path = config_path()?;
     let buf = match fs::read_to_string(&path) {
@@ -30,7 +53,9 @@ pub fn read() -> Result<Option<Config>> {
             return Err(err).with_context(|| format!("reading config file at {path:?}"))?;
         }
     };
-    toml::from_str(&buf)
+    let buf = migrate::upgrade(buf, migrate::Kind::Config)
+        .with_context(|| format!("upgrading config file at {path:?}"))?;
+    parse::file(&path, &buf, parse::Format::Toml)
         .with_context(|| format!("parsing config file at {path:?}"))
         .map(Some)
 }
@@ -44,6 +69,19 @@ where
         return Ok(config);
     };
 
+    let mut defaults = toml::Value::try_from(defaults).context("convert defaults to toml Value")?;
+    if let Value::Table(defaults) = &mut defaults {
+        defaults.retain(|key, _| WORKSPACE_DEFAULTS.contains(&key));
+    }
+    fill(config, defaults)
+}
+
+/// Fills in keys missing from `config` from `defaults`, tables are merged key by key
+pub fn fill<T, D>(config: T, defaults: D) -> Result<T>
+where
+    T: Serialize + DeserializeOwned,
+    D: Serialize,
+{
     let defaults = toml::Value::try_from(defaults).context("convert defaults to toml Value")?;
     let mut config = toml::Value::try_from(config).context("convert T to toml Value")?;
 
@@ -53,9 +91,9 @@ where
 }
 
 fn fill_defaults_value(config: &mut Value, defaults: Value) {
-    match (config, defaults) {
-        (Value::Table(config), Value::Table(defaults)) => fill_defaults_table(config, defaults),
-        _ => {} // Only tables get merged, for anything else the config is left intact.
+    // Only tables get merged, for anything else the config is left intact.
+    if let (Value::Table(config), Value::Table(defaults)) = (config, defaults) {
+        fill_defaults_table(config, defaults);
     }
 }
 
@@ -71,3 +109,79 @@ fn fill_defaults_table(config: &mut Table, defaults: Table) {
         }
     }
 }
+
+/// Returns the contents of the config file, empty if there is none
+fn read_buf(path: &Path) -> Result<String> {
+    match fs::read_to_string(path) {
+        Ok(buf) => Ok(buf),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(err).with_context(|| format!("reading config file at {path:?}")),
+    }
+}
+
+/// Checks config file contents `buf` meant for `path`, unknown keys are errors
+fn check(path: &Path, buf: &str) -> Result<()> {
+    let buf = migrate::upgrade(buf.to_owned(), migrate::Kind::Config)
+        .with_context(|| format!("upgrading config file at {path:?}"))?;
+    parse::check_strict::<Config>(path, &buf)
+}
+
+/// Writes config file contents `buf`, which must already be checked
+fn write(path: &Path, buf: &str) -> Result<()> {
+    let dir = path.parent().expect("config file is in a directory");
+    fs::create_dir_all(dir).with_context(|| format!("creating config directory at {dir:?}"))?;
+    AtomicFile::new(path, atomicwrites::AllowOverwrite)
+        .write(|file| file.write_all(buf.as_bytes()))
+        .with_context(|| format!("atomically write config file at {path:?}"))
+}
+
+/// Returns the value of `.` separated `key` as written in the config file, `None` if it isn't set
+pub fn get(key: &str) -> Result<Option<Value>> {
+    let path = config_path()?;
+    let buf = read_buf(&path)?;
+    let value = parse::value(&path, &buf, parse::Format::Toml)
+        .with_context(|| format!("parsing config file at {path:?}"))?;
+    Ok(key
+        .split('.')
+        .try_fold(&value, |value, key| value.get(key))
+        .cloned())
+}
+
+/// Sets `.` separated `key` in the config file to `value`, keeping its comments and formatting
+///
+/// The config file is created if there is none. The edited config is checked before it's written.
+pub fn set(key: &str, value: toml_edit::Value) -> Result<()> {
+    let path = config_path()?;
+    let mut document = read_buf(&path)?
+        .parse::<Document>()
+        .with_context(|| format!("parsing config file at {path:?}"))?;
+    workspace::set_key(&mut document, key, value)?;
+    let buf = document.to_string();
+    check(&path, &buf).context("edit results in an invalid config")?;
+    write(&path, &buf)
+}
+
+/// Opens the config file in `editor` and saves it once it's valid
+///
+/// The config is edited in a draft next to it. An invalid draft is opened again if the user wants
+/// to fix it, or else kept and edited the next time.
+pub fn edit(editor: &str) -> Result<()> {
+    let path = config_path()?;
+    let draft = dir_path()?.join("config.draft.toml");
+    let original = read_buf(&path)?;
+    let buf = match fs::read_to_string(&draft) {
+        Ok(buf) => {
+            eprintln!("INFO continuing the unsaved edit in {draft:?}");
+            buf
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => original.clone(),
+        Err(err) => return Err(err).with_context(|| format!("reading draft at {draft:?}")),
+    };
+    write(&draft, &buf)?;
+    let buf = prompt::edit(editor, &draft, "config", check)?;
+    if buf != original {
+        write(&path, &buf)?;
+        println!("saved config file {path:?}");
+    }
+    fs::remove_file(&draft).with_context(|| format!("removing draft at {draft:?}"))
+}
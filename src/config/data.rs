@@ -1,12 +1,143 @@
+use clap::ValueEnum;
 use serde_derive::{Deserialize, Serialize};
 
-use crate::workspace;
+use crate::{launch, workspace};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// Schema version of the config file
+    pub version: Option<i64>,
+
     /// Editor configuration
     pub editor: Option<workspace::Editor>,
 
     /// Shell configuration
     pub shell: Option<workspace::Shell>,
+
+    /// `list` command configuration
+    pub list: Option<List>,
+
+    /// Encryption of workspace definitions
+    pub age: Option<Age>,
+
+    /// Options for connecting to remote workspaces
+    pub ssh: Option<Ssh>,
+
+    /// Terminal windows are opened in
+    pub terminal: Option<Terminal>,
+
+    /// Commands run for every workspace, before or after its own hooks
+    pub hooks: Option<workspace::Hooks>,
+
+    /// Git related defaults
+    pub git: Option<Git>,
+
+    /// Seconds non-interactive commands may take, 30 if not set and at least 1
+    ///
+    /// Also used as the ssh `ConnectTimeout`. Workspaces can override it.
+    pub timeout: Option<u64>,
+
+    /// Confirm everything without asking, like `--yes`
+    pub assume_yes: Option<bool>,
+
+    /// Group of workspaces created without `new --group`, none if not set
+    ///
+    /// `{{org}}` is replaced with the owner of the directory's git remote `origin`, like `acme`
+    /// for `git@github.com:acme/api.git`. Directories without one get no group.
+    pub default_group: Option<String>,
+
+    /// Default terminal multiplexer of workspaces, see [`workspace::Multiplexer`]
+    pub multiplexer: Option<workspace::Multiplexer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct List {
+    /// Default sort order, `name` if not set
+    pub sort: Option<Sort>,
+
+    /// Whether symlinked group directories in the workspaces directory are listed, `true` if not
+    /// set
+    pub follow_symlinks: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Age {
+    /// Path to the age identity file, relative to the home directory
+    pub identity: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ssh {
+    /// Program asked for passphrases and passwords by ssh connections made in the background
+    ///
+    /// Without one such connections fail instead of prompting.
+    pub askpass: Option<String>,
+
+    /// Default host key verification policy
+    pub host_key: Option<workspace::HostKey>,
+
+    /// Default terminfo handling in remote sessions
+    pub terminfo: Option<workspace::Terminfo>,
+
+    /// Default `TERM` used with `terminfo = "term"`
+    pub term: Option<String>,
+
+    /// Default locale of remote sessions, exported as `LANG` and `LC_ALL`
+    pub locale: Option<String>,
+
+    /// Seconds detected properties of remote hosts are reused for, a day if not set
+    pub cache_ttl: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Terminal {
+    /// Terminal emulator backend, the first installed terminal is used if not set
+    pub backend: Option<launch::Backend>,
+
+    /// Terminal emulator command for terminals without a backend, takes precedence over `backend`
+    pub command: Option<String>,
+
+    /// Arguments of `command`, see [`launch`] for the placeholders
+    pub args: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Git {
+    /// Git UI opened by `git-ui`, `lazygit` if not set
+    ///
+    /// The command is run in the workspace directory, for example `gitui` or `tig`.
+    pub ui: Option<String>,
+}
+
+/// Order of workspaces printed by `list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    /// Alphabetically by name
+    Name,
+
+    /// Most recently opened first
+    Recent,
+
+    /// Most frequently and recently opened first
+    Frecency,
+
+    /// Grouped by ssh host, local workspaces first
+    Host,
+
+    /// Most recently created first
+    Created,
+}
+
+/// Output format of `list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// Names only, one per line
+    Plain,
+
+    /// A JSON array of objects with the name, directory and host of each workspace
+    Json,
+
+    /// Names, directories and hosts in aligned columns
+    Table,
 }
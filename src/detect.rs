@@ -0,0 +1,228 @@
+//! Detection of remote host properties
+//!
+//! Remote workspaces which don't configure a shell or an editor use the defaults of the remote
+//! user. They're detected over ssh and cached per host for `ssh.cache_ttl` seconds, so opening a
+//! terminal doesn't connect twice. `cache clear` forgets them early.
+//!
+//! Each detection also stores the host's capability profile, which programs it has and what
+//! system it runs, as `hosts/<host>.json` in the user state directory. Unlike the cache it's kept
+//! until the next detection, so features which only benefit from knowing the host, like `grep`
+//! picking between `rg` and `grep`, never connect just to find out.
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use atomicwrites::AtomicFile;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::cache::{self, Key};
+use crate::{config, process, runtime, ssh, workspace};
+
+/// How long detected properties are reused when `ssh.cache_ttl` isn't set
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Editors looked for on remote hosts
+const EDITORS: &[&str] = &["nvim", "vim", "vi", "emacs", "nano", "hx", "micro", "kak"];
+
+/// Other programs features of `workspacectl` can make use of
+const TOOLS: &[&str] = &["tmux", "rg", "fd", "git", "nix"];
+
+/// Properties of a remote host and the remote user's environment
+///
+/// Fields are `None` when they couldn't be detected, or were stored by a version which didn't
+/// detect them yet.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Host {
+    /// Login shell, `$SHELL`
+    pub shell: Option<String>,
+
+    /// Preferred editor, `$VISUAL` or `$EDITOR`
+    pub editor: Option<String>,
+
+    /// Operating system, `uname -s`
+    pub os: Option<String>,
+
+    /// Machine architecture, `uname -m`
+    pub arch: Option<String>,
+
+    /// Home directory of the remote user, `$HOME`
+    pub home: Option<String>,
+
+    /// Installed editors out of [`EDITORS`]
+    pub editors: Option<Vec<String>>,
+
+    /// Installed programs out of [`TOOLS`]
+    pub tools: Option<Vec<String>>,
+}
+
+impl Host {
+    /// Returns whether `program` is installed, `None` if it's not known
+    pub fn has(&self, program: &str) -> Option<bool> {
+        let installed = if EDITORS.contains(&program) {
+            self.editors.as_ref()?
+        } else if TOOLS.contains(&program) {
+            self.tools.as_ref()?
+        } else {
+            return None;
+        };
+        Some(installed.iter().any(|installed| installed == program))
+    }
+}
+
+/// Returns the properties of `ssh`'s host, detecting them if they aren't cached
+///
+/// `timeout` is the workspace's timeout.
+pub fn host(ssh: &workspace::Ssh, timeout: Option<u64>) -> Result<Host> {
+    let key = Key::Host(ssh.host.clone());
+    // A cache which can't be read is only a missed shortcut.
+    if let Some(cached) = cache::read_fresh(key.clone(), ttl()?).ok().flatten() {
+        if let Ok(host) = serde_json::from_str(&cached) {
+            return Ok(host);
+        }
+    }
+
+    let host = detect(ssh, timeout)?;
+    let json = serde_json::to_string(&host).context("serializing detected host")?;
+    cache::write(key, json).context("caching detected host")?;
+    if let Err(err) = write_profile(&ssh.host, &host) {
+        eprintln!("WARN could not store host profile: {err:#}");
+    }
+    Ok(host)
+}
+
+/// Returns how long what's read from remote hosts is cached, `ssh.cache_ttl` from the config
+pub fn ttl() -> Result<Duration> {
+    Ok(config::read()?
+        .and_then(|config| config.ssh)
+        .and_then(|ssh| ssh.cache_ttl)
+        .map_or(DEFAULT_TTL, Duration::from_secs))
+}
+
+/// Detects the properties of `ssh`'s host
+fn detect(ssh: &workspace::Ssh, timeout: Option<u64>) -> Result<Host> {
+    let programs = EDITORS.iter().chain(TOOLS).copied().collect::<Vec<_>>();
+    // One value per line, the installed programs on the last one.
+    let script = format!(
+        r#"printf '%s\n' "$SHELL" "${{VISUAL:-$EDITOR}}" "$(uname -s)" "$(uname -m)" "$HOME"; for p in {}; do command -v "$p" >/dev/null 2>&1 && printf '%s ' "$p"; done; echo"#,
+        programs.join(" "),
+    );
+    let mut command = ssh::background(ssh, timeout)?;
+    command.arg(script);
+    let output = process::output_with_timeout(command, runtime::timeout(timeout)?)
+        .context("detect remote host")?
+        .with_context(|| format!("timed out detecting {:?}", ssh.host))?;
+    if !output.status.success() {
+        return Err(ssh::failure(&ssh.host, &output)).context("detect remote host");
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(str::trim);
+    let mut next = || {
+        lines
+            .next()
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+    };
+    let (shell, editor, os, arch, home) = (next(), next(), next(), next(), next());
+    let installed = next().unwrap_or_default();
+    let installed = installed.split_whitespace().collect::<Vec<_>>();
+    let filter = |known: &[&str]| {
+        let found = known.iter().filter(|program| installed.contains(program));
+        Some(found.map(|program| program.to_string()).collect())
+    };
+    Ok(Host {
+        shell,
+        editor,
+        os,
+        arch,
+        home,
+        editors: filter(EDITORS),
+        tools: filter(TOOLS),
+    })
+}
+
+/// Returns path to the directory storing host profiles
+fn profile_dir() -> Result<PathBuf> {
+    Ok(cache::state_dir_path()?.join("hosts"))
+}
+
+/// Returns path to the profile of `host`
+fn profile_path(host: &str) -> Result<PathBuf> {
+    let host = cache::encode_file_name(host);
+    Ok(profile_dir()?.join(format!("{host}.json")))
+}
+
+/// Stores the capability profile of `host`
+fn write_profile(host: &str, profile: &Host) -> Result<()> {
+    let dir = profile_dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("creating directory {dir:?}"))?;
+    let path = profile_path(host)?;
+    let json = serde_json::to_string_pretty(profile).context("serializing host profile")?;
+    AtomicFile::new(&path, atomicwrites::AllowOverwrite)
+        .write(|file| {
+            file.write_all(json.as_bytes())?;
+            file.write_all(b"\n")
+        })
+        .with_context(|| format!("writing host profile {path:?}"))
+}
+
+/// Returns the stored capability profile of `host`, if it was ever detected
+pub fn profile(host: &str) -> Result<Option<Host>> {
+    let path = profile_path(host)?;
+    let buf = match fs::read_to_string(&path) {
+        Ok(buf) => buf,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("reading host profile {path:?}")),
+    };
+    serde_json::from_str(&buf)
+        .with_context(|| format!("parsing host profile {path:?}"))
+        .map(Some)
+}
+
+/// Returns the hosts which have a stored capability profile
+pub fn profile_hosts() -> Result<Vec<String>> {
+    let dir = profile_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("listing host profiles in {dir:?}")),
+    };
+    let mut hosts = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("listing host profiles in {dir:?}"))?;
+        let path = entry.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            if let Some(host) = path.file_stem() {
+                hosts.push(cache::decode_file_name(&host.to_string_lossy()));
+            }
+        }
+    }
+    hosts.sort();
+    Ok(hosts)
+}
+
+/// Removes the stored capability profile of `host`
+pub fn remove_profile(host: &str) -> Result<()> {
+    let path = profile_path(host)?;
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("removing host profile {path:?}")),
+    }
+}
+
+/// Forgets the detected properties of `host`, or of all hosts
+///
+/// Only the cache is cleared, stored profiles stay until they're replaced.
+pub fn clear(host: Option<&str>) -> Result<()> {
+    match host {
+        Some(host) => cache::remove(Key::Host(host.to_owned())),
+        None => cache::remove_hosts(),
+    }
+}
@@ -0,0 +1,175 @@
+//! Stream of workspace events for external tools
+//!
+//! Events are appended to a log in the cache as JSON lines, for example
+//! `{"time":1700000000,"event":"workspace-opened","workspace":"foo"}`. `events --follow` prints
+//! them as they happen, so time trackers or status bars can react without polling `workspacectl`.
+//! Only the most recent events are kept.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::process::{Command, ExitStatus};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_derive::Serialize;
+
+use crate::cache::{self, Key};
+use crate::{history, runner};
+
+/// Number of events kept when the log is trimmed
+const KEEP: usize = 1000;
+
+/// The log is trimmed to [`KEEP`] events once it's larger than this many bytes
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/// How often `--follow` checks for new events
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Something that happened, as seen by subscribers
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    /// A workspace was opened by `open`, `start` or `activate`
+    WorkspaceOpened { workspace: &'a str },
+
+    /// A workspace was closed by `close`
+    WorkspaceClosed { workspace: &'a str },
+
+    /// A process was spawned for a workspace, like a window, a hook or a plugin
+    SpawnStarted {
+        workspace: Option<&'a str>,
+        program: &'a str,
+        pid: u32,
+    },
+
+    /// A process `workspacectl` waited on exited, `code` is `None` if it was killed by a signal
+    SpawnExited {
+        workspace: Option<&'a str>,
+        program: &'a str,
+        pid: u32,
+        code: Option<i32>,
+    },
+}
+
+/// An event with the time it happened
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    /// Unix time in seconds
+    time: u64,
+
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// Appends `event` to the log
+///
+/// Subscribers are only informed, failing to reach them never fails a command so errors are only
+/// reported. Nothing happened while commands are only printed, so nothing is appended then.
+pub fn emit(event: Event) {
+    if runner::is_dry_run() {
+        return;
+    }
+    let record = Record {
+        time: history::now(),
+        event,
+    };
+    if let Err(err) = append(&record) {
+        eprintln!("WARN could not record event: {err:#}");
+    }
+}
+
+/// Runs `command` to completion like [`runner::status`], emitting its start and exit
+pub fn status(command: &mut Command, workspace: Option<&str>) -> io::Result<ExitStatus> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let Some(mut child) = runner::spawn(command)? else {
+        return Ok(ExitStatus::default());
+    };
+    let pid = child.id();
+    emit(Event::SpawnStarted {
+        workspace,
+        program: &program,
+        pid,
+    });
+    let status = child.wait()?;
+    emit(Event::SpawnExited {
+        workspace,
+        program: &program,
+        pid,
+        code: status.code(),
+    });
+    Ok(status)
+}
+
+fn append(record: &Record) -> Result<()> {
+    let line = serde_json::to_string(record).context("serializing event")?;
+    cache::append(Key::Events, &line)?;
+    let path = cache::path(&Key::Events)?;
+    let size = path.metadata().map_or(0, |metadata| metadata.len());
+    if size > MAX_SIZE {
+        let lines = cache::read_lines(Key::Events)?;
+        let keep = lines.len().saturating_sub(KEEP);
+        cache::write_lines(Key::Events, &lines[keep..])?;
+    }
+    Ok(())
+}
+
+/// Prints the logged events, then waits for new ones and prints them too if `follow`
+pub fn print(follow: bool) -> Result<()> {
+    let path = cache::path(&Key::Events)?;
+    let mut stdout = io::stdout().lock();
+    let mut last = None;
+    let mut offset = 0;
+    loop {
+        let file = match File::open(&path) {
+            Ok(file) => Some(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err).with_context(|| format!("opening {path:?}")),
+        };
+        if let Some(mut file) = file {
+            let size = file
+                .metadata()
+                .with_context(|| format!("reading {path:?}"))?
+                .len();
+            let mut skip_until = None;
+            if size < offset {
+                // The log was trimmed, continue after the last printed event.
+                offset = 0;
+                skip_until = last.take();
+            }
+            file.seek(SeekFrom::Start(offset))
+                .with_context(|| format!("reading {path:?}"))?;
+            let mut reader = BufReader::new(file);
+            let mut line = String::new();
+            let mut lines = Vec::new();
+            while reader
+                .read_line(&mut line)
+                .with_context(|| format!("reading {path:?}"))?
+                > 0
+            {
+                // A line without its newline is still being written.
+                if !line.ends_with('\n') {
+                    break;
+                }
+                offset += line.len() as u64;
+                lines.push(std::mem::take(&mut line));
+            }
+            let start = skip_until
+                .and_then(|last| lines.iter().position(|line| *line == last))
+                .map_or(0, |position| position + 1);
+            for line in &lines[start..] {
+                stdout
+                    .write_all(line.as_bytes())
+                    .context("writing to stdout")?;
+            }
+            stdout.flush().context("writing to stdout")?;
+            if let Some(line) = lines.pop() {
+                last = Some(line);
+            }
+        }
+        if !follow {
+            return Ok(());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
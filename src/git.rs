@@ -0,0 +1,112 @@
+//! Keeping the git checkout of a workspace on its pinned branch
+//!
+//! Workspaces can pin a branch with `git.branch`. Opening them warns when the repository is on a
+//! different branch, or switches to it with `open --checkout` as long as there are no uncommitted
+//! changes which could get in the way.
+
+use std::process::{Command, Output};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::workspace::Workspace;
+use crate::{process, runtime, shell, ssh};
+
+/// Runs `git` with `args` in the directory of `workspace`, over ssh for remote workspaces
+fn git(workspace: &Workspace, args: &[&str]) -> Result<Output> {
+    let command = match &workspace.ssh {
+        Some(ssh) => {
+            let mut command = ssh::background(ssh, workspace.timeout)?;
+            let git = shell::join(std::iter::once(&"git").chain(args));
+            command.arg(format!("cd {} && {git}", shell::quote_dir(&workspace.dir)));
+            command
+        }
+        None => {
+            let mut command = Command::new("git");
+            command.arg("-C").arg(workspace.local_dir()?).args(args);
+            command
+        }
+    };
+    let timeout = runtime::timeout(workspace.timeout)?;
+    let output = process::output_with_timeout(command, timeout)
+        .with_context(|| format!("run git {}", args.join(" ")))?
+        .with_context(|| {
+            format!(
+                "git {} timed out after {}s",
+                args.join(" "),
+                timeout.as_secs(),
+            )
+        })?;
+    if let (Some(ssh), Some(255)) = (&workspace.ssh, output.status.code()) {
+        return Err(ssh::failure(&ssh.host, &output));
+    }
+    Ok(output)
+}
+
+/// Returns the stdout of a successful `git` invocation, or its stderr as the error
+fn stdout(output: Output) -> Result<String> {
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("{}", stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Returns the owner of the `origin` remote of `workspace`, like `acme` for
+/// `git@github.com:acme/api.git`
+///
+/// Nested owners like GitLab subgroups are kept whole, `acme/backend`. `None` if the directory
+/// has no `origin` remote or its URL has no owner.
+pub fn origin_owner(workspace: &Workspace) -> Result<Option<String>> {
+    let output = git(workspace, &["remote", "get-url", "origin"])?;
+    let Ok(url) = stdout(output) else {
+        return Ok(None);
+    };
+    // Both `scheme://host/path` and scp-like `user@host:path` URLs.
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map(|(_, path)| path),
+        None => url.split_once(':').map(|(_, path)| path),
+    };
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    Ok(path
+        .rsplit_once('/')
+        .map(|(owner, _)| owner.trim_matches('/').to_owned())
+        .filter(|owner| !owner.is_empty()))
+}
+
+/// Checks `workspace` is on its pinned branch, checking the branch out if `checkout`
+///
+/// Does nothing for workspaces without a pinned branch.
+pub fn check_branch(workspace: &Workspace, checkout: bool) -> Result<()> {
+    let Some(pinned) = workspace.git.as_ref().and_then(|git| git.branch.as_ref()) else {
+        return Ok(());
+    };
+    let current = stdout(git(workspace, &["rev-parse", "--abbrev-ref", "HEAD"])?)
+        .context("reading current git branch")?;
+    if current == *pinned {
+        return Ok(());
+    }
+    if !checkout {
+        eprintln!(
+            "WARN workspace {:?} is on branch {current:?} instead of {pinned:?}, open it with --checkout to switch",
+            workspace.name,
+        );
+        return Ok(());
+    }
+
+    let status = stdout(git(workspace, &["status", "--porcelain"])?)
+        .context("checking for uncommitted changes")?;
+    if !status.is_empty() {
+        bail!(
+            "refusing to check out branch {pinned:?}, workspace {:?} has uncommitted changes",
+            workspace.name
+        );
+    }
+    stdout(git(workspace, &["checkout", pinned])?)
+        .with_context(|| format!("checking out branch {pinned:?}"))?;
+    eprintln!("INFO checked out branch {pinned:?}");
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! History of opened workspaces
+//!
+//! Stored in the cache as a log with one `<unix time>\t<name>` entry per line, oldest first.
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+use crate::cache::{self, Key, Transaction};
+
+#[derive(Debug)]
+pub struct Entry {
+    /// Unix time in seconds when the workspace was opened
+    pub time: u64,
+
+    /// Name of the opened workspace
+    pub name: String,
+}
+
+/// Returns current unix time in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Record in `transaction` that workspace `name` was opened just now
+pub fn record(transaction: &mut Transaction, name: &str) {
+    transaction.append(Key::History, &format!("{}\t{name}", now()));
+}
+
+/// Read all history entries, oldest first
+pub fn read() -> Result<Vec<Entry>> {
+    let entries = cache::read_lines(Key::History)?
+        .into_iter()
+        .filter_map(|line| {
+            let entry = line.split_once('\t').and_then(|(time, name)| {
+                Some(Entry {
+                    time: time.parse().ok()?,
+                    name: name.to_owned(),
+                })
+            });
+            if entry.is_none() {
+                eprintln!("WARN ignoring malformed history entry {line:?}");
+            }
+            entry
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Returns when workspace `name` was last opened
+pub fn last_opened(name: &str) -> Result<Option<u64>> {
+    Ok(read()?
+        .into_iter()
+        .filter(|entry| entry.name == name)
+        .map(|entry| entry.time)
+        .max())
+}
+
+/// Returns when each workspace in the history was last opened
+pub fn last_opened_all() -> Result<HashMap<String, u64>> {
+    let mut last_opened = HashMap::new();
+    for entry in read()? {
+        let time = last_opened.entry(entry.name).or_default();
+        *time = entry.time.max(*time);
+    }
+    Ok(last_opened)
+}
+
+/// Returns a score for each workspace in the history based on how often and how recently it was
+/// opened
+///
+/// Each time a workspace was opened adds to its score, more recent opens count for more.
+pub fn frecency() -> Result<HashMap<String, f64>> {
+    const HOUR: u64 = 60 * 60;
+    const DAY: u64 = 24 * HOUR;
+    let now = now();
+    let mut scores = HashMap::new();
+    for entry in read()? {
+        let weight = match now.saturating_sub(entry.time) {
+            age if age < 4 * HOUR => 8.0,
+            age if age < DAY => 4.0,
+            age if age < 7 * DAY => 2.0,
+            age if age < 30 * DAY => 1.0,
+            _ => 0.25,
+        };
+        *scores.entry(entry.name).or_default() += weight;
+    }
+    Ok(scores)
+}
+
+/// Rewrite history entries of workspace `old` to `new` in `transaction`
+pub fn rename(transaction: &mut Transaction, old: &str, new: &str) -> Result<()> {
+    let entries = read()?;
+    if entries.iter().all(|entry| entry.name != old) {
+        return Ok(());
+    }
+    let lines = entries
+        .into_iter()
+        .map(|entry| {
+            let name = if entry.name == old { new } else { &entry.name };
+            format!("{}\t{name}", entry.time)
+        })
+        .collect::<Vec<_>>();
+    transaction.write_lines(Key::History, &lines);
+    Ok(())
+}
+
+/// Keeps only the history entries for which `keep` returns `true` in `transaction`
+///
+/// Returns the removed entries.
+pub fn retain(
+    transaction: &mut Transaction,
+    mut keep: impl FnMut(&Entry) -> Result<bool>,
+) -> Result<Vec<Entry>> {
+    let mut kept = Vec::new();
+    let mut removed = Vec::new();
+    for entry in read()? {
+        if keep(&entry)? {
+            kept.push(format!("{}\t{}", entry.time, entry.name));
+        } else {
+            removed.push(entry);
+        }
+    }
+    if !removed.is_empty() {
+        transaction.write_lines(Key::History, &kept);
+    }
+    Ok(removed)
+}
+
+/// Formats the time elapsed since unix time `time` in a human readable way
+pub fn ago(time: u64) -> String {
+    let elapsed = now().saturating_sub(time);
+    let (amount, unit) = match elapsed {
+        0..=59 => return "just now".to_owned(),
+        60..=3599 => (elapsed / 60, "minute"),
+        3600..=86399 => (elapsed / 3600, "hour"),
+        _ => (elapsed / 86400, "day"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{amount} {unit}{plural} ago")
+}
@@ -0,0 +1,205 @@
+//! Hooks and the environment of programs integrating with `workspacectl`
+//!
+//! Hooks are shell commands run when something happens to a workspace, configured in the
+//! `[hooks]` table of the global config and of the workspace definition. Global hooks apply to
+//! every workspace and run on this machine, in the workspace directory of local workspaces and the
+//! home directory for remote ones. Hooks of the workspace run in its directory, over ssh for
+//! remote workspaces. For each event the global hooks run before the workspace's, except when
+//! closing where the workspace's hooks run first, so global hooks see the workspace set up for
+//! all of its lifetime. Commands of one table run in the order they're listed and the first one
+//! to fail stops the rest.
+//!
+//! Terminal and editor hooks run before the window opens, so they can prepare what it uses, like
+//! a dev server or an env file. Editors which only open a file in an already running instance
+//! don't run them.
+//!
+//! Hooks wait for their commands to finish, long running commands should put themselves in the
+//! background. Their output goes to stderr so it can't mix with the output of `workspacectl`.
+//!
+//! Hooks, tasks and plugin subcommands get the same set of environment variables describing the
+//! workspace they run for, so they don't have to parse the output of `cat`:
+//!
+//! - `WORKSPACECTL_NAME` name of the workspace
+//! - `WORKSPACECTL_DIR` workspace directory, absolute for local workspaces and as defined for
+//!   remote ones
+//! - `WORKSPACECTL_HOST` ssh host of remote workspaces, not set for local ones
+//! - `WORKSPACECTL_EVENT` what the program runs for, the [`Event`] of hooks and `plugin` for
+//!   plugin subcommands
+//! - `WORKSPACECTL_CONFIG_DIR` directory of the global config and workspace definitions
+//! - `WORKSPACECTL_CACHE_DIR` directory of cached state like the history
+//! - `WORKSPACECTL_STATE_DIR` directory of state which outlives the cache
+//!
+//! The workspace variables are left out when there's no workspace, like for a plugin run while no
+//! workspace is open. Shells and editors opened in a workspace get only the workspace variables,
+//! see [`workspace_env`].
+
+use std::io;
+use std::process::Stdio;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::workspace::{Hooks, Workspace};
+use crate::{cache, config, events, launch, shell, ssh};
+
+/// What happened to a workspace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The workspace was opened, by `open`, `start` or `activate`
+    Open,
+
+    /// The workspace was closed by `close`
+    Close,
+
+    /// A terminal window of the workspace is about to open, by `terminal` or `start`
+    Terminal,
+
+    /// An editor of the workspace is about to open, by `editor` or `start`
+    Editor,
+}
+
+impl Event {
+    /// Returns the name of the event as passed to hooks in `WORKSPACECTL_EVENT`
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::Open => "open",
+            Event::Close => "close",
+            Event::Terminal => "terminal",
+            Event::Editor => "editor",
+        }
+    }
+
+    /// Returns the commands configured for the event in `hooks`
+    fn commands(self, hooks: &Hooks) -> &[String] {
+        let commands = match self {
+            Event::Open => &hooks.on_open,
+            Event::Close => &hooks.on_close,
+            Event::Terminal => &hooks.on_terminal,
+            Event::Editor => &hooks.on_editor,
+        };
+        commands.as_deref().unwrap_or_default()
+    }
+}
+
+/// Runs the global hooks and the hooks of `workspace` for `event`
+pub fn run(workspace: &Workspace, event: Event) -> Result<()> {
+    let global = config::read()?.and_then(|config| config.hooks);
+    let global = global
+        .as_ref()
+        .map_or(&[][..], |hooks| event.commands(hooks));
+    let own = workspace
+        .hooks
+        .as_ref()
+        .map_or(&[][..], |hooks| event.commands(hooks));
+    if global.is_empty() && own.is_empty() {
+        return Ok(());
+    }
+
+    let env = env(Some(workspace), event.name())?;
+    let run_global = || -> Result<()> {
+        for command in global {
+            run_local(workspace, command, &env)
+                .with_context(|| format!("running global {} hook", event.name()))?;
+        }
+        Ok(())
+    };
+    let run_own = || -> Result<()> {
+        for command in own {
+            match &workspace.ssh {
+                Some(_) => run_remote(workspace, command, &env),
+                None => run_local(workspace, command, &env),
+            }
+            .with_context(|| format!("running {} hook of the workspace", event.name()))?;
+        }
+        Ok(())
+    };
+    if event == Event::Close {
+        run_own()?;
+        run_global()
+    } else {
+        run_global()?;
+        run_own()
+    }
+}
+
+/// Returns the global hooks and the hooks of `workspace` for `event`, in the order they run
+pub fn resolved(workspace: &Workspace, event: Event) -> Result<Vec<String>> {
+    let global = config::read()?.and_then(|config| config.hooks);
+    let global = global
+        .as_ref()
+        .map_or(&[][..], |hooks| event.commands(hooks));
+    let own = workspace
+        .hooks
+        .as_ref()
+        .map_or(&[][..], |hooks| event.commands(hooks));
+    Ok(match event {
+        Event::Close => [own, global].concat(),
+        _ => [global, own].concat(),
+    })
+}
+
+/// Runs hook `command` on this machine
+fn run_local(workspace: &Workspace, command: &str, env: &[(String, String)]) -> Result<()> {
+    let dir = match &workspace.ssh {
+        Some(_) => dirs::home_dir().context("could not determine user home directory")?,
+        None => workspace.local_dir()?,
+    };
+    let mut shell = launch::system_shell(command);
+    shell
+        .current_dir(dir)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stdout(io::stderr());
+    let status = events::status(&mut shell, Some(&workspace.name))
+        .with_context(|| format!("spawn hook {command:?}"))?;
+    ensure!(status.success(), "hook {command:?} failed with {status}");
+    Ok(())
+}
+
+/// Runs hook `command` in the directory of remote `workspace`
+fn run_remote(workspace: &Workspace, command: &str, env: &[(String, String)]) -> Result<()> {
+    let ssh = workspace.ssh.as_ref().expect("workspace is remote");
+    let mut script = format!("cd {} && ", shell::quote_dir(&workspace.dir));
+    for (key, value) in env {
+        script.push_str(&format!("export {key}={}; ", shell::quote(value)));
+    }
+    script.push_str(command);
+    let mut command = ssh::background(ssh, workspace.timeout)?;
+    command.arg(script).stdout(io::stderr());
+    let status = events::status(&mut command, Some(&workspace.name))
+        .with_context(|| format!("spawn ssh for hook {command:?}"))?;
+    ensure!(status.success(), "hook {command:?} failed with {status}");
+    Ok(())
+}
+
+/// Returns the environment variables describing `workspace`
+///
+/// Set for shells and editors opened in the workspace, so prompts and status lines can show it
+/// without calling `workspacectl`.
+pub fn workspace_env(workspace: &Workspace) -> Result<Vec<(String, String)>> {
+    let mut env = Vec::new();
+    let mut set = |key: &str, value: String| env.push((format!("WORKSPACECTL_{key}"), value));
+    set("NAME", workspace.name.clone());
+    match &workspace.ssh {
+        Some(ssh) => {
+            set("DIR", workspace.dir.clone());
+            set("HOST", ssh.host.clone());
+        }
+        None => set("DIR", workspace.local_dir()?.to_string_lossy().into_owned()),
+    }
+    Ok(env)
+}
+
+/// Returns the environment variables of a program run for `event` in `workspace`
+pub fn env(workspace: Option<&Workspace>, event: &str) -> Result<Vec<(String, String)>> {
+    let mut env = match workspace {
+        Some(workspace) => workspace_env(workspace)?,
+        None => Vec::new(),
+    };
+    let mut set = |key: &str, value: String| env.push((format!("WORKSPACECTL_{key}"), value));
+    set("EVENT", event.to_owned());
+    let path = |path: std::path::PathBuf| path.to_string_lossy().into_owned();
+    set("CONFIG_DIR", path(config::dir_path()?));
+    set("CACHE_DIR", path(cache::dir_path()?));
+    set("STATE_DIR", path(cache::state_dir_path()?));
+    Ok(env)
+}
@@ -0,0 +1,117 @@
+//! Reverse index from directories to the workspaces in them
+//!
+//! Finding the workspace of a directory means reading every definition, so the index is kept in
+//! the cache as [`Key::Dirs`] together with the [`workspace::generation`] it was built at. Any
+//! change to the definitions rebuilds it on next use.
+//!
+//! Directories are identified by [`key`], the canonical path for local workspaces and
+//! `<host>:<dir>` for remote ones.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::cache::{self, Key};
+use crate::workspace::{self, Workspace};
+
+/// Names of the workspaces in each directory, by [`key`]
+pub type Dirs = BTreeMap<String, Vec<String>>;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Index {
+    /// Generation of the definitions the index was built from
+    generation: String,
+
+    /// Indexed directories
+    dirs: Dirs,
+}
+
+/// Returns the key identifying the directory of `workspace`
+///
+/// `None` for a local workspace if there's no home directory its `dir` could be relative to.
+pub fn key(workspace: &Workspace) -> Option<String> {
+    match &workspace.ssh {
+        Some(ssh) => Some(remote_key(&ssh.host, &workspace.dir)),
+        None => workspace.local_dir().ok().map(|dir| local_key(&dir)),
+    }
+}
+
+/// Returns the key identifying local directory `dir`
+///
+/// Directories which don't exist can't be canonicalized, they only lose `.` components.
+pub fn local_key(dir: &Path) -> String {
+    let dir = workspace::canonicalize(dir).unwrap_or_else(|_| {
+        dir.components()
+            .filter(|component| *component != Component::CurDir)
+            .collect()
+    });
+    dir.to_string_lossy().into_owned()
+}
+
+/// Returns the key identifying directory `dir` on `host`
+///
+/// Remote directories are relative to the remote home directory, with or without a leading `~/`.
+pub fn remote_key(host: &str, dir: &str) -> String {
+    let dir = dir.strip_prefix("~/").unwrap_or(dir).trim_end_matches('/');
+    format!("{host}:{dir}")
+}
+
+/// Indexes the directories of `workspaces`
+pub fn build(workspaces: &[Workspace]) -> Dirs {
+    let mut dirs = Dirs::new();
+    for workspace in workspaces {
+        if let Some(key) = key(workspace) {
+            dirs.entry(key).or_default().push(workspace.name.clone());
+        }
+    }
+    dirs
+}
+
+/// Returns the names of the workspaces in the directory with key `key`
+pub fn lookup(key: &str) -> Result<Vec<String>> {
+    Ok(load()?.remove(key).unwrap_or_default())
+}
+
+/// Returns the names of the workspaces in the closest directory containing local path `path`
+/// which has any
+pub fn containing(path: &Path) -> Result<Option<Vec<String>>> {
+    containing_canonical(&workspace::canonicalize(path)?)
+}
+
+/// Like [`containing`] for a `path` which is already canonical, like the working directory
+///
+/// Skips canonicalizing, which stats every component of the path.
+pub fn containing_canonical(path: &Path) -> Result<Option<Vec<String>>> {
+    let mut dirs = load()?;
+    Ok(path
+        .ancestors()
+        .find_map(|dir| dirs.remove(&*dir.to_string_lossy())))
+}
+
+/// Returns the index, rebuilding it if any definition changed since it was built
+fn load() -> Result<Dirs> {
+    let generation = workspace::generation()?;
+    let cached = cache::read(Key::Dirs)
+        .ok()
+        .and_then(|cached| serde_json::from_str::<Index>(&cached).ok());
+    if let Some(index) = cached.filter(|index| index.generation == generation) {
+        return Ok(index.dirs);
+    }
+
+    let mut workspaces = Vec::new();
+    for name in workspace::list() {
+        match workspace::read_own(&name) {
+            Ok(workspace) => workspaces.push(workspace),
+            Err(err) => eprintln!("WARN not indexing workspace {name:?}: {err:#}"),
+        }
+    }
+    let index = Index {
+        generation,
+        dirs: build(&workspaces),
+    };
+    let json = serde_json::to_string(&index).context("serializing directory index")?;
+    cache::write(Key::Dirs, json).context("caching directory index")?;
+    Ok(index.dirs)
+}
@@ -0,0 +1,31 @@
+//! JetBrains Gateway
+//!
+//! Gateway opens a project of a remote host in a JetBrains IDE backend running on that host, with
+//! the local JetBrains Client as its frontend. It connects when it opens a `jetbrains-gateway://`
+//! URL describing the ssh connection, the IDE product and the project, and deploys the IDE to the
+//! host first if it isn't there yet.
+
+use crate::{uri, workspace};
+
+/// Returns the Gateway URL opening `project` on `ssh`'s host in the IDE with product code
+/// `product`
+pub fn url(ssh: &workspace::Ssh, product: &str, project: &str) -> String {
+    let port = ssh.port.unwrap_or(22).to_string();
+    let mut params = vec![
+        ("type", "ssh"),
+        ("deploy", "true"),
+        ("host", &ssh.host),
+        ("port", &port),
+    ];
+    // Gateway asks for the user if it's missing.
+    if let Some(user) = &ssh.user {
+        params.push(("user", user));
+    }
+    params.extend([("productCode", product), ("projectPath", project)]);
+    let fragment = params
+        .iter()
+        .map(|(key, value)| format!("{key}={}", uri::encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("jetbrains-gateway://connect#{fragment}")
+}
@@ -0,0 +1,108 @@
+//! kitty session files
+//!
+//! A [session file] lists the OS windows, tabs and windows kitty opens when it's started with
+//! `--session`. Starting all windows of the workspaces this way leaves arranging them to kitty,
+//! instead of spawning and placing them one by one.
+//!
+//! [session file]: https://sw.kovidgoyal.net/kitty/overview/#startup-sessions
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::launch::Window;
+use crate::{cache, shell};
+
+/// Tab of a kitty session
+#[derive(Debug)]
+pub struct Tab {
+    /// Tab title, kitty uses the title of the active window if not set
+    pub title: Option<String>,
+
+    /// kitty layout the windows are arranged in, kitty's default if not set
+    pub layout: Option<&'static str>,
+
+    /// Windows of the tab, in order
+    pub windows: Vec<Window>,
+}
+
+/// Returns the kitty layout closest to tmux layout `layout`, if there's one
+pub fn layout(layout: &str) -> Option<&'static str> {
+    match layout {
+        "even-horizontal" => Some("horizontal"),
+        "even-vertical" => Some("vertical"),
+        "main-vertical" => Some("tall"),
+        "main-horizontal" => Some("fat"),
+        "tiled" => Some("grid"),
+        _ => None,
+    }
+}
+
+/// Returns a session file opening an OS window with the tabs of each of `os_windows`
+pub fn session(os_windows: &[Vec<Tab>]) -> String {
+    let mut session = String::new();
+    for (index, tabs) in os_windows.iter().enumerate() {
+        if index > 0 {
+            session.push_str("new_os_window\n");
+        }
+        for tab in tabs {
+            match &tab.title {
+                Some(title) => session.push_str(&format!("new_tab {title}\n")),
+                None => session.push_str("new_tab\n"),
+            }
+            if let Some(layout) = tab.layout {
+                session.push_str(&format!("layout {layout}\n"));
+            }
+            for window in &tab.windows {
+                session.push_str(&launch(window));
+                session.push('\n');
+            }
+        }
+    }
+    session
+}
+
+/// Returns the `launch` line opening `window`
+///
+/// Secrets of the window are left out, they're passed to the kitty started with the session
+/// instead, see [`secrets`].
+fn launch(window: &Window) -> String {
+    let mut args = vec!["launch".to_owned()];
+    if let Some(title) = &window.title {
+        args.push(format!("--title={title}"));
+    }
+    if let Some(dir) = &window.dir {
+        args.push(format!("--cwd={}", dir.to_string_lossy()));
+    }
+    for (key, value) in &window.env {
+        args.push(format!("--env={key}={value}"));
+    }
+    args.push(window.program.clone());
+    args.extend(window.args.iter().cloned());
+    shell::join(args)
+}
+
+/// Returns the secrets of the windows in `os_windows`, which kitty has to be started with
+///
+/// Session files are kept, so the windows inherit secrets from kitty instead of having them set in
+/// the file.
+pub fn secrets(os_windows: &[Vec<Tab>]) -> Vec<(String, String)> {
+    os_windows
+        .iter()
+        .flatten()
+        .flat_map(|tab| &tab.windows)
+        .flat_map(|window| window.secrets.iter().cloned())
+        .collect()
+}
+
+/// Writes session file `session` for the workspaces started as `name` and returns its path
+///
+/// Session files are kept in the cache directory, so they can be looked at after kitty started.
+pub fn write(name: &str, session: &str) -> Result<PathBuf> {
+    let dir = cache::dir_path()?.join("sessions");
+    fs::create_dir_all(&dir).with_context(|| format!("creating directory {dir:?}"))?;
+    let path = dir.join(format!("{}.kitty", name.replace(['/', '\\', ':'], "_")));
+    fs::write(&path, session).with_context(|| format!("writing kitty session {path:?}"))?;
+    Ok(path)
+}
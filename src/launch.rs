@@ -0,0 +1,429 @@
+//! Opening terminal windows
+//!
+//! Commands describe what should run in a new window with a [`Window`] and a [`Backend`] decides
+//! how to open it. The backend is configured by `terminal.backend` in the global config. Without
+//! one Windows Terminal is used on Windows, elsewhere the first installed terminal out of
+//! `$TERMINAL` and [`KNOWN_TERMINALS`], falling back to Terminal.app on macOS.
+//!
+//! Terminals without a backend can be used by setting `terminal.command` and `terminal.args`.
+//! The arguments can contain these placeholders:
+//!
+//! - `{{dir}}` working directory, arguments containing it are left out if the window doesn't have
+//!   one
+//! - `{{title}}` window title, arguments containing it are left out if the window doesn't have one
+//! - `{{cmd}}` the program and its arguments, as separate arguments when it's a whole argument or
+//!   as a shell command line when it's part of one
+//! - `{{script}}` shell command line which also changes the directory and sets the environment,
+//!   for terminals which can't do that themselves
+//!
+//! For example `command = "gnome-terminal"` with
+//! `args = ["--working-directory={{dir}}", "--", "{{cmd}}"]`.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::{env, fs};
+
+use anyhow::{bail, Context, Result};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{config, process, runner, secret, shell, template};
+
+/// A program to run in a new terminal window
+#[derive(Debug, Default)]
+pub struct Window {
+    /// Window title, the terminal decides when not set
+    pub title: Option<String>,
+
+    /// Local working directory of the program
+    pub dir: Option<PathBuf>,
+
+    /// Program to run
+    pub program: String,
+
+    /// Arguments of the program
+    pub args: Vec<String>,
+
+    /// Environment variables set for the program
+    pub env: Vec<(String, String)>,
+
+    /// Environment variables set for the program which hold secrets
+    ///
+    /// Set like `env`, but never on a command line, see [`secret`](crate::secret).
+    pub secrets: Vec<(String, String)>,
+}
+
+impl Window {
+    /// Returns a window running `program` with no arguments
+    pub fn new(program: impl Into<String>) -> Window {
+        Window {
+            program: program.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a POSIX shell command line changing into the directory, setting the environment and
+    /// running the program
+    ///
+    /// Used by backends which can only run a command line in the user's shell. Secrets are sourced
+    /// from a private file, see [`secret::private_file`].
+    fn script(&self) -> Result<String> {
+        let mut script = String::new();
+        if let Some(dir) = &self.dir {
+            script.push_str(&format!("cd {} && ", shell::quote(&dir.to_string_lossy())));
+        }
+        for (key, value) in &self.env {
+            script.push_str(&format!("export {key}={} && ", shell::quote(value)));
+        }
+        if !self.secrets.is_empty() {
+            script.push_str(&secret::private_file(&self.secrets)?);
+            script.push_str(" && ");
+        }
+        script.push_str("exec ");
+        script.push_str(&self.command_line());
+        Ok(script)
+    }
+
+    /// Returns the program and its arguments as a POSIX shell command line
+    fn command_line(&self) -> String {
+        shell::join(std::iter::once(&self.program).chain(&self.args))
+    }
+}
+
+/// Terminal emulators windows can be opened in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// [kitty](https://sw.kovidgoyal.net/kitty/)
+    Kitty,
+
+    /// Windows Terminal, `wt.exe`
+    WindowsTerminal,
+
+    /// iTerm2 on macOS, driven through AppleScript
+    Iterm2,
+
+    /// Terminal.app on macOS, driven through AppleScript
+    TerminalApp,
+}
+
+/// Terminals looked for when none is configured, in order of preference, with their arguments
+///
+/// kitty comes first and has its own backend.
+const KNOWN_TERMINALS: &[(&str, &[&str])] = &[
+    ("wezterm", &["start", "--cwd={{dir}}", "--", "{{cmd}}"]),
+    (
+        "alacritty",
+        &[
+            "--working-directory={{dir}}",
+            "--title={{title}}",
+            "-e",
+            "{{cmd}}",
+        ],
+    ),
+    (
+        "foot",
+        &[
+            "--working-directory={{dir}}",
+            "--title={{title}}",
+            "{{cmd}}",
+        ],
+    ),
+    (
+        "gnome-terminal",
+        &[
+            "--working-directory={{dir}}",
+            "--title={{title}}",
+            "--",
+            "{{cmd}}",
+        ],
+    ),
+    ("xterm", &["-e", "{{cmd}}"]),
+];
+
+/// Shell used by local workspaces which don't configure one when the user's login shell is unknown
+#[cfg(not(windows))]
+pub const DEFAULT_SHELL: &str = "/usr/bin/bash";
+
+/// Shell used by local workspaces which don't configure one
+#[cfg(windows)]
+pub const DEFAULT_SHELL: &str = "powershell.exe";
+
+/// Returns the login shell of the local user, `$SHELL` or their entry in `/etc/passwd`
+pub fn login_shell() -> Option<String> {
+    if cfg!(windows) {
+        return None;
+    }
+    if let Some(shell) = env::var("SHELL").ok().filter(|shell| !shell.is_empty()) {
+        return Some(shell);
+    }
+    let user = env::var("USER").or_else(|_| env::var("LOGNAME")).ok()?;
+    let passwd = fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields = line.split(':').collect::<Vec<_>>();
+        match fields[..] {
+            [name, .., shell] if name == user && fields.len() == 7 && !shell.is_empty() => {
+                Some(shell.to_owned())
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Returns a command running command line `line` in the system shell, `cmd` on Windows and `sh`
+/// elsewhere
+pub fn system_shell(line: &str) -> Command {
+    let (program, flag) = match cfg!(windows) {
+        true => ("cmd", "/C"),
+        false => ("sh", "-c"),
+    };
+    let mut command = Command::new(program);
+    command.arg(flag).arg(line);
+    command
+}
+
+/// Quotes `arg` so the [`system_shell`] treats it as a single word
+pub fn system_quote(arg: &str) -> String {
+    match cfg!(windows) {
+        // Windows paths can't contain `"`.
+        true => format!("\"{arg}\""),
+        false => shell::quote(arg),
+    }
+}
+
+/// Opens `window` in a new terminal window
+///
+/// Returns the process id and program of the spawned process, `None` if the runner didn't start it.
+pub fn spawn(window: Window) -> Result<Option<(u32, String)>> {
+    let terminal = config::read()?.and_then(|config| config.terminal);
+    let mut command = match terminal {
+        Some(config::Terminal {
+            command: Some(program),
+            args,
+            ..
+        }) => custom(&program, &args.unwrap_or_default(), window)?,
+        Some(config::Terminal {
+            backend: Some(backend),
+            ..
+        }) => backend_command(backend, window)?,
+        _ => detect(window)?,
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    let child =
+        runner::spawn(&mut command).with_context(|| format!("spawn terminal {program:?}"))?;
+    Ok(child.map(|child| (child.id(), program)))
+}
+
+/// Runs `window`'s program in the current terminal instead of opening a new window
+///
+/// On Unix the program replaces this process. Elsewhere it runs to completion and the exit code is
+/// passed on.
+pub fn run_here(window: Window) -> Result<()> {
+    let mut command = Command::new(&window.program);
+    command
+        .args(&window.args)
+        .envs(window.env)
+        .envs(window.secrets);
+    if let Some(dir) = &window.dir {
+        command.current_dir(dir);
+    }
+    runner::exec(&mut command)
+}
+
+/// Returns the command opening `window` with `backend`
+fn backend_command(backend: Backend, window: Window) -> Result<Command> {
+    match backend {
+        Backend::Kitty => Ok(kitty(window)),
+        Backend::WindowsTerminal => Ok(windows_terminal(window)),
+        Backend::Iterm2 => iterm2(window),
+        Backend::TerminalApp => terminal_app(window),
+    }
+}
+
+/// Terminal used when none is configured
+pub enum Installed {
+    /// A terminal with a backend
+    Backend(Backend),
+
+    /// Any other terminal, with its arguments like `terminal.command` and `terminal.args`
+    Custom(String, Vec<String>),
+}
+
+/// Returns the first installed terminal, `None` if none was found
+pub fn installed() -> Option<Installed> {
+    if cfg!(windows) {
+        return Some(Installed::Backend(Backend::WindowsTerminal));
+    }
+    // Most terminals follow the convention of `-e` running a command, this is what
+    // `i3-sensible-terminal` and others assume too.
+    if let Ok(terminal) = env::var("TERMINAL") {
+        if !terminal.is_empty() && process::find_program(&terminal).is_some() {
+            let args = vec!["-e".to_owned(), "{{cmd}}".to_owned()];
+            return Some(Installed::Custom(terminal, args));
+        }
+    }
+    if process::find_program("kitty").is_some() {
+        return Some(Installed::Backend(Backend::Kitty));
+    }
+    for (program, args) in KNOWN_TERMINALS {
+        if process::find_program(program).is_some() {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            return Some(Installed::Custom(program.to_string(), args));
+        }
+    }
+    if cfg!(target_os = "macos") {
+        return Some(Installed::Backend(Backend::TerminalApp));
+    }
+    None
+}
+
+/// Returns the command opening `window` in the first installed terminal
+fn detect(window: Window) -> Result<Command> {
+    match installed() {
+        Some(Installed::Backend(backend)) => return backend_command(backend, window),
+        Some(Installed::Custom(program, args)) => return custom(&program, &args, window),
+        None => {}
+    }
+    let tried = std::iter::once("kitty")
+        .chain(KNOWN_TERMINALS.iter().map(|(program, _)| *program))
+        .collect::<Vec<_>>();
+    bail!(
+        "no terminal found, tried $TERMINAL and {}, set `terminal.backend` or `terminal.command` in the config",
+        tried.join(", "),
+    )
+}
+
+/// Returns the command opening `window` with a terminal configured by `terminal.command`
+fn custom(program: &str, args: &[String], window: Window) -> Result<Command> {
+    let mut command = Command::new(program);
+    for arg in args {
+        let mut variables = Vec::new();
+        let rest = template::expand_with(arg, |variable| {
+            variables.push(variable.to_owned());
+            Ok(String::new())
+        })
+        .context("expanding terminal arguments")?;
+        if rest.is_empty() && variables == ["cmd"] {
+            command.arg(&window.program).args(&window.args);
+            continue;
+        }
+        let missing = |name: &str| match name {
+            "title" => window.title.is_none(),
+            "dir" => window.dir.is_none(),
+            _ => false,
+        };
+        if variables.iter().any(|variable| missing(variable)) {
+            continue;
+        }
+        let arg = template::expand_with(arg, |variable| {
+            Ok(match variable {
+                "dir" => window
+                    .dir
+                    .as_ref()
+                    .map(|dir| dir.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                "title" => window.title.clone().unwrap_or_default(),
+                "cmd" => window.command_line(),
+                "script" => window.script()?,
+                _ => bail!("unknown terminal placeholder {variable:?}"),
+            })
+        })
+        .context("expanding terminal arguments")?;
+        command.arg(arg);
+    }
+    if let Some(dir) = &window.dir {
+        command.current_dir(dir);
+    }
+    command.envs(window.env).envs(window.secrets);
+    Ok(command)
+}
+
+/// Returns the command opening `window` in kitty
+fn kitty(window: Window) -> Command {
+    let mut command = Command::new("kitty");
+    if let Some(title) = &window.title {
+        command.args(["--title", title]);
+    }
+    if let Some(dir) = &window.dir {
+        command.current_dir(dir);
+    }
+    command
+        .arg(&window.program)
+        .args(&window.args)
+        .envs(window.env)
+        .envs(window.secrets);
+    command
+}
+
+/// Returns the command opening `window` in a new Windows Terminal tab
+///
+/// When Windows Terminal is already running the tab is opened by the running instance, so
+/// environment variables only reach it if the program itself is passed them. They are set anyway
+/// for the case when `wt.exe` starts a new instance.
+fn windows_terminal(window: Window) -> Command {
+    let mut command = Command::new("wt.exe");
+    command.arg("new-tab");
+    if let Some(title) = &window.title {
+        command.args(["--title", title]);
+    }
+    if let Some(dir) = &window.dir {
+        command.arg("--startingDirectory").arg(dir);
+    }
+    // `;` separates `wt.exe` subcommands, escape it so it reaches the program.
+    let escape = |arg: &str| arg.replace(';', r"\;");
+    command
+        .arg(escape(&window.program))
+        .args(window.args.iter().map(|arg| escape(arg)))
+        .envs(window.env)
+        .envs(window.secrets);
+    command
+}
+
+/// AppleScript opening an iTerm2 window running the command in the first argument, titled by the
+/// optional second argument
+const ITERM2_SCRIPT: &str = r#"on run argv
+    tell application "iTerm2"
+        set newWindow to (create window with default profile command (item 1 of argv))
+        if (count of argv) > 1 then
+            tell current session of newWindow to set name to (item 2 of argv)
+        end if
+    end tell
+end run"#;
+
+/// Returns the command opening `window` in a new iTerm2 window
+fn iterm2(window: Window) -> Result<Command> {
+    // iTerm2 splits the command into words itself but doesn't run it through a shell.
+    let script = format!("/bin/sh -c {}", shell::quote(&window.script()?));
+    Ok(osascript(ITERM2_SCRIPT, script, window.title))
+}
+
+/// AppleScript opening a Terminal.app window running the command in the first argument, titled by
+/// the optional second argument
+const TERMINAL_APP_SCRIPT: &str = r#"on run argv
+    tell application "Terminal"
+        activate
+        set newTab to do script (item 1 of argv)
+        if (count of argv) > 1 then
+            set custom title of newTab to (item 2 of argv)
+        end if
+    end tell
+end run"#;
+
+/// Returns the command opening `window` in a new Terminal.app window
+fn terminal_app(window: Window) -> Result<Command> {
+    // Terminal.app types the command into the user's shell.
+    Ok(osascript(
+        TERMINAL_APP_SCRIPT,
+        window.script()?,
+        window.title,
+    ))
+}
+
+/// Returns the command running AppleScript `source` with the command line and title as arguments
+///
+/// Passing them as arguments avoids having to quote them for AppleScript.
+fn osascript(source: &str, script: String, title: Option<String>) -> Command {
+    let mut command = Command::new("osascript");
+    command.args(["-e", source, &script]);
+    command.args(title);
+    command
+}
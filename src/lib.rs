@@ -1,29 +1,239 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
-use std::io::{self, Write};
-use std::process::Command;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, Context, Result};
-use cache::Key;
-use workspace::Workspace;
+use anyhow::{bail, ensure, Context, Result};
+use cache::{Key, Transaction};
+use launch::Window;
+use serde_json::json;
+use walkdir::WalkDir;
+use workspace::{EditorKind, Multiplexer, Workspace};
 
+pub use config::{ListFormat, Sort};
+pub use logging::{init_logging, LogFormat, LogLevel};
+pub use parse::set_strict;
+pub use parse::Format;
+pub use paths::{set_paths, Paths};
+pub use porcelain::{set_porcelain, Porcelain};
+pub use progress::set_quiet;
+pub use project::set_ignore as set_ignore_project_config;
+pub use prompt::set_input;
+pub use runner::{set_runner, Print, Runner, System};
+
+mod age;
 mod cache;
 mod config;
+mod detect;
+mod events;
+mod git;
+mod history;
+mod hooks;
+mod index;
+mod jetbrains;
+mod kitty;
+mod launch;
+mod lint;
+mod logging;
+mod meta;
+mod migrate;
+mod nix;
+mod nvim;
+mod parse;
+mod paths;
+mod porcelain;
+mod process;
+mod progress;
+mod project;
+mod prompt;
+mod python;
+mod runner;
+mod runtime;
+mod secret;
+mod session;
+mod shell;
+mod ssh;
+mod status;
+mod template;
+mod tmux;
+mod uri;
+mod vscode;
+mod wezterm;
+mod windows;
 mod workspace;
 
-pub fn init(ssh: Option<String>, path: String, name: Option<String>) -> Result<()> {
-    match ssh {
-        Some(host) => init_ssh(host, path, name),
-        None => init_local(path, name),
+/// Creates a workspace for directory `path`, first cloning git repository `clone` into it if set
+///
+/// The workspace is created in `group`, or else the configured `default_group`, see [`grouped`].
+/// Settings and secrets the project config in the directory asks for are reported, see
+/// [`onboard`].
+#[allow(clippy::too_many_arguments)]
+pub fn init(
+    ssh: Option<String>,
+    path: Option<String>,
+    interactive: bool,
+    clone: Option<String>,
+    name: Option<String>,
+    group: Option<String>,
+    format: Format,
+    encrypt: bool,
+) -> Result<()> {
+    let ssh = ssh.as_deref().map(ssh::parse).transpose()?;
+    // Remote workspaces have no useful default, ask for the path when someone can answer.
+    let interactive = interactive || (ssh.is_some() && path.is_none() && prompt::interactive());
+    let path = match (path, &clone) {
+        (Some(path), _) => path,
+        (None, Some(url)) => repository_name(url)?,
+        (None, None) if interactive => prompt::path("path: ", ssh.as_ref())?,
+        (None, None) => ".".to_owned(),
+    };
+    if let Some(url) = &clone {
+        git_clone(ssh.as_ref(), url, &path)?;
+    }
+    let name = match ssh {
+        Some(ssh) => init_ssh(ssh, path, name, group, format, encrypt)?,
+        None => init_local(path, name, group, format, encrypt)?,
+    };
+    onboard(&name)
+}
+
+/// Returns the directory `git clone` clones repository `url` into by default
+fn repository_name(url: &str) -> Result<String> {
+    let url = url.trim_end_matches('/');
+    let name = url.rsplit(['/', ':']).next().unwrap_or(url);
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    ensure!(
+        !name.is_empty(),
+        "cannot infer directory for repository {url:?}, pass a PATH",
+    );
+    Ok(name.to_owned())
+}
+
+/// Clones git repository `url` into `path`, on `ssh`'s host if set
+///
+/// Runs in the foreground, git may ask for credentials.
+fn git_clone(ssh: Option<&workspace::Ssh>, url: &str, path: &str) -> Result<()> {
+    let mut command = match ssh {
+        Some(ssh) => {
+            let mut command = ssh::foreground(ssh, None, true)?;
+            command.arg(format!(
+                "git clone -- {} {}",
+                shell::quote(url),
+                shell::quote(path.strip_prefix("~/").unwrap_or(path)),
+            ));
+            command
+        }
+        None => {
+            let mut command = Command::new("git");
+            command.args(["clone", "--", url, path]);
+            command
+        }
+    };
+    let status = runner::status(&mut command).context("run git clone")?;
+    ensure!(status.success(), "cloning {url:?} failed");
+    Ok(())
+}
+
+/// Reports what the project config of new workspace `name` sets, and asks for the secrets it
+/// needs
+///
+/// Settings aren't copied into the definition, they're merged whenever it's read so changes to the
+/// project config apply. Secrets which can't be asked for are listed instead.
+fn onboard(name: &str) -> Result<()> {
+    let workspace = workspace::read_own(name).context("reading workspace definition")?;
+    if workspace.ssh.is_some() {
+        // Everything else only uses the cached copy.
+        let fetched = runtime::block_on(project::fetch(&workspace))?;
+        if let Err(err) = fetched {
+            eprintln!("WARN ignoring project config of {name:?}: {err:#}");
+            return Ok(());
+        }
+    }
+    let contents = match project::is_used(&workspace) {
+        true => project::contents(&workspace)?,
+        false => None,
+    };
+    if let Some((path, buf)) = contents {
+        if !project::is_allowed(&path, &buf)? {
+            eprintln!("INFO the project has a {}:\n{buf}", project::FILE_NAME);
+            if !prompt::interactive() || !prompt::confirm("allow it?")? {
+                eprintln!("INFO review it and allow it later with `wsctl project allow {name}`");
+                return Ok(());
+            }
+            project::allow(&path, &buf)?;
+        }
+    }
+    let Some(project) = project::read(&workspace)? else {
+        return Ok(());
+    };
+    let tables = [
+        ("editor", project.editor.is_some()),
+        ("shell", project.shell.is_some()),
+        ("tmux", project.tmux.is_some()),
+        ("nix", project.nix.is_some()),
+        ("python", project.python.is_some()),
+    ];
+    let tables = tables
+        .into_iter()
+        .filter_map(|(table, set)| set.then_some(table))
+        .collect::<Vec<_>>();
+    if !tables.is_empty() {
+        eprintln!(
+            "INFO using {} settings from {}",
+            tables.join(", "),
+            project::FILE_NAME,
+        );
+    }
+
+    let set = match project.secrets {
+        Some(_) => secret::list(name).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let mut missing = Vec::new();
+    for key in project.secrets.into_iter().flatten() {
+        if set.contains(&key) {
+            continue;
+        }
+        if let Err(err) = secret::check_key(&key) {
+            eprintln!("WARN ignoring secret of {}: {err:#}", project::FILE_NAME);
+            continue;
+        }
+        if !prompt::interactive() {
+            missing.push(key);
+            continue;
+        }
+        let value = rpassword::prompt_password(format!("value of {key} (empty to skip): "))
+            .context("reading secret from the terminal")?;
+        match value.is_empty() {
+            true => missing.push(key),
+            false => secret::set(name, &key, &value)?,
+        }
+    }
+    for key in missing {
+        eprintln!(
+            "INFO the project needs secret {key}, set it with `wsctl secret set {name} {key}`"
+        );
     }
+    Ok(())
 }
 
-fn init_local(path: String, name: Option<String>) -> Result<()> {
+/// Creates a workspace for local directory `path` in `group`, returns its name
+fn init_local(
+    path: String,
+    name: Option<String>,
+    group: Option<String>,
+    format: Format,
+    encrypt: bool,
+) -> Result<String> {
     let dir = env::current_dir()
         .context("get current working directory")?
         .join(path);
-    let dir = dir
-        .canonicalize()
-        .with_context(|| format!("canonicalize path {dir:?}"))?;
+    let dir = workspace::canonicalize(&dir)?;
     let name = match name {
         Some(name) => name,
         None => dir
@@ -33,6 +243,8 @@ fn init_local(path: String, name: Option<String>) -> Result<()> {
             .with_context(|| format!("directory name is an invalid workspace name {dir:?}"))?
             .to_owned(),
     };
+    warn_registered(&index::local_key(&dir));
+
     // Try to make the path relative to the user's `$HOME` directory
     let dir = match dirs::home_dir().and_then(|home| dir.strip_prefix(home).ok()) {
         Some(relative) => relative.to_owned(),
@@ -46,138 +258,3153 @@ fn init_local(path: String, name: Option<String>) -> Result<()> {
 
     let workspace = Workspace {
         name,
+        version: Some(migrate::WORKSPACE_VERSION),
         dir,
-        ssh: None,
-        editor: None,
-        shell: None,
+        ..Default::default()
     };
-    workspace::create(&workspace).context("create new workspace config")
+    let workspace = grouped(workspace, group)?;
+    workspace::create(&workspace, format, encrypt).context("create new workspace config")?;
+    Ok(workspace.name)
 }
 
-fn init_ssh(host: String, path: String, name: Option<String>) -> Result<()> {
-    // TODO parse host into user@host:port
-
-    // Check the target directory exists
-    let output = Command::new("ssh")
-        .arg(&host)
-        .arg(format!("cd {path}"))
-        .output()
-        .context("verify remote workspace path")?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("{stderr}")).context("verify remote workpace path");
+/// Creates a workspace for directory `path` on `ssh`'s host in `group`, returns its name
+fn init_ssh(
+    ssh: workspace::Ssh,
+    path: String,
+    name: Option<String>,
+    group: Option<String>,
+    format: Format,
+    encrypt: bool,
+) -> Result<String> {
+    // Resolve the target directory, which also checks it exists. `ssh` starts in the remote
+    // `$HOME` so relative paths are relative to it, and `~/` means the same.
+    let target = path.strip_prefix("~/").unwrap_or(&path);
+    let target = match target {
+        "~" | "" => ".".to_owned(),
+        target => shell::quote(target),
+    };
+    let host = ssh.host.clone();
+    let mut command = ssh::background(&ssh, None)?;
+    command.arg(format!("cd {target} && pwd -P && cd && pwd -P"));
+    let timeout = runtime::timeout(None)?;
+    let spinner = progress::spinner(format!("verifying {path:?} on {host:?}"));
+    let output = process::output_with_timeout(command, timeout);
+    spinner.finish_and_clear();
+    let output = output.context("verify remote workspace path")?.with_context(|| {
+        format!(
+            "verifying remote path {path:?} on {host:?} timed out after {}s, raise `timeout` in the config to wait longer",
+            timeout.as_secs(),
+        )
+    })?;
+    if output.status.code() == Some(255) {
+        return Err(ssh::failure(&host, &output)).context("verify remote workspace path");
     }
+    ensure!(
+        output.status.success(),
+        "remote path {path:?} does not exist on {host:?}",
+    );
+    let stdout = String::from_utf8(output.stdout).context("remote path is not valid utf-8")?;
+    let mut lines = stdout.lines();
+    let (Some(dir), Some(home)) = (lines.next(), lines.next()) else {
+        bail!("could not resolve remote path {path:?} on {host:?}");
+    };
 
     let name = match name {
         Some(name) => name,
-        None => path
-            .split('/')
-            .last()
-            .with_context(|| format!("cannot infer name for workspace with remote path {path:?}"))?
+        None => dir
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .with_context(|| {
+                format!("cannot infer name for workspace in remote directory {dir:?}")
+            })?
             .to_owned(),
     };
+    // Try to make the path relative to the remote `$HOME` directory
+    let dir = match Path::new(dir).strip_prefix(home) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => dir.to_owned(),
+    };
+    warn_registered(&index::remote_key(&host, &dir));
 
     let workspace = Workspace {
         name,
-        dir: path,
-        ssh: Some(workspace::Ssh {
-            command: None,
-            user: None,
-            host,
-            port: None,
-            identity_file: None,
-        }),
-        editor: None,
-        shell: None,
+        version: Some(migrate::WORKSPACE_VERSION),
+        dir,
+        ssh: Some(ssh),
+        ..Default::default()
+    };
+    let workspace = grouped(workspace, group)?;
+    workspace::create(&workspace, format, encrypt).context("create new workspace config")?;
+    Ok(workspace.name)
+}
+
+/// Moves new `workspace` into `group`, or else the group `default_group` in the config expands to
+///
+/// `{{org}}` in `default_group` is the owner of the git remote `origin` of the workspace directory.
+/// Without one the workspace isn't grouped.
+fn grouped(mut workspace: Workspace, group: Option<String>) -> Result<Workspace> {
+    let group = match group {
+        Some(group) => group,
+        None => {
+            let Some(template) = config::read()?.and_then(|config| config.default_group) else {
+                return Ok(workspace);
+            };
+            let mut owner = None;
+            let group = template::expand_with(&template, |variable| match variable {
+                "org" => {
+                    owner = git::origin_owner(&workspace)?;
+                    Ok(owner.clone().unwrap_or_default())
+                }
+                _ => bail!("unknown template variable {variable:?}"),
+            })
+            .context("expanding default_group of the config")?;
+            if template.contains("{{") && owner.is_none() {
+                eprintln!(
+                    "INFO not grouping workspace {:?}, its directory has no git remote owner",
+                    workspace.name,
+                );
+                return Ok(workspace);
+            }
+            group
+        }
     };
-    workspace::create(&workspace).context("create new workspace config")
+    let group = group.trim_matches('/');
+    if !group.is_empty() {
+        workspace.name = format!("{group}/{}", workspace.name);
+    }
+    Ok(workspace)
+}
+
+/// Warns if the directory with index key `key` already has a workspace
+fn warn_registered(key: &str) {
+    match index::lookup(key) {
+        Ok(names) if names.is_empty() => {}
+        Ok(names) => eprintln!("WARN {key:?} is already registered as {}", names.join(", ")),
+        Err(err) => eprintln!("WARN could not check for workspaces in {key:?}: {err:#}"),
+    }
 }
 
-pub fn list() -> Result<()> {
+/// Lists workspaces matching `pattern`, or all of them, with their cached status if `long`
+///
+/// Formats other than [`ListFormat::Plain`] describe each workspace, see [`list_details`].
+pub fn list(
+    pattern: Option<String>,
+    sort: Option<Sort>,
+    all: bool,
+    long: bool,
+    format: ListFormat,
+) -> Result<()> {
+    let mut workspaces = match &pattern {
+        Some(pattern) => workspace::matching(pattern)?,
+        None => workspace::list(),
+    };
+    if !all {
+        workspaces.retain(|name| !workspace::is_archived(name));
+    }
+    let sort = match sort {
+        Some(sort) => sort,
+        None => config::read()?
+            .and_then(|config| config.list)
+            .and_then(|list| list.sort)
+            .unwrap_or(Sort::Name),
+    };
+    sort_workspaces(&mut workspaces, sort)?;
+
     let mut stdout = io::stdout().lock();
-    stdout.write_all(b"~\n").context("writing to stdout")?;
-    for workspace in workspace::list() {
+    if let Some(Porcelain::V1) = porcelain::version() {
+        let last_opened = history::last_opened_all().context("reading workspace history")?;
+        for workspace in workspaces {
+            let last_opened = last_opened.get(&workspace);
+            porcelain::write(
+                &mut stdout,
+                &[
+                    workspace.clone(),
+                    porcelain::flag(workspace::is_archived(&workspace)),
+                    porcelain::flag(workspace::is_encrypted(&workspace)?),
+                    last_opened.map_or_else(String::new, u64::to_string),
+                ],
+            )?;
+        }
+        return Ok(());
+    }
+    if format != ListFormat::Plain {
+        return list_details(&mut stdout, workspaces, format);
+    }
+    // Only mark encrypted workspaces for humans, scripts expect bare names.
+    let mark_encrypted = stdout.is_terminal();
+    if pattern.is_none() {
+        stdout.write_all(b"~\n").context("writing to stdout")?;
+    }
+    let width = workspaces.iter().map(|name| name.chars().count()).max();
+    for workspace in workspaces {
+        let mut line = workspace.clone();
+        if long {
+            let status = status::read(&workspace);
+            let status = status.map_or_else(|| "not checked".to_owned(), |status| status.summary());
+            let width = width.unwrap_or_default();
+            line = format!("{line:width$}  {status}");
+        }
+        if mark_encrypted && workspace::is_encrypted(&workspace)? {
+            line.push_str(" 🔒");
+        }
         stdout
-            .write_all(workspace.as_bytes())
+            .write_all(format!("{line}\n").as_bytes())
+            .context("writing to stdout")?;
+    }
+    Ok(())
+}
+
+/// Prints the name, directory and host of each of `workspaces`, and whether it's the current one
+///
+/// Definitions are read as they're written. Encrypted ones aren't decrypted, asking for every
+/// passphrase would defeat scripts, so their directory and host are unknown.
+fn list_details(
+    stdout: &mut impl Write,
+    workspaces: Vec<String>,
+    format: ListFormat,
+) -> Result<()> {
+    let current = session::current().ok();
+    let mut rows = Vec::new();
+    for name in workspaces {
+        let workspace = match workspace::is_encrypted(&name)? {
+            true => None,
+            false => match workspace::read_own(&name) {
+                Ok(workspace) => Some(workspace),
+                Err(err) => {
+                    eprintln!("WARN skipping workspace {name:?}: {err:#}");
+                    continue;
+                }
+            },
+        };
+        let dir = match &workspace {
+            Some(workspace) if workspace.ssh.is_none() => {
+                Some(workspace.local_dir()?.to_string_lossy().into_owned())
+            }
+            Some(workspace) => Some(workspace.dir.clone()),
+            None => None,
+        };
+        let host = workspace.and_then(|workspace| workspace.ssh.map(|ssh| ssh.host));
+        let is_current = current.as_ref() == Some(&name);
+        rows.push((name, dir, host, is_current));
+    }
+
+    if format == ListFormat::Json {
+        let rows = rows
+            .into_iter()
+            .map(|(name, dir, host, current)| {
+                json!({ "name": name, "dir": dir, "host": host, "current": current })
+            })
+            .collect::<Vec<_>>();
+        return writeln!(stdout, "{}", serde_json::Value::Array(rows)).context("writing to stdout");
+    }
+    let unknown = || "?".to_owned();
+    let rows = rows
+        .into_iter()
+        .map(|(name, dir, host, current)| {
+            let name = if current { format!("{name} *") } else { name };
+            let host = host.unwrap_or_else(|| {
+                if dir.is_some() {
+                    "-".to_owned()
+                } else {
+                    unknown()
+                }
+            });
+            [name, host, dir.unwrap_or_else(unknown)]
+        })
+        .collect::<Vec<_>>();
+    let header = ["NAME", "HOST", "DIR"].map(str::to_owned);
+    let width = |column: usize| {
+        let cells = rows.iter().chain([&header]);
+        cells
+            .map(|row| row[column].chars().count())
+            .max()
+            .unwrap_or_default()
+    };
+    let (name_width, host_width) = (width(0), width(1));
+    for [name, host, dir] in [header].iter().chain(&rows) {
+        writeln!(stdout, "{name:name_width$}  {host:host_width$}  {dir}")
             .context("writing to stdout")?;
-        stdout.write_all(b"\n").context("writing to stdout")?;
     }
     Ok(())
 }
 
-pub fn open(name: String) -> Result<()> {
-    let _workspace = workspace::read(&name).context("reading workpsace definition")?;
-    cache::write(Key::Current, name).context("setting currently open workspace")?;
+/// Sorts workspace names, `names` must already be sorted by name
+///
+/// The sort is stable so ties stay ordered by name.
+fn sort_workspaces(names: &mut [String], sort: Sort) -> Result<()> {
+    match sort {
+        Sort::Name => {}
+        Sort::Recent => {
+            let last_opened = history::last_opened_all().context("reading workspace history")?;
+            names.sort_by_key(|name| Reverse(last_opened.get(name).copied()));
+        }
+        Sort::Frecency => {
+            let scores = history::frecency().context("reading workspace history")?;
+            let score = |name: &String| scores.get(name).copied().unwrap_or_default();
+            names.sort_by(|a, b| score(b).total_cmp(&score(a)));
+        }
+        Sort::Host => {
+            let hosts = names
+                .iter()
+                .map(|name| {
+                    let host = match workspace::read_own(name) {
+                        Ok(workspace) => workspace.ssh.map(|ssh| ssh.host),
+                        Err(err) => {
+                            eprintln!("WARN reading workspace {name:?}: {err:#}");
+                            None
+                        }
+                    };
+                    (name.clone(), host)
+                })
+                .collect::<HashMap<_, _>>();
+            names.sort_by_key(|name| hosts[name].clone());
+        }
+        Sort::Created => {
+            names.sort_by_cached_key(|name| Reverse(workspace::created(name).ok()));
+        }
+    }
+    Ok(())
+}
+
+/// Resolves a workspace name or a glob pattern into a list of workspace names
+fn resolve_pattern(pattern: &str) -> Result<Vec<String>> {
+    if workspace::is_glob(pattern) {
+        let names = workspace::matching(pattern)?;
+        ensure!(!names.is_empty(), "no workspace matches {pattern:?}");
+        Ok(names)
+    } else {
+        Ok(vec![pattern.to_owned()])
+    }
+}
+
+/// Previews the workspaces affected by a bulk operation and asks for a single confirmation
+///
+/// Returns `false` when the operation should not go ahead.
+fn confirm_bulk(action: &str, names: &[String], dry_run: bool) -> Result<bool> {
+    eprintln!("{action} {} workspace(s):", names.len());
+    for name in names {
+        eprintln!("    {name}");
+    }
+    if dry_run {
+        return Ok(false);
+    }
+    prompt::confirm("continue?")
+}
+
+pub fn mv(source: String, destination: String, dry_run: bool, unlock: bool) -> Result<()> {
+    let names = resolve_pattern(&source)?;
+    let moves = if workspace::is_glob(&source) {
+        ensure!(
+            destination.ends_with('/'),
+            "destination must be a group ending with '/' when moving by a glob pattern",
+        );
+        // Everything after the last literal group in the pattern is kept under the destination.
+        let first_meta = source.find(['*', '?', '[']).unwrap_or(source.len());
+        let prefix_len = source[..first_meta].rfind('/').map_or(0, |i| i + 1);
+        names
+            .into_iter()
+            .map(|name| {
+                let new = format!("{destination}{}", &name[prefix_len..]);
+                (name, new)
+            })
+            .collect::<Vec<_>>()
+    } else if destination.ends_with('/') {
+        let base = source.rsplit('/').next().unwrap_or(&source);
+        let new = format!("{destination}{base}");
+        vec![(source.clone(), new)]
+    } else {
+        vec![(source.clone(), destination)]
+    };
+
+    apply_moves(moves, workspace::is_glob(&source), dry_run, unlock)
+}
+
+/// Renames workspaces and everything referring to them
+///
+/// Shows a preview when `bulk` or `dry_run` is set, and asks for confirmation when `bulk` is set.
+fn apply_moves(
+    moves: Vec<(String, String)>,
+    bulk: bool,
+    dry_run: bool,
+    unlock: bool,
+) -> Result<()> {
+    // Check everything up front so a conflict doesn't leave a half-done move behind.
+    for (i, (old, new)) in moves.iter().enumerate() {
+        ensure_unlocked(old, unlock)?;
+        ensure!(!workspace::exists(new)?, "workspace {new:?} already exists");
+        ensure!(
+            moves[..i].iter().all(|(_, other)| other != new),
+            "multiple workspaces would be moved to {new:?}",
+        );
+    }
+
+    if bulk || dry_run {
+        let preview = moves
+            .iter()
+            .map(|(old, new)| format!("{old} -> {new}"))
+            .collect::<Vec<_>>();
+        if !confirm_bulk("moving", &preview, dry_run)? {
+            return Ok(());
+        }
+    }
+
+    for (old, new) in moves {
+        workspace::rename(&old, &new).with_context(|| format!("moving workspace {old:?}"))?;
+        let mut transaction = Transaction::default();
+        history::rename(&mut transaction, &old, &new).context("updating workspace history")?;
+        windows::rename(&mut transaction, &old, &new).context("updating opened windows")?;
+        session::rename(&mut transaction, &old, &new)
+            .context("updating currently open workspaces")?;
+        // Keys named after the workspace follow it.
+        for key in [Key::Nvim, Key::Status, Key::Project] {
+            if let Ok(value) = cache::read(key(old.clone())) {
+                transaction
+                    .remove(key(old.clone()))
+                    .write(key(new.clone()), &value);
+            }
+        }
+        transaction
+            .commit()
+            .context("updating state of the moved workspace")?;
+        // The definition has already moved, don't stop halfway through the batch.
+        if let Err(err) = secret::rename(&old, &new) {
+            eprintln!("WARN could not move secrets of workspace {old:?}: {err:#}");
+        }
+    }
+    Ok(())
+}
+
+pub fn archive(pattern: String, dry_run: bool, unlock: bool) -> Result<()> {
+    let moves = resolve_pattern(&pattern)?
+        .into_iter()
+        .map(|name| {
+            ensure!(
+                !workspace::is_archived(&name),
+                "workspace {name:?} is already archived",
+            );
+            let archived = format!("{}/{name}", workspace::ARCHIVE);
+            Ok((name, archived))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    apply_moves(moves, workspace::is_glob(&pattern), dry_run, unlock)
+}
+
+pub fn unarchive(pattern: String, dry_run: bool, unlock: bool) -> Result<()> {
+    // Archived workspaces can be referred to with or without the archive group.
+    let pattern = if workspace::is_archived(&pattern) {
+        pattern
+    } else {
+        format!("{}/{pattern}", workspace::ARCHIVE)
+    };
+    let moves = resolve_pattern(&pattern)?
+        .into_iter()
+        .map(|name| {
+            let unarchived = name[workspace::ARCHIVE.len() + 1..].to_owned();
+            (name, unarchived)
+        })
+        .collect::<Vec<_>>();
+    apply_moves(moves, workspace::is_glob(&pattern), dry_run, unlock)
+}
+
+/// Deletes the definitions of the workspaces matching `pattern` after asking for confirmation,
+/// unless `force`
+///
+/// A glob pattern or `dry_run` shows a preview of the workspaces first. A deleted workspace which
+/// is currently open is no longer, and its history, cached state and secrets are forgotten.
+pub fn delete(pattern: String, force: bool, dry_run: bool, unlock: bool) -> Result<()> {
+    let names = resolve_pattern(&pattern)?;
+    // Check everything up front so a locked workspace doesn't leave a half-done delete behind.
+    for name in &names {
+        ensure!(
+            workspace::exists(name)?,
+            "workspace {name:?} does not exist"
+        );
+        ensure_unlocked(name, unlock)?;
+    }
+
+    if dry_run || (workspace::is_glob(&pattern) && !force) {
+        if !confirm_bulk("deleting", &names, dry_run)? {
+            return Ok(());
+        }
+    } else if !force && !prompt::confirm(&format!("delete workspace {pattern:?}?"))? {
+        bail!("not deleting workspace {pattern:?}");
+    }
+
+    for name in names {
+        // A workspace created under the same name later mustn't get them.
+        secret::clear(&name).context("removing secrets of the deleted workspace")?;
+        workspace::remove(&name)?;
+        let mut transaction = Transaction::default();
+        session::forget(&mut transaction, &name)?;
+        history::retain(&mut transaction, |entry| Ok(entry.name != name))?;
+        for key in [Key::Nvim, Key::Status, Key::Project] {
+            transaction.remove(key(name.clone()));
+        }
+        transaction
+            .commit()
+            .context("forgetting deleted workspace")?;
+        println!("deleted workspace {name:?}");
+    }
+    Ok(())
+}
+
+/// Fails if workspace `name` is locked, unless `unlock` is set
+fn ensure_unlocked(name: &str, unlock: bool) -> Result<()> {
+    ensure!(
+        unlock || !workspace::is_locked(name)?,
+        "workspace {name:?} is locked, pass --unlock to modify it anyway",
+    );
+    Ok(())
+}
+
+pub fn lock(pattern: String) -> Result<()> {
+    for name in resolve_pattern(&pattern)? {
+        workspace::set_locked(&name, true)
+            .with_context(|| format!("locking workspace {name:?}"))?;
+        println!("locked workspace {name:?}");
+    }
+    Ok(())
+}
+
+pub fn unlock(pattern: String) -> Result<()> {
+    for name in resolve_pattern(&pattern)? {
+        workspace::set_locked(&name, false)
+            .with_context(|| format!("unlocking workspace {name:?}"))?;
+        println!("unlocked workspace {name:?}");
+    }
+    Ok(())
+}
+
+pub fn set(pattern: String, key: String, value: String, dry_run: bool, unlock: bool) -> Result<()> {
+    let names = resolve_pattern(&pattern)?;
+    for name in &names {
+        ensure_unlocked(name, unlock)?;
+    }
+    // Values which aren't valid TOML, like bare words, are taken as strings.
+    let value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(value));
+
+    if workspace::is_glob(&pattern) || dry_run {
+        let action = format!("setting {key} = {} in", value.to_string().trim());
+        if !confirm_bulk(&action, &names, dry_run)? {
+            return Ok(());
+        }
+    }
+
+    for name in names {
+        workspace::set(&name, &key, value.clone())
+            .with_context(|| format!("setting {key:?} in workspace {name:?}"))?;
+    }
     Ok(())
 }
 
-pub fn cat(name: Option<String>) -> Result<()> {
+/// Opens the definition of workspace `name`, or the current one, in `$VISUAL` or `$EDITOR` and
+/// saves it once it's valid
+pub fn edit(name: Option<String>, unlock: bool) -> Result<()> {
+    // Only the name is needed, a definition which doesn't parse is what gets fixed here.
     let name = match name {
         Some(name) => name,
-        None => cache::read(Key::Current).context("get current workspace name")?,
+        None => session::current().context("get current workspace name")?,
     };
-    let workspace = workspace::read(&name).context("reading workpsace definition")?;
-    let json = serde_json::to_string(&workspace).context("serializing workspace definition")?;
-    println!("{json}");
+    ensure_unlocked(&name, unlock)?;
+    if workspace::edit(&name, &file_editor())? {
+        println!("saved workspace {name:?}");
+    }
     Ok(())
 }
 
-pub fn terminal() -> Result<()> {
-    let workspace = workspace::current().context("get current workspace")?;
-    let dir = &workspace.dir;
-    let shell_cmd = match &workspace.shell {
-        Some(shell) => shell.command.as_str(),
-        None => "/usr/bin/bash", // TODO use remote user's default `$SHELL`
+pub fn migrate(dry_run: bool) -> Result<()> {
+    let verb = if dry_run { "would migrate" } else { "migrated" };
+    let config = config::config_path()?;
+    if config.is_file() && migrate::rewrite(&config, migrate::Kind::Config, dry_run)?.is_some() {
+        println!("{verb} config file to version {}", migrate::CONFIG_VERSION);
+    }
+    for name in workspace::list() {
+        match workspace::upgrade(&name, dry_run) {
+            Ok(Some(_)) => println!(
+                "{verb} workspace {name:?} to version {}",
+                migrate::WORKSPACE_VERSION,
+            ),
+            Ok(None) => {}
+            Err(err) => eprintln!("WARN failed to migrate workspace {name:?}: {err:#}"),
+        }
+    }
+    Ok(())
+}
+
+/// Checks git status and reachability of workspaces last checked more than `max_age` seconds ago,
+/// see [`status`]
+pub fn refresh(max_age: u64) -> Result<()> {
+    let now = history::now();
+    let mut workspaces = Vec::new();
+    let mut remote = Vec::new();
+    for name in workspace::list() {
+        if workspace::is_archived(&name) {
+            continue;
+        }
+        let workspace = match workspace::read(&name) {
+            Ok(workspace) => Arc::new(workspace),
+            Err(err) => {
+                eprintln!("WARN skipping workspace {name:?}: {err:#}");
+                continue;
+            }
+        };
+        if workspace.ssh.is_some() {
+            remote.push(Arc::clone(&workspace));
+        }
+        let fresh =
+            status::read(&name).is_some_and(|status| now.saturating_sub(status.time) < max_age);
+        if !fresh {
+            workspaces.push(workspace);
+        }
+    }
+    let spinner = progress::spinner(format!("checking {} workspace(s)", workspaces.len()));
+    let (statuses, projects) = runtime::block_on(async {
+        // Project configs are fetched alongside, they don't need the statuses.
+        let projects = tokio::spawn(project::fetch_stale(remote));
+        let statuses = status::check_all(workspaces).await;
+        (statuses, projects.await)
+    })?;
+    spinner.finish_and_clear();
+    for (name, err) in projects.context("fetching project configs")?? {
+        eprintln!("WARN failed to read project config of workspace {name:?}: {err:#}");
+    }
+    let mut refreshed = 0;
+    for (name, status) in statuses? {
+        match status {
+            Ok(status) => {
+                status::write(&name, &status)?;
+                refreshed += 1;
+            }
+            Err(err) => eprintln!("WARN failed to check workspace {name:?}: {err:#}"),
+        }
+    }
+    eprintln!("INFO refreshed the status of {refreshed} workspace(s)");
+    Ok(())
+}
+
+/// Prints suspicious settings of all workspace definitions, see [`lint`]
+///
+/// Fails if there are any, so it can run in scripts.
+pub fn lint() -> Result<()> {
+    let mut workspaces = Vec::new();
+    let mut unreadable = 0;
+    for name in workspace::list() {
+        match workspace::read_own(&name) {
+            Ok(workspace) => workspaces.push(workspace),
+            Err(err) => {
+                println!("{name}: can't be read: {err:#}");
+                unreadable += 1;
+            }
+        }
+    }
+    let problems = lint::check(&workspaces);
+    for problem in &problems {
+        println!("{}: {}", problem.name, problem.message);
+    }
+    let count = unreadable + problems.len();
+    ensure!(count == 0, "found {count} problem(s)");
+    Ok(())
+}
+
+/// Opens workspace `name`, and with `and_exec` runs it in the workspace and switches back to the
+/// previously open workspace
+///
+/// Without a `name` the workspace is picked from a list, see [`pick`]. The workspace becomes the
+/// current one of this terminal session, and with `global` of all sessions, see [`session`].
+pub fn open(
+    name: Option<String>,
+    no_deps: bool,
+    checkout: bool,
+    global: bool,
+    and_exec: Vec<String>,
+) -> Result<()> {
+    let name = match name {
+        Some(name) => name,
+        None => pick()?,
     };
+    let previous = session::snapshot();
+    let mut workspaces = if no_deps {
+        vec![workspace::read(&name).context("reading workspace definition")?]
+    } else {
+        meta::with_dependencies(std::slice::from_ref(&name))?
+    };
+    let workspace = workspaces.pop().expect("the workspace comes last");
+    git::check_branch(&workspace, checkout)?;
+    for dependency in workspaces {
+        let mut transaction = Transaction::default();
+        history::record(&mut transaction, &dependency.name);
+        transaction
+            .commit()
+            .context("recording workspace history")?;
+        events::emit(events::Event::WorkspaceOpened {
+            workspace: &dependency.name,
+        });
+        hooks::run(&dependency, hooks::Event::Open)?;
+    }
+    let mut transaction = Transaction::default();
+    history::record(&mut transaction, &name);
+    session::open(&mut transaction, &name, global)?;
+    transaction
+        .commit()
+        .context("setting currently open workspace")?;
+    events::emit(events::Event::WorkspaceOpened { workspace: &name });
+    hooks::run(&workspace, hooks::Event::Open)?;
+    if and_exec.is_empty() {
+        return Ok(());
+    }
 
-    if let Some(ssh) = &workspace.ssh {
-        Command::new("kitty")
-            .args([
-                "ssh",
-                "-t",
-                &ssh.host,
-                &format!("cd {dir}; exec {shell_cmd} --login"),
-            ])
-            .spawn()
-            .context("spawn terminal")?;
+    let status = workspace_command(&workspace, &and_exec, false).and_then(|mut command| {
+        runner::status(&mut command).with_context(|| format!("run {:?}", and_exec[0]))
+    });
+    let mut transaction = Transaction::default();
+    session::restore(&mut transaction, previous);
+    transaction
+        .commit()
+        .context("restoring previously open workspace")?;
+    exit_like(status?)
+}
+
+/// Asks for a workspace in a fuzzy searchable list, most frecently opened first
+///
+/// Archived workspaces aren't listed.
+fn pick() -> Result<String> {
+    let mut names = workspace::list();
+    names.retain(|name| !workspace::is_archived(name));
+    sort_workspaces(&mut names, Sort::Frecency)?;
+    let picked = prompt::pick("open workspace", &names)?;
+    Ok(names.swap_remove(picked))
+}
+
+/// Runs `command` in workspace `name`, or the current one, and exits with its exit code
+///
+/// With `tty` the command gets a terminal, over ssh too, for interactive programs like `htop`.
+pub fn exec(name: Option<String>, command: Vec<String>, tty: bool) -> Result<()> {
+    let workspace = select(name)?;
+    // Printed commands are run later, in a terminal or not.
+    let terminal = io::stdin().is_terminal() || runner::is_dry_run();
+    if tty && !terminal {
+        eprintln!("WARN --tty without a terminal, the command runs without one");
+    }
+    let mut command = workspace_command(&workspace, &command, tty && terminal)?;
+    let program = command.get_program().to_string_lossy().into_owned();
+    exit_like(runner::status(&mut command).with_context(|| format!("run {program}"))?)
+}
+
+/// Prints the environment variables `workspacectl` sets for processes it spawns in workspace
+/// `name`, or the currently open one
+///
+/// With `resolved` the whole environment of local spawns is printed instead, variables added to
+/// the current environment marked with `+` and changed ones with `~`. Values of secrets are
+/// hidden.
+pub fn env(name: Option<String>, resolved: bool) -> Result<()> {
+    let workspace = select(name)?;
+    let set = session_env(&workspace)?;
+    let secrets = secret_env(&workspace)
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect::<HashSet<_>>();
+    let shown = |key: &str, value: &str| match secrets.contains(key) {
+        true => "<secret>".to_owned(),
+        false => value.to_owned(),
+    };
+    if resolved && workspace.ssh.is_some() {
+        eprintln!("INFO remote processes get these on top of the environment on the host");
+    }
+    if !resolved || workspace.ssh.is_some() {
+        for (key, value) in &set {
+            println!("{key}={}", shown(key, value));
+        }
+        return Ok(());
+    }
+
+    let current = env::vars_os()
+        .map(|(key, value)| {
+            let key = key.to_string_lossy().into_owned();
+            (key, value.to_string_lossy().into_owned())
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut spawned = current.clone();
+    spawned.extend(set);
+    let color = io::stdout().is_terminal();
+    for (key, value) in &spawned {
+        let value = shown(key, value);
+        let (mark, ansi, was) = match current.get(key) {
+            None => ("+", "\x1b[32m", String::new()),
+            Some(old) if shown(key, old) != value => {
+                ("~", "\x1b[33m", format!(" (was {})", shown(key, old)))
+            }
+            Some(_) => {
+                println!("  {key}={value}");
+                continue;
+            }
+        };
+        match color {
+            true => println!("{ansi}{mark} {key}={value}{was}\x1b[0m"),
+            false => println!("{mark} {key}={value}{was}"),
+        }
+    }
+    if nix_env(&workspace).is_some() || conda_env(&workspace).is_some() {
+        eprintln!("INFO the Nix or conda environment of the workspace is entered on top of this");
+    }
+    Ok(())
+}
+
+/// Opens directory `dir` as the scratch workspace, or saves the scratch workspace as workspace
+/// `save`
+///
+/// `dir` defaults to the current directory, and when saving to the scratch workspace's directory.
+/// A saved scratch workspace which is open stays open under its new name.
+pub fn scratch(dir: Option<String>, save: Option<String>) -> Result<()> {
+    let Some(name) = save else {
+        let dir = env::current_dir()
+            .context("get current working directory")?
+            .join(dir.unwrap_or_default());
+        let dir = workspace::canonicalize(&dir)?;
+        let dir = dir
+            .to_str()
+            .with_context(|| format!("path {dir:?} is not valid utf-8"))?;
+        let mut transaction = Transaction::default();
+        transaction.write(Key::Scratch, dir);
+        session::open(&mut transaction, workspace::SCRATCH, false)?;
+        transaction
+            .commit()
+            .context("setting currently open workspace")?;
+        events::emit(events::Event::WorkspaceOpened {
+            workspace: workspace::SCRATCH,
+        });
+        eprintln!("INFO opened {dir:?} as the scratch workspace");
+        return Ok(());
+    };
+
+    let dir = match dir {
+        Some(dir) => dir,
+        None => workspace::read(workspace::SCRATCH)?.dir,
+    };
+    let name = init_local(dir, Some(name), None, Format::Toml, false)?;
+    let mut transaction = Transaction::default();
+    session::rename(&mut transaction, workspace::SCRATCH, &name)?;
+    transaction
+        .commit()
+        .context("setting currently open workspace")?;
+    eprintln!("INFO saved the scratch workspace as {name:?}");
+    Ok(())
+}
+
+/// Opens workspace `name` and prints shell code entering it in the current shell
+///
+/// Local workspaces change into the directory and export the workspace environment, remote
+/// workspaces start an ssh session. Meant for `eval "$(workspacectl activate NAME)"`.
+pub fn activate(name: String) -> Result<()> {
+    let workspace = workspace::read(&name).context("reading workspace definition")?;
+    let mut transaction = Transaction::default();
+    history::record(&mut transaction, &name);
+    session::open(&mut transaction, &name, false)?;
+    transaction
+        .commit()
+        .context("setting currently open workspace")?;
+    events::emit(events::Event::WorkspaceOpened { workspace: &name });
+    hooks::run(&workspace, hooks::Event::Open)?;
+
+    let mut env = public_env(&workspace)?;
+    env.push(("WS_NAME".to_owned(), name));
+    let secrets = secret_env(&workspace);
+    let code = if let Some(ssh) = &workspace.ssh {
+        let shell_cmd = interactive_shell(&workspace)?;
+        let window = ssh::session(
+            ssh,
+            workspace.timeout,
+            &env,
+            &secrets,
+            &format!("{}exec {shell_cmd}", remote_cd(&workspace)),
+        )?;
+        // Variables sent with `SendEnv` have to be set for ssh only.
+        let assignments = window
+            .env
+            .iter()
+            .chain(&window.secrets)
+            .map(|(key, value)| format!("{key}={} ", shell::quote(value)))
+            .collect::<String>();
+        let command = shell::join(std::iter::once(&window.program).chain(&window.args));
+        format!("{assignments}{command}\n")
     } else {
-        Command::new("kitty")
-            .arg(shell_cmd)
-            .current_dir(dir)
-            .spawn()
-            .context("spawn terminal")?;
+        let dir = workspace.local_dir()?;
+        let mut code = format!("cd {}\n", shell::quote(&dir.to_string_lossy()));
+        for (key, value) in secrets.into_iter().chain(env) {
+            code.push_str(&format!("export {key}={}\n", shell::quote(&value)));
+        }
+        code
+    };
+    io::stdout()
+        .write_all(code.as_bytes())
+        .context("writing to stdout")
+}
+
+/// Prints the definitions of the workspaces matching `name`, or of the current workspace, as JSON
+///
+/// With `resolve` the settings they're opened with are printed instead, see [`resolved`].
+pub fn cat(name: Option<String>, resolve: bool, origin: bool) -> Result<()> {
+    let pattern = match name {
+        Some(name) => name,
+        None => session::current().context("get current workspace name")?,
+    };
+    for name in resolve_pattern(&pattern)? {
+        let workspace = workspace::read(&name).context("reading workpsace definition")?;
+        let json = match resolve {
+            true => resolved(&workspace, origin)?.to_string(),
+            false => {
+                serde_json::to_string(&workspace).context("serializing workspace definition")?
+            }
+        };
+        println!("{json}");
     }
     Ok(())
 }
 
-pub fn editor() -> Result<()> {
-    let workspace = workspace::current().context("get current workspace")?;
-    let dir = &workspace.dir;
-    let editor_cmd = match &workspace.editor {
-        Some(editor) => editor.command.as_str(),
-        None => "vim", // TODO find remote user's default `$EDITOR`
+/// Where a resolved setting of a workspace comes from
+#[derive(Debug, Clone, Copy)]
+enum Origin {
+    /// The workspace definition
+    Workspace,
+
+    /// The project config in the workspace directory, see [`project`]
+    Project,
+
+    /// The global config
+    Config,
+
+    /// The remote host, see [`detect`], or the local environment
+    Detected,
+
+    /// Built-in default
+    Default,
+}
+
+impl Origin {
+    /// Returns the origin of a setting set in the workspace definition if `own`, otherwise in the
+    /// global config if `global`
+    fn of(own: bool, global: bool) -> Origin {
+        match (own, global) {
+            (true, _) => Origin::Workspace,
+            (false, true) => Origin::Config,
+            (false, false) => Origin::Default,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Origin::Workspace => "workspace",
+            Origin::Project => "project",
+            Origin::Config => "config",
+            Origin::Detected => "detected",
+            Origin::Default => "default",
+        }
+    }
+}
+
+/// Returns the settings `workspace` is opened with as a JSON object
+///
+/// Unlike its definition the settings include the defaults from the global config and the remote
+/// host, and have templates expanded. With `origin` each value is an object with the `value` and
+/// its [`Origin`]. Secrets are left out.
+fn resolved(workspace: &Workspace, origin: bool) -> Result<serde_json::Value> {
+    let own = workspace::read_own(&workspace.name)?;
+    let config = config::read()?;
+    let global_ssh = config.as_ref().and_then(|config| config.ssh.as_ref());
+    let project = project::read(workspace)?
+        .map(toml::Value::try_from)
+        .transpose()
+        .context("convert project config to toml Value")?;
+    let mut resolved = serde_json::Map::new();
+    let mut set = |key: &str, value: serde_json::Value, from: Origin| {
+        // Whatever the workspace doesn't set itself is looked up in the project config first.
+        let in_project = project.as_ref().is_some_and(|project| {
+            key.split('.')
+                .try_fold(project, |value, key| value.get(key))
+                .is_some()
+        });
+        let from = match from {
+            Origin::Config if in_project => Origin::Project,
+            from => from,
+        };
+        let value = match origin {
+            true => json!({ "value": value, "origin": from.as_str() }),
+            false => value,
+        };
+        match key.split_once('.') {
+            Some((table, key)) => {
+                resolved.entry(table).or_insert_with(|| json!({}))[key] = value;
+            }
+            None => {
+                resolved.insert(key.to_owned(), value);
+            }
+        }
+    };
+
+    let dir = match workspace.ssh {
+        Some(_) => workspace.dir.clone(),
+        None => workspace.local_dir()?.to_string_lossy().into_owned(),
+    };
+    let dir_origin = match workspace.name.as_str() {
+        "~" => Origin::Default,
+        _ => Origin::Workspace,
+    };
+    set("dir", json!(dir), dir_origin);
+
+    // Only detected when it provides a default, detection connects to the host.
+    let host = match &workspace.ssh {
+        Some(ssh) if configured_shell(workspace).is_none() || workspace.editor.is_none() => {
+            Some(detected_host(ssh, workspace.timeout))
+        }
+        _ => None,
+    };
+    let own_shell = own.shell.as_ref();
+    let (shell, from) = match (configured_shell(workspace), &host) {
+        (Some(_), _) => (
+            shell_command(workspace)?,
+            Origin::of(own_shell.is_some_and(|shell| shell.command.is_some()), true),
+        ),
+        (None, Some(host)) => match &host.shell {
+            Some(shell) => (shell.clone(), Origin::Detected),
+            None => (DEFAULT_REMOTE_SHELL.to_owned(), Origin::Default),
+        },
+        (None, None) => match launch::login_shell() {
+            Some(shell) => (shell, Origin::Detected),
+            None => (launch::DEFAULT_SHELL.to_owned(), Origin::Default),
+        },
+    };
+    set("shell.command", json!(shell), from);
+    set(
+        "shell.login",
+        json!(login_shell(workspace)),
+        Origin::of(
+            own_shell.is_some_and(|shell| shell.login.is_some()),
+            workspace
+                .shell
+                .as_ref()
+                .is_some_and(|shell| shell.login.is_some()),
+        ),
+    );
+
+    let (editor, from) = match (&workspace.editor, &host) {
+        (Some(_), _) => (
+            editor_command(workspace)?,
+            Origin::of(own.editor.is_some(), true),
+        ),
+        (None, Some(host)) => match &host.editor {
+            Some(editor) => (editor.clone(), Origin::Detected),
+            None => (DEFAULT_EDITOR.to_owned(), Origin::Default),
+        },
+        (None, None) => (DEFAULT_EDITOR.to_owned(), Origin::Default),
+    };
+    set("editor.command", json!(editor), from);
+    let own_editor = own.editor.as_ref();
+    let editor = workspace.editor.as_ref();
+    set(
+        "editor.kind",
+        json!(editor_kind(workspace)),
+        Origin::of(
+            own_editor.is_some_and(|editor| editor.kind.is_some()),
+            editor.is_some_and(|editor| editor.kind.is_some()),
+        ),
+    );
+    set(
+        "editor.gui",
+        json!(has_gui_editor(workspace)),
+        Origin::of(
+            own_editor.is_some_and(|editor| editor.gui.is_some()),
+            editor.is_some_and(|editor| editor.gui.is_some()),
+        ),
+    );
+
+    let global_git = config.as_ref().and_then(|config| config.git.as_ref());
+    set(
+        "git_ui",
+        json!(git_ui_command(workspace)?),
+        Origin::of(
+            own.git.is_some_and(|git| git.ui.is_some()),
+            global_git.is_some_and(|git| git.ui.is_some()),
+        ),
+    );
+
+    set(
+        "timeout",
+        json!(runtime::timeout(workspace.timeout)?.as_secs()),
+        Origin::of(
+            workspace.timeout.is_some(),
+            config
+                .as_ref()
+                .is_some_and(|config| config.timeout.is_some()),
+        ),
+    );
+
+    if let Some(ssh) = &workspace.ssh {
+        set("ssh.host", json!(ssh.host), Origin::Workspace);
+        set(
+            "ssh.user",
+            json!(ssh.user),
+            Origin::of(ssh.user.is_some(), false),
+        );
+        set(
+            "ssh.port",
+            json!(ssh.port),
+            Origin::of(ssh.port.is_some(), false),
+        );
+        let global = global_ssh.and_then(|ssh| ssh.host_key);
+        set(
+            "ssh.host_key",
+            json!(ssh.host_key.or(global)),
+            Origin::of(ssh.host_key.is_some(), global.is_some()),
+        );
+        let global = global_ssh.and_then(|ssh| ssh.terminfo);
+        set(
+            "ssh.terminfo",
+            json!(ssh.terminfo.or(global)),
+            Origin::of(ssh.terminfo.is_some(), global.is_some()),
+        );
+        let global = global_ssh.and_then(|ssh| ssh.locale.as_ref());
+        set(
+            "ssh.locale",
+            json!(ssh.locale.as_ref().or(global)),
+            Origin::of(ssh.locale.is_some(), global.is_some()),
+        );
+    }
+
+    if let Some(tmux) = tmux_settings(workspace) {
+        let session = tmux::session_name(&workspace.name);
+        set("tmux.session", json!(session), Origin::Default);
+        set(
+            "tmux.layout",
+            json!(tmux.layout),
+            Origin::of(
+                own.tmux.is_some_and(|tmux| tmux.layout.is_some()),
+                tmux.layout.is_some(),
+            ),
+        );
+    }
+
+    let env = hooks::workspace_env(workspace)?;
+    set(
+        "env",
+        json!(env.into_iter().collect::<HashMap<_, _>>()),
+        Origin::Default,
+    );
+    Ok(serde_json::Value::Object(resolved))
+}
+
+/// Prints a tmuxp session file opening workspace `name` like its tmux session
+///
+/// Remote workspaces are exported for running tmuxp on their host.
+pub fn export_tmuxp(name: String) -> Result<()> {
+    let workspace = workspace::read(&name).context("reading workspace definition")?;
+    let shell = interactive_shell(&workspace)?;
+    let dir = match &workspace.ssh {
+        // tmuxp expands `~` but takes relative paths relative to the session file.
+        Some(_) if Path::new(&workspace.dir).is_relative() => format!("~/{}", workspace.dir),
+        Some(_) => workspace.dir.clone(),
+        None => workspace.local_dir()?.to_string_lossy().into_owned(),
+    };
+    let env = hooks::workspace_env(&workspace)?;
+    print!("{}", tmux::export(&workspace, dir, shell, env)?);
+    Ok(())
+}
+
+/// How long `info` may spend inspecting the workspace directory
+const INFO_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Details about a workspace directory which can take a while to gather
+#[derive(Debug, Default)]
+struct DirInfo {
+    /// Absolute path with symlinks resolved
+    resolved: Option<String>,
+
+    /// Currently checked out git branch
+    git_branch: Option<String>,
+
+    /// Total size of files in bytes
+    size: Option<u64>,
+
+    /// Number of files
+    files: Option<u64>,
+
+    /// Whether the directory was walked fully before running out of time
+    complete: bool,
+}
+
+pub fn info(name: Option<String>) -> Result<()> {
+    let workspace = select(name)?;
+    let dir_info = match &workspace.ssh {
+        Some(ssh) => remote_dir_info(ssh, &workspace.dir, workspace.timeout)?,
+        None => local_dir_info(&workspace.local_dir()?),
     };
+    if let Some(Porcelain::V1) = porcelain::version() {
+        let last_opened = history::last_opened(&workspace.name)?;
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let number = |value: Option<u64>| value.map_or_else(String::new, |value| value.to_string());
+        return porcelain::print_pairs(&[
+            ("name", workspace.name.clone()),
+            ("dir", dir_info.resolved.unwrap_or(workspace.dir)),
+            ("ssh", optional(workspace.ssh.map(|ssh| ssh.host))),
+            (
+                "editor",
+                optional(workspace.editor.map(|editor| editor.command)),
+            ),
+            (
+                "shell",
+                optional(workspace.shell.and_then(|shell| shell.command)),
+            ),
+            ("tags", optional(workspace.tags.map(|tags| tags.join(",")))),
+            ("last-opened", number(last_opened)),
+            ("git-branch", optional(dir_info.git_branch)),
+            ("size", number(dir_info.size)),
+            ("files", number(dir_info.files)),
+            ("complete", porcelain::flag(dir_info.complete)),
+            ("notes", optional(workspace.notes)),
+        ]);
+    }
+    let unknown = || "unknown".to_owned();
 
+    println!("{:<12} {}", "name:", workspace.name);
+    println!(
+        "{:<12} {}",
+        "dir:",
+        dir_info.resolved.as_deref().unwrap_or(&workspace.dir),
+    );
     if let Some(ssh) = &workspace.ssh {
-        Command::new("kitty")
-            .args(["--title", &format!("{}: {editor_cmd} {dir}", ssh.host)])
-            .args([
-                "ssh",
-                "-t",
-                &ssh.host,
-                &format!("cd {dir}; exec /usr/bin/bash --login -c '{editor_cmd} .'",),
-            ])
-            .spawn()
-            .context("spawn terminal")?;
+        println!("{:<12} {}", "ssh:", ssh.host);
+    }
+    if let Some(editor) = &workspace.editor {
+        println!("{:<12} {}", "editor:", editor.command);
+    }
+    if let Some(command) = workspace
+        .shell
+        .as_ref()
+        .and_then(|shell| shell.command.as_ref())
+    {
+        println!("{:<12} {}", "shell:", command);
+    }
+    if let Some(tags) = &workspace.tags {
+        println!("{:<12} {}", "tags:", tags.join(", "));
+    }
+    let last_opened = history::last_opened(&workspace.name)?;
+    println!(
+        "{:<12} {}",
+        "last opened:",
+        last_opened.map_or_else(|| "never".to_owned(), history::ago),
+    );
+    println!(
+        "{:<12} {}",
+        "git branch:",
+        dir_info.git_branch.unwrap_or_else(|| "none".to_owned()),
+    );
+    let at_least = if dir_info.complete { "" } else { "at least " };
+    println!(
+        "{:<12} {at_least}{} in {at_least}{} files",
+        "disk usage:",
+        dir_info.size.map_or_else(unknown, human_size),
+        dir_info
+            .files
+            .map_or_else(unknown, |files| files.to_string()),
+    );
+    for event in [
+        hooks::Event::Open,
+        hooks::Event::Close,
+        hooks::Event::Terminal,
+        hooks::Event::Editor,
+    ] {
+        let label = format!("on_{}:", event.name());
+        // Commands after the first are aligned under it.
+        for (i, command) in hooks::resolved(&workspace, event)?.iter().enumerate() {
+            println!("{:<12} {command}", if i == 0 { label.as_str() } else { "" });
+        }
+    }
+    if let Some(notes) = &workspace.notes {
+        println!("notes:");
+        for line in notes.lines() {
+            println!("    {line}");
+        }
+    }
+    Ok(())
+}
+
+fn local_dir_info(dir: &Path) -> DirInfo {
+    let deadline = Instant::now() + INFO_TIMEOUT;
+
+    let resolved = dir
+        .canonicalize()
+        .ok()
+        .and_then(|dir| dir.to_str().map(|dir| dir.to_owned()));
+
+    let mut git = Command::new("git");
+    git.arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--abbrev-ref", "HEAD"]);
+    let git_branch = process::output_with_timeout(git, INFO_TIMEOUT)
+        .ok()
+        .flatten()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned());
+
+    let (mut size, mut files, mut complete) = (0, 0, true);
+    for entry in WalkDir::new(dir).into_iter().filter_map(|entry| entry.ok()) {
+        if Instant::now() >= deadline {
+            complete = false;
+            break;
+        }
+        if entry.file_type().is_file() {
+            files += 1;
+            size += entry.metadata().map_or(0, |metadata| metadata.len());
+        }
+    }
+
+    DirInfo {
+        resolved,
+        git_branch,
+        size: Some(size),
+        files: Some(files),
+        complete,
+    }
+}
+
+fn remote_dir_info(ssh: &workspace::Ssh, dir: &str, timeout: Option<u64>) -> Result<DirInfo> {
+    let dir = shell::quote_dir(dir);
+    // Everything is gathered in one connection, one value per line.
+    let script = format!(
+        "cd {dir} && pwd -P && (git rev-parse --abbrev-ref HEAD 2>/dev/null || echo) && du -sk . | cut -f1 && find . -type f | wc -l",
+    );
+    let mut command = ssh::background(ssh, timeout)?;
+    command.arg(script);
+    let spinner = progress::spinner(format!("inspecting {dir} on {:?}", ssh.host));
+    // `info` only spends a moment on this, no matter how long the workspace may wait.
+    let output =
+        process::output_with_timeout(command, INFO_TIMEOUT.min(runtime::timeout(timeout)?));
+    spinner.finish_and_clear();
+    let output = output.context("inspect remote workspace directory")?;
+    let Some(output) = output else {
+        eprintln!("WARN timed out inspecting remote workspace directory");
+        return Ok(DirInfo::default());
+    };
+    if !output.status.success() {
+        let error = ssh::failure(&ssh.host, &output);
+        eprintln!("WARN failed to inspect remote workspace directory: {error}");
+        return Ok(DirInfo::default());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(|line| line.trim());
+    let mut next = || lines.next().filter(|line| !line.is_empty());
+    Ok(DirInfo {
+        resolved: next().map(|dir| dir.to_owned()),
+        git_branch: next().map(|branch| branch.to_owned()),
+        size: next()
+            .and_then(|kib| kib.parse::<u64>().ok())
+            .map(|kib| kib * 1024),
+        files: next().and_then(|files| files.parse().ok()),
+        complete: true,
+    })
+}
+
+/// Formats a size in bytes with a binary unit prefix
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Prints the workspaces of `path`, or of the closest directory containing it
+///
+/// Defaults to the current directory.
+pub fn which(path: Option<String>) -> Result<()> {
+    let cwd = env::current_dir().context("get current working directory")?;
+    // Prompts run this in the working directory, which Unix already has without symlinks.
+    let (path, names) = match path {
+        None if cfg!(unix) => {
+            let names = index::containing_canonical(&cwd)?;
+            (cwd, names)
+        }
+        path => {
+            let path = cwd.join(path.as_deref().unwrap_or("."));
+            let names = index::containing(&path)?;
+            (path, names)
+        }
+    };
+    let names = names.with_context(|| format!("no workspace contains {path:?}"))?;
+    for name in names {
+        println!("{name}");
+    }
+    Ok(())
+}
+
+/// Prints the URI of the directory of workspace `name`, or of the currently open one, or opens it
+/// in the default application if `open`
+pub fn uri(name: Option<String>, open: bool) -> Result<()> {
+    let workspace = select(name)?;
+    let uri = match &workspace.ssh {
+        Some(ssh) => uri::sftp(ssh, &absolute_remote_dir(&workspace, ssh)?),
+        None => uri::file(&workspace::canonicalize(&workspace.local_dir()?)?),
+    };
+    if !open {
+        println!("{uri}");
+        return Ok(());
+    }
+    let mut command = if cfg!(windows) {
+        // The empty argument is the window title `start` takes first when quoted.
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
     } else {
-        let show_dir = &dir;
-        let dir = dirs::home_dir().unwrap().join(dir).canonicalize().unwrap();
-        Command::new("kitty")
-            .args(["--title", &format!("{editor_cmd} {show_dir}")])
-            .args([editor_cmd, "."])
-            .current_dir(dir)
-            .spawn()
-            .context("spawn terminal")?;
+        Command::new("xdg-open")
+    };
+    command.arg(&uri);
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = runner::status(&mut command).with_context(|| format!("run {program}"))?;
+    ensure!(status.success(), "{program} failed to open {uri:?}");
+    Ok(())
+}
+
+pub fn search(query: String) -> Result<()> {
+    let color = io::stdout().is_terminal();
+    for name in workspace::list() {
+        let workspace = match workspace::read_own(&name) {
+            Ok(workspace) => workspace,
+            Err(err) => {
+                eprintln!("WARN skipping workspace {name:?}: {err:#}");
+                continue;
+            }
+        };
+
+        let mut fields = vec![("name", name.clone()), ("dir", workspace.dir)];
+        if let Some(ssh) = workspace.ssh {
+            fields.push(("host", ssh.host));
+        }
+        for tag in workspace.tags.into_iter().flatten() {
+            fields.push(("tag", tag));
+        }
+        for line in workspace.notes.iter().flat_map(|notes| notes.lines()) {
+            fields.push(("notes", line.to_owned()));
+        }
+
+        for (field, value) in fields {
+            if let Some(value) = highlight(&value, &query, color) {
+                println!("{name}\t{field}: {value}");
+            }
+        }
     }
     Ok(())
 }
+
+/// Marks all case-insensitive occurrences of `query` in `value`
+///
+/// Returns `None` if there is no match. Matches are marked with bold red when `color` is set and
+/// with brackets otherwise.
+fn highlight(value: &str, query: &str, color: bool) -> Option<String> {
+    let (start, end) = if color {
+        ("\x1b[1;31m", "\x1b[0m")
+    } else {
+        ("[", "]")
+    };
+    // ASCII lowercasing keeps byte offsets valid for the original string.
+    let haystack = value.to_ascii_lowercase();
+    let needle = query.to_ascii_lowercase();
+    if needle.is_empty() || !haystack.contains(&needle) {
+        return None;
+    }
+
+    let mut marked = String::with_capacity(value.len());
+    let mut last = 0;
+    for (i, _) in haystack.match_indices(&needle) {
+        marked.push_str(&value[last..i]);
+        marked.push_str(start);
+        marked.push_str(&value[i..i + needle.len()]);
+        marked.push_str(end);
+        last = i + needle.len();
+    }
+    marked.push_str(&value[last..]);
+    Some(marked)
+}
+
+/// Reads the definition of workspace `name`, or of the currently open workspace
+fn select(name: Option<String>) -> Result<Workspace> {
+    match name {
+        Some(name) => workspace::read(&name).context("reading workspace definition"),
+        None => workspace::current().context("get current workspace"),
+    }
+}
+
+/// Returns the environment of shells and editors opened in `workspace`, its secrets, the
+/// variables describing it and its locale and time zone
+///
+/// Local workspaces also get their activated virtualenv, remote ones activate it in
+/// [`remote_cd`].
+fn session_env(workspace: &Workspace) -> Result<Vec<(String, String)>> {
+    let mut env = secret_env(workspace);
+    env.extend(public_env(workspace)?);
+    Ok(env)
+}
+
+/// Returns the environment of shells and editors opened in `workspace` without its secrets, see
+/// [`session_env`]
+fn public_env(workspace: &Workspace) -> Result<Vec<(String, String)>> {
+    let mut env = hooks::workspace_env(workspace)?;
+    if let Some(locale) = &workspace.locale {
+        env.extend(["LANG", "LC_ALL"].map(|key| (key.to_owned(), locale.clone())));
+    }
+    if let Some(tz) = &workspace.tz {
+        env.push(("TZ".to_owned(), tz.clone()));
+    }
+    if let (None, Some(venv)) = (&workspace.ssh, venv(workspace)) {
+        env.extend(python::venv_env(venv, &workspace.local_dir()?)?);
+    }
+    Ok(env)
+}
+
+/// Returns the virtualenv of `workspace`, if it has one
+fn venv(workspace: &Workspace) -> Option<&str> {
+    workspace.python.as_ref()?.venv.as_deref()
+}
+
+/// Returns the conda environment of `workspace`, if it has one
+fn conda_env(workspace: &Workspace) -> Option<&str> {
+    workspace.python.as_ref()?.conda.as_deref()
+}
+
+/// Returns the start of a script run on the host of remote `workspace`, changing into its
+/// directory and activating its virtualenv
+fn remote_cd(workspace: &Workspace) -> String {
+    let venv = venv(workspace).map(python::venv_script).unwrap_or_default();
+    format!("cd {}; {venv}", shell::quote_dir(&workspace.dir))
+}
+
+/// Returns the secrets of `workspace` as environment variables
+///
+/// A keyring which can't be read shouldn't keep the workspace from opening, so errors are only
+/// reported.
+fn secret_env(workspace: &Workspace) -> Vec<(String, String)> {
+    secret::env(&workspace.name).unwrap_or_else(|err| {
+        eprintln!("WARN could not read workspace secrets: {err:#}");
+        Vec::new()
+    })
+}
+
+/// Exits with the same code as a child process which didn't succeed
+///
+/// Used by commands which wrap another program and should be transparent to scripts.
+fn exit_like(status: ExitStatus) -> Result<()> {
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// Returns the command running `args` in the directory and environment of `workspace`
+///
+/// Remote commands run over ssh through the remote shell, with a terminal only if `tty`. Local
+/// commands share the terminal `workspacectl` runs in.
+fn workspace_command(workspace: &Workspace, args: &[String], tty: bool) -> Result<Command> {
+    let (program, args) = args.split_first().context("missing command")?;
+    let (mut program, mut args) = (program.clone(), args.to_vec());
+    if let Some(ssh) = &workspace.ssh {
+        if let Some(nix) = nix_env(workspace) {
+            (program, args) = nix::wrap(nix, &program, &args);
+        }
+        let kind = shell::Kind::of(&shell_command(workspace)?);
+        let line = kind.join(std::iter::once(&program).chain(&args));
+        let mut exports = public_env(workspace)?
+            .iter()
+            .map(|(key, value)| format!("export {key}={}; ", shell::quote(value)))
+            .collect::<String>();
+        let secrets = secret::exports(&secret_env(workspace));
+        if let Some(source) = ssh::private_exports(ssh, workspace.timeout, &secrets)? {
+            exports.push_str(&format!("{source}; "));
+        }
+        let mut command = ssh::foreground(ssh, workspace.timeout, tty)?;
+        command.arg(format!(
+            "{exports}{}",
+            remote_program_script(workspace, &line)?
+        ));
+        return Ok(command);
+    }
+
+    if let Some(name) = conda_env(workspace) {
+        (program, args) = python::conda_wrap(name, &program, &args);
+    }
+    if let Some(nix) = nix_env(workspace) {
+        (program, args) = nix::wrap(nix, &program, &args);
+    }
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .current_dir(workspace.local_dir()?)
+        .envs(session_env(workspace)?);
+    Ok(command)
+}
+
+/// Runs `command` in the workspace directory, or `fallback` when `command` is not installed
+///
+/// Remote workspaces run the command over ssh and stream the output back.
+fn run_with_fallback(
+    workspace: &Workspace,
+    command: &[&str],
+    fallback: &[&str],
+) -> Result<ExitStatus> {
+    if let Some(ssh) = &workspace.ssh {
+        let dir = shell::quote_dir(&workspace.dir);
+        let program = shell::quote(command[0]);
+        let installed = detect::profile(&ssh.host)
+            .ok()
+            .flatten()
+            .and_then(|profile| profile.has(command[0]));
+        let script = match installed {
+            Some(true) => format!("cd {dir} && exec {}", shell::join(command)),
+            Some(false) => format!("cd {dir} && exec {}", shell::join(fallback)),
+            None => format!(
+                "cd {dir} && if command -v {program} >/dev/null 2>&1; then exec {}; else exec {}; fi",
+                shell::join(command),
+                shell::join(fallback),
+            ),
+        };
+        let mut command = ssh::foreground(ssh, workspace.timeout, false)?;
+        command.arg(script);
+        return runner::status(&mut command).with_context(|| format!("run {program} over ssh"));
+    }
+
+    let dir = workspace.local_dir()?;
+    match runner::status(
+        Command::new(command[0])
+            .args(&command[1..])
+            .current_dir(&dir),
+    ) {
+        Ok(status) => Ok(status),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            eprintln!(
+                "INFO {} not found, falling back to {}",
+                command[0], fallback[0],
+            );
+            runner::status(
+                Command::new(fallback[0])
+                    .args(&fallback[1..])
+                    .current_dir(&dir),
+            )
+            .with_context(|| format!("run {}", fallback[0]))
+        }
+        Err(err) => Err(err).with_context(|| format!("run {}", command[0])),
+    }
+}
+
+pub fn grep(name: Option<String>, pattern: String, args: Vec<String>) -> Result<()> {
+    let workspace = select(name)?;
+    let args = args.iter().map(String::as_str);
+    let rg = ["rg", "-e", &pattern]
+        .into_iter()
+        .chain(args.clone())
+        .collect::<Vec<_>>();
+    let fallback = ["grep", "-rnI", "-e", &pattern]
+        .into_iter()
+        .chain(args)
+        .chain(["."])
+        .collect::<Vec<_>>();
+    exit_like(run_with_fallback(&workspace, &rg, &fallback)?)
+}
+
+pub fn find(name: Option<String>, glob: String) -> Result<()> {
+    let workspace = select(name)?;
+    let fd = ["fd", "--glob", &glob];
+    let fallback = ["find", ".", "-name", &glob];
+    exit_like(run_with_fallback(&workspace, &fd, &fallback)?)
+}
+
+/// Shell of remote workspaces when none is configured or detected
+const DEFAULT_REMOTE_SHELL: &str = "/usr/bin/bash";
+
+/// Editor when none is configured or detected
+const DEFAULT_EDITOR: &str = "vim";
+
+/// Git UI when none is configured
+const DEFAULT_GIT_UI: &str = "lazygit";
+
+/// Returns the shell command of `workspace`
+///
+/// Without one configured remote workspaces use the detected shell of the remote user, local ones
+/// the login shell of the local user.
+fn shell_command(workspace: &Workspace) -> Result<String> {
+    Ok(match configured_shell(workspace) {
+        Some(command) => template::expand(command, workspace).context("expanding shell command")?,
+        None => match &workspace.ssh {
+            Some(ssh) => detected_host(ssh, workspace.timeout)
+                .shell
+                .unwrap_or_else(|| DEFAULT_REMOTE_SHELL.to_owned()),
+            None => launch::login_shell().unwrap_or_else(|| launch::DEFAULT_SHELL.to_owned()),
+        },
+    })
+}
+
+/// Returns the shell command configured for `workspace`, if there's one
+fn configured_shell(workspace: &Workspace) -> Option<&String> {
+    workspace.shell.as_ref()?.command.as_ref()
+}
+
+/// Returns whether the shell of `workspace` is started as a login shell
+fn login_shell(workspace: &Workspace) -> bool {
+    let login = workspace.shell.as_ref().and_then(|shell| shell.login);
+    login.unwrap_or(workspace.ssh.is_some())
+}
+
+/// Returns the command line starting the interactive shell of `workspace`
+///
+/// Local shells are a single program, remote ones are taken as a command line by the remote
+/// shell.
+fn interactive_shell(workspace: &Workspace) -> Result<String> {
+    let shell_cmd = shell_command(workspace)?;
+    let mut line = match workspace.ssh {
+        Some(_) => shell_cmd.clone(),
+        None => shell::quote(&shell_cmd),
+    };
+    if login_shell(workspace) {
+        line = format!("{line} {}", shell::login_flag(&shell_cmd));
+    }
+    if let Some(name) = conda_env(workspace) {
+        line = python::conda_wrap_line(name, &line);
+    }
+    if let Some(nix) = nix_env(workspace) {
+        line = nix::wrap_line(nix, &line);
+    }
+    Ok(line)
+}
+
+/// Returns the Nix environment shells of `workspace` are started in
+///
+/// Remote workspaces only get it if their host has `nix`, or it's not known whether it does.
+fn nix_env(workspace: &Workspace) -> Option<&workspace::Nix> {
+    let nix = workspace.nix.as_ref()?;
+    if let Some(ssh) = &workspace.ssh {
+        if detected_host(ssh, workspace.timeout).has("nix") == Some(false) {
+            eprintln!(
+                "WARN nix is not installed on {:?}, starting the shell without it",
+                ssh.host
+            );
+            return None;
+        }
+    }
+    Some(nix)
+}
+
+/// Returns the detected properties of `ssh`'s host
+///
+/// They only provide defaults, so failing to detect them is reported and then ignored.
+fn detected_host(ssh: &workspace::Ssh, timeout: Option<u64>) -> detect::Host {
+    detect::host(ssh, timeout).unwrap_or_else(|err| {
+        eprintln!("WARN could not detect remote defaults: {err:#}");
+        detect::Host::default()
+    })
+}
+
+pub fn terminal(here: bool) -> Result<()> {
+    let workspace = workspace::current().context("get current workspace")?;
+    open_terminal(&workspace, here).map(drop)
+}
+
+/// Opens the editor of the current workspace, at file and line `at` if given
+///
+/// Neovim which is already running for a local workspace opens the file itself, see [`nvim`].
+pub fn editor(here: bool, at: Option<String>) -> Result<()> {
+    let workspace = workspace::current().context("get current workspace")?;
+    let at = at.as_deref().map(Position::parse);
+    if let Some(at) = &at {
+        if !here && workspace.ssh.is_none() && editor_in_terminal(&workspace) {
+            let program = editor_command(&workspace)?;
+            let path = workspace.local_dir()?.join(at.file);
+            if nvim::is_nvim(&program) && nvim::open(&workspace.name, &program, &path, at.line)? {
+                return Ok(());
+            }
+        }
+    }
+    open_editor(&workspace, here, at.as_ref()).map(drop)
+}
+
+/// File and line the editor opens, given as `FILE[:LINE]`
+///
+/// Relative files are relative to the workspace directory.
+struct Position<'a> {
+    file: &'a str,
+    line: Option<u32>,
+}
+
+impl Position<'_> {
+    fn parse(at: &str) -> Position<'_> {
+        if let Some((file, line)) = at.rsplit_once(':') {
+            if let Ok(line) = line.parse() {
+                return Position {
+                    file,
+                    line: Some(line),
+                };
+            }
+        }
+        Position {
+            file: at,
+            line: None,
+        }
+    }
+}
+
+/// Returns the arguments making a terminal editor open `at`, or the workspace directory
+///
+/// Most terminal editors understand `+LINE` before the file.
+fn editor_args(at: Option<&Position>) -> Vec<String> {
+    match at {
+        Some(at) => at
+            .line
+            .map(|line| format!("+{line}"))
+            .into_iter()
+            .chain([at.file.to_owned()])
+            .collect(),
+        None => vec![".".to_owned()],
+    }
+}
+
+/// Emits the start of process `spawned` opening a window of `workspace` and passes it on
+fn started(workspace: &Workspace, spawned: (u32, String)) -> (u32, String) {
+    let (pid, program) = &spawned;
+    events::emit(events::Event::SpawnStarted {
+        workspace: Some(&workspace.name),
+        program,
+        pid: *pid,
+    });
+    spawned
+}
+
+/// Opens a shell in `workspace`, in a new terminal window unless `here`
+///
+/// Returns the process id and program of what was spawned to open a new window.
+fn open_terminal(workspace: &Workspace, here: bool) -> Result<Option<(u32, String)>> {
+    hooks::run(workspace, hooks::Event::Terminal)?;
+    let window = terminal_window(
+        workspace,
+        &[],
+        public_env(workspace)?,
+        &secret_env(workspace),
+    )?;
+    if here {
+        launch::run_here(window)?;
+        return Ok(None);
+    }
+    launch::spawn(window).map(|spawned| spawned.map(|spawned| started(workspace, spawned)))
+}
+
+/// Returns the tmux settings of `workspace` if its terminals attach to a tmux session
+///
+/// `multiplexer` decides, without one workspaces with a `[tmux]` table use tmux.
+fn tmux_settings(workspace: &Workspace) -> Option<&workspace::Tmux> {
+    /// Settings of workspaces using tmux without a `[tmux]` table
+    static DEFAULT_TMUX: workspace::Tmux = workspace::Tmux { layout: None };
+    match workspace.multiplexer {
+        Some(Multiplexer::Tmux) => Some(workspace.tmux.as_ref().unwrap_or(&DEFAULT_TMUX)),
+        Some(Multiplexer::None) => None,
+        None => workspace.tmux.as_ref(),
+    }
+}
+
+/// Returns the window of a shell in `workspace`
+///
+/// `commands` run before the shell starts. Without any, workspaces using tmux attach to their tmux
+/// session instead, see [`tmux_settings`]. The shell gets the variables in `env` and `secrets`.
+fn terminal_window(
+    workspace: &Workspace,
+    commands: &[String],
+    env: Vec<(String, String)>,
+    secrets: &[(String, String)],
+) -> Result<Window> {
+    let shell_cmd = shell_command(workspace)?;
+    let title = workspace
+        .shell
+        .as_ref()
+        .and_then(|shell| shell.title.as_ref())
+        .map(|title| template::expand(title, workspace))
+        .transpose()
+        .context("expanding shell title")?;
+
+    let tmux = tmux_settings(workspace).filter(|_| commands.is_empty());
+    let mut window = if let Some(ssh) = &workspace.ssh {
+        let script = remote_terminal_script(workspace, commands, &env, secrets)?;
+        ssh::session(ssh, workspace.timeout, &env, secrets, &script)?
+    } else if tmux.is_some() || !commands.is_empty() {
+        ensure!(
+            !cfg!(windows),
+            "tmux sessions and layouts are not supported on Windows"
+        );
+        let shell_cmd = interactive_shell(workspace)?;
+        let script = match tmux {
+            Some(tmux) => tmux::attach_script(workspace, tmux, &shell_cmd, &env, &names(secrets))?,
+            None => format!("{}exec {shell_cmd}", before(commands)),
+        };
+        Window {
+            dir: Some(workspace.local_dir()?),
+            args: vec!["-c".to_owned(), script],
+            env,
+            secrets: secrets.to_vec(),
+            ..Window::new("sh")
+        }
+    } else {
+        let login = login_shell(workspace).then(|| shell::login_flag(&shell_cmd).to_owned());
+        let mut args = login.into_iter().collect::<Vec<_>>();
+        let mut program = shell_cmd;
+        if let Some(name) = conda_env(workspace) {
+            (program, args) = python::conda_wrap(name, &program, &args);
+        }
+        if let Some(nix) = nix_env(workspace) {
+            (program, args) = nix::wrap(nix, &program, &args);
+        }
+        Window {
+            dir: Some(workspace.local_dir()?),
+            args,
+            env,
+            secrets: secrets.to_vec(),
+            ..Window::new(program)
+        }
+    };
+    window.title = title;
+    Ok(window)
+}
+
+/// Returns the script starting the shell of remote `workspace` on its host
+///
+/// `commands` run before the shell starts, see [`terminal_window`]. `env` and `secrets` only reach
+/// a tmux session, the caller exports them for everything else.
+fn remote_terminal_script(
+    workspace: &Workspace,
+    commands: &[String],
+    env: &[(String, String)],
+    secrets: &[(String, String)],
+) -> Result<String> {
+    let shell_cmd = interactive_shell(workspace)?;
+    let script = match tmux_settings(workspace).filter(|_| commands.is_empty()) {
+        Some(tmux) => tmux::attach_script(workspace, tmux, &shell_cmd, env, &names(secrets))?,
+        None => format!("{}exec {shell_cmd}", before(commands)),
+    };
+    Ok(format!("{}{script}", remote_cd(workspace)))
+}
+
+/// Returns the names of the variables in `env`
+fn names(env: &[(String, String)]) -> Vec<String> {
+    env.iter().map(|(key, _)| key.clone()).collect()
+}
+
+/// Returns `commands` as the start of a shell script
+fn before(commands: &[String]) -> String {
+    commands
+        .iter()
+        .map(|command| format!("{command}; "))
+        .collect()
+}
+
+/// Returns the script starting `editor_cmd` with `args` for remote `workspace` on its host
+fn remote_editor_script(
+    workspace: &Workspace,
+    editor_cmd: &str,
+    args: &[String],
+) -> Result<String> {
+    let kind = shell::Kind::of(&shell_command(workspace)?);
+    remote_program_script(workspace, &format!("{editor_cmd} {}", kind.join(args)))
+}
+
+/// Returns the script running command line `command` for remote `workspace` on its host
+///
+/// The command is run by the workspace shell as a login shell, so it finds programs from the
+/// remote user's `PATH`, and has to be written in the shell's syntax.
+fn remote_program_script(workspace: &Workspace, command: &str) -> Result<String> {
+    let shell_cmd = shell_command(workspace)?;
+    let mut line = format!(
+        "{shell_cmd} {} {} {}",
+        shell::login_flag(&shell_cmd),
+        shell::Kind::of(&shell_cmd).command_flag(),
+        shell::quote(command),
+    );
+    if let Some(name) = conda_env(workspace) {
+        line = python::conda_wrap_line(name, &line);
+    }
+    Ok(format!("{}exec {line}", remote_cd(workspace)))
+}
+
+/// Returns whether `workspace` is edited with a GUI editor
+fn has_gui_editor(workspace: &Workspace) -> bool {
+    workspace.editor.as_ref().and_then(|editor| editor.gui) == Some(true)
+}
+
+/// Returns the kind of the editor of `workspace`, `None` for ordinary commands
+fn editor_kind(workspace: &Workspace) -> Option<EditorKind> {
+    workspace.editor.as_ref().and_then(|editor| editor.kind)
+}
+
+/// Returns whether remote `workspace` is edited with VS Code connecting over Remote-SSH
+fn has_vscode_remote(workspace: &Workspace) -> bool {
+    let command = workspace
+        .editor
+        .as_ref()
+        .map(|editor| editor.command.as_str());
+    workspace.ssh.is_some() && command.is_some_and(vscode::is_code)
+}
+
+/// Returns whether the editor of `workspace` runs in a terminal window
+fn editor_in_terminal(workspace: &Workspace) -> bool {
+    editor_kind(workspace).is_none() && !has_gui_editor(workspace) && !has_vscode_remote(workspace)
+}
+
+/// Returns the editor command of `workspace`
+fn editor_command(workspace: &Workspace) -> Result<String> {
+    Ok(match &workspace.editor {
+        Some(editor) => {
+            template::expand(&editor.command, workspace).context("expanding editor command")?
+        }
+        None => workspace
+            .ssh
+            .as_ref()
+            .and_then(|ssh| detected_host(ssh, workspace.timeout).editor)
+            .unwrap_or_else(|| DEFAULT_EDITOR.to_owned()),
+    })
+}
+
+/// Opens the editor in `workspace`, in a new terminal window unless `here` or it has no terminal
+///
+/// The editor opens `at`, or the workspace directory. Returns the process id and program of what
+/// was spawned to open a new window.
+fn open_editor(
+    workspace: &Workspace,
+    here: bool,
+    at: Option<&Position>,
+) -> Result<Option<(u32, String)>> {
+    hooks::run(workspace, hooks::Event::Editor)?;
+    match editor_kind(workspace) {
+        Some(EditorKind::Emacs) => return open_emacs(workspace, here, at),
+        Some(EditorKind::JetbrainsGateway) => return open_gateway(workspace, at),
+        None if has_vscode_remote(workspace) => return open_vscode_remote(workspace, at),
+        None if has_gui_editor(workspace) => return open_gui_editor(workspace, at),
+        None => {}
+    }
+    let args = editor_args(at);
+    let mut window = editor_window(
+        workspace,
+        public_env(workspace)?,
+        &secret_env(workspace),
+        &args,
+    )?;
+    if workspace.ssh.is_none() && nvim::is_nvim(&window.program) {
+        nvim::listen(&mut window, &workspace.name)?;
+    }
+    if here {
+        launch::run_here(window)?;
+        return Ok(None);
+    }
+    launch::spawn(window).map(|spawned| spawned.map(|spawned| started(workspace, spawned)))
+}
+
+/// Starts the GUI editor of local `workspace`, opening file `at` or the workspace directory
+///
+/// GUI editors don't share a way to open a line, only the file is opened. Returns the process id
+/// and program of what was spawned.
+fn open_gui_editor(workspace: &Workspace, at: Option<&Position>) -> Result<Option<(u32, String)>> {
+    ensure!(
+        workspace.ssh.is_none(),
+        "GUI editors can only open local workspaces",
+    );
+    let editor_cmd = editor_command(workspace)?;
+    let mut path = workspace::canonicalize(&workspace.local_dir()?)?;
+    if let Some(at) = at {
+        if at.line.is_some() {
+            eprintln!("WARN GUI editors are opened without a line");
+        }
+        path = path.join(at.file);
+    }
+    let path = launch::system_quote(&path.to_string_lossy());
+    let mut command = launch::system_shell(&format!("{editor_cmd} {path}"));
+    command.envs(session_env(workspace)?);
+    spawn_editor(workspace, command)
+}
+
+/// Returns the absolute directory of remote `workspace` on `ssh`'s host
+///
+/// Directories relative to the remote home are resolved with the detected home directory.
+fn absolute_remote_dir(workspace: &Workspace, ssh: &workspace::Ssh) -> Result<String> {
+    if workspace.dir.starts_with('/') {
+        return Ok(workspace.dir.clone());
+    }
+    let dir = workspace.dir.strip_prefix("~/").unwrap_or(&workspace.dir);
+    let host = &ssh.host;
+    let home = detected_host(ssh, workspace.timeout)
+        .home
+        .with_context(|| format!("could not determine the home directory on {host:?}"))?;
+    Ok(format!("{}/{dir}", home.trim_end_matches('/')))
+}
+
+/// Opens remote `workspace` in the local VS Code, which connects to the host with Remote-SSH
+///
+/// VS Code needs the absolute path of the directory. Returns the process id and program of what
+/// was spawned.
+fn open_vscode_remote(
+    workspace: &Workspace,
+    at: Option<&Position>,
+) -> Result<Option<(u32, String)>> {
+    let ssh = workspace.ssh.as_ref().context("workspace is not remote")?;
+    let editor_cmd = editor_command(workspace)?;
+    let dir = absolute_remote_dir(workspace, ssh)?;
+    let path = match at {
+        Some(at) => {
+            let file = match at.file.starts_with('/') {
+                true => at.file.to_owned(),
+                false => format!("{}/{}", dir.trim_end_matches('/'), at.file),
+            };
+            let file = match at.line {
+                Some(line) => format!("{file}:{line}"),
+                None => file,
+            };
+            format!("--goto {}", launch::system_quote(&file))
+        }
+        None => launch::system_quote(&dir),
+    };
+    let authority = launch::system_quote(&vscode::authority(ssh));
+    let mut command = launch::system_shell(&format!("{editor_cmd} --remote {authority} {path}"));
+    command.envs(session_env(workspace)?);
+    spawn_editor(workspace, command)
+}
+
+/// Spawns `command` opening an editor for `workspace`, returns the process id and program of what
+/// was spawned
+fn spawn_editor(workspace: &Workspace, mut command: Command) -> Result<Option<(u32, String)>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let child = runner::spawn(&mut command).context("spawn editor")?;
+    Ok(child.map(|child| started(workspace, (child.id(), program))))
+}
+
+/// Opens remote `workspace` in the IDE `editor.product` on its host with JetBrains Gateway
+///
+/// Gateway opens the project directory, file `at` is ignored. Returns the process id and program
+/// of what was spawned.
+fn open_gateway(workspace: &Workspace, at: Option<&Position>) -> Result<Option<(u32, String)>> {
+    let ssh = workspace
+        .ssh
+        .as_ref()
+        .context("JetBrains Gateway can only open remote workspaces")?;
+    let product = workspace
+        .editor
+        .as_ref()
+        .and_then(|editor| editor.product.as_deref())
+        .context("`editor.product` is required with `kind = \"jetbrains-gateway\"`")?;
+    if at.is_some() {
+        eprintln!("WARN JetBrains Gateway opens the project directory, not a file");
+    }
+    let editor_cmd = editor_command(workspace)?;
+    let url = jetbrains::url(ssh, product, &absolute_remote_dir(workspace, ssh)?);
+    let url = launch::system_quote(&url);
+    let mut command = launch::system_shell(&format!("{editor_cmd} {url}"));
+    command.envs(session_env(workspace)?);
+    spawn_editor(workspace, command)
+}
+
+/// Opens `workspace` in a new frame of the Emacs server, starting the server if it isn't running
+///
+/// The frame opens file `at` or the workspace directory, remote workspaces over TRAMP. With
+/// `here` the frame is opened in the current terminal. Returns the process id and program of
+/// `emacsclient`, which keeps running until the frame is closed.
+fn open_emacs(
+    workspace: &Workspace,
+    here: bool,
+    at: Option<&Position>,
+) -> Result<Option<(u32, String)>> {
+    let editor_cmd = editor_command(workspace)?;
+    // The editor command may carry its own arguments, like `emacsclient -s work`.
+    let mut words = editor_cmd.split_whitespace();
+    let program = words.next().context("editor command is empty")?.to_owned();
+    let mut command = Command::new(&program);
+    command.args(words);
+    // An empty alternate editor starts the server.
+    command.arg(if here { "-t" } else { "-c" }).args(["-a", ""]);
+    if let Some(line) = at.and_then(|at| at.line) {
+        command.arg(format!("+{line}"));
+    }
+    match &workspace.ssh {
+        Some(ssh) => {
+            let mut authority = ssh.host.clone();
+            if let Some(user) = &ssh.user {
+                authority = format!("{user}@{authority}");
+            }
+            if let Some(port) = ssh.port {
+                authority = format!("{authority}#{port}");
+            }
+            let dir = match workspace.dir.starts_with('/') {
+                true => workspace.dir.clone(),
+                false => format!("~/{}", workspace.dir),
+            };
+            let path = match at {
+                Some(at) if at.file.starts_with('/') => at.file.to_owned(),
+                Some(at) => format!("{}/{}", dir.trim_end_matches('/'), at.file),
+                None => dir,
+            };
+            command.arg(format!("/ssh:{authority}:{path}"));
+        }
+        None => {
+            let dir = workspace.local_dir()?;
+            command.arg(at.map_or(dir.clone(), |at| dir.join(at.file)));
+            command.current_dir(dir);
+        }
+    }
+    command.envs(session_env(workspace)?);
+    if here {
+        runner::exec(&mut command)?;
+        return Ok(None);
+    }
+    let child = runner::spawn(&mut command).context("spawn emacsclient")?;
+    Ok(child.map(|child| started(workspace, (child.id(), program))))
+}
+
+/// Returns the window of the terminal editor of `workspace` opened with `args`, which gets the
+/// variables in `env` and `secrets`
+fn editor_window(
+    workspace: &Workspace,
+    env: Vec<(String, String)>,
+    secrets: &[(String, String)],
+    args: &[String],
+) -> Result<Window> {
+    let dir = &workspace.dir;
+    let editor_cmd = editor_command(workspace)?;
+    let title = workspace
+        .editor
+        .as_ref()
+        .and_then(|editor| editor.title.as_ref())
+        .map(|title| template::expand(title, workspace))
+        .transpose()
+        .context("expanding editor title")?;
+
+    Ok(if let Some(ssh) = &workspace.ssh {
+        let title = title.unwrap_or_else(|| format!("{}: {editor_cmd} {dir}", ssh.host));
+        Window {
+            title: Some(title),
+            ..ssh::session(
+                ssh,
+                workspace.timeout,
+                &env,
+                secrets,
+                &remote_editor_script(workspace, &editor_cmd, args)?,
+            )?
+        }
+    } else {
+        let title = title.unwrap_or_else(|| format!("{editor_cmd} {dir}"));
+        let dir = workspace::canonicalize(&workspace.local_dir()?)?;
+        // The editor command may carry its own arguments, they go before the ones given.
+        let mut words = editor_cmd.split_whitespace().map(str::to_owned);
+        let editor = words.next().unwrap_or_default();
+        let args: Vec<String> = words.chain(args.iter().cloned()).collect();
+        let (program, args) = match conda_env(workspace) {
+            Some(name) => python::conda_wrap(name, &editor, &args),
+            None => (editor, args),
+        };
+        Window {
+            title: Some(title),
+            dir: Some(dir),
+            args,
+            env,
+            secrets: secrets.to_vec(),
+            ..Window::new(program)
+        }
+    })
+}
+
+/// Opens the Git UI of the workspace `name`, or of the current workspace
+///
+/// The UI is opened like a terminal, in a new window unless `here`.
+pub fn git_ui(name: Option<String>, here: bool) -> Result<()> {
+    let workspace = select(name)?;
+    let window = git_ui_window(&workspace, public_env(&workspace)?, &secret_env(&workspace))?;
+    if here {
+        return launch::run_here(window);
+    }
+    if let Some(spawned) = launch::spawn(window)? {
+        started(&workspace, spawned);
+    }
+    Ok(())
+}
+
+/// Copies local `file` into the directory of remote workspace `name`, or of the currently open one
+pub fn push_file(name: Option<String>, file: String) -> Result<()> {
+    let workspace = select(name)?;
+    let ssh = remote_ssh(&workspace, "push-file")?;
+    let dir = match workspace.dir.as_str() {
+        "" => ".",
+        dir => dir,
+    };
+    let target = ssh::remote_file(ssh, dir);
+    scp(&workspace, ssh, &file, &target)?;
+    eprintln!("INFO copied {file:?} to {target:?}");
+    Ok(())
+}
+
+/// Copies `path` from remote workspace `name`, or the currently open one, into the current
+/// directory
+///
+/// Relative paths are relative to the workspace directory.
+pub fn pull_file(name: Option<String>, path: String) -> Result<()> {
+    let workspace = select(name)?;
+    let ssh = remote_ssh(&workspace, "pull-file")?;
+    let source = ssh::remote_file(ssh, &remote_path(&workspace, &path));
+    scp(&workspace, ssh, &source, ".")?;
+    eprintln!("INFO copied {source:?} to the current directory");
+    Ok(())
+}
+
+/// Prints changes to `path` in remote workspace `name`, or the currently open one, as they happen,
+/// also as desktop notifications if `notify`
+///
+/// Relative paths are relative to the workspace directory. Changes are watched with `inotifywait`
+/// on the remote host until it exits or is interrupted.
+pub fn watch_remote(name: Option<String>, path: String, notify: bool) -> Result<()> {
+    let workspace = select(name)?;
+    let ssh = remote_ssh(&workspace, "watch-remote")?;
+    let script = [
+        format!("cd {} || exit", shell::quote_dir(&workspace.dir)),
+        "command -v inotifywait >/dev/null 2>&1 || \
+         { echo 'inotifywait is not installed, install inotify-tools' >&2; exit 127; }"
+            .to_owned(),
+        format!(
+            "exec inotifywait -m -r -q -e modify,create,delete,move --format '%e %w%f' -- {}",
+            shell::quote(&path),
+        ),
+    ]
+    .join("\n");
+    // Remote login shells aren't necessarily POSIX shells.
+    let mut command = ssh::background(ssh, workspace.timeout)?;
+    command
+        .arg(format!("sh -c {}", shell::quote(&script)))
+        .stdout(Stdio::piped());
+    let Some(mut child) = runner::spawn(&mut command).context("spawn ssh")? else {
+        return Ok(());
+    };
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let title = format!("workspace {}", workspace.name);
+    let mut notify = notify;
+    for line in BufReader::new(stdout).lines() {
+        let line = line.context("reading changes from ssh")?;
+        println!("{line}");
+        if !notify {
+            continue;
+        }
+        let notification = runner::status(Command::new("notify-send").args([&title, &line]));
+        if let Err(err) = notification {
+            eprintln!("WARN not showing notifications anymore, running notify-send failed: {err}");
+            notify = false;
+        }
+    }
+    let status = child.wait().context("waiting for ssh")?;
+    ensure!(
+        status.success(),
+        "watching {path:?} on {:?} failed with {status}",
+        ssh.host,
+    );
+    Ok(())
+}
+
+/// Returns the ssh settings of `workspace`, failing for local workspaces as `command` only works
+/// with remote ones
+fn remote_ssh<'a>(workspace: &'a Workspace, command: &str) -> Result<&'a workspace::Ssh> {
+    let name = &workspace.name;
+    workspace
+        .ssh
+        .as_ref()
+        .with_context(|| format!("{command} only works with remote workspaces, {name:?} is local"))
+}
+
+/// Returns remote `path` relative to the directory of `workspace`, for `scp`
+fn remote_path(workspace: &Workspace, path: &str) -> String {
+    match workspace.dir.as_str() {
+        _ if path.starts_with('/') => path.to_owned(),
+        "" => path.to_owned(),
+        dir => format!("{}/{path}", dir.trim_end_matches('/')),
+    }
+}
+
+/// Copies `source` to `target` with `scp`, one of them on the host of `workspace`
+///
+/// Directories are copied recursively. `scp` shows its own progress unless progress indicators
+/// are hidden.
+fn scp(workspace: &Workspace, ssh: &workspace::Ssh, source: &str, target: &str) -> Result<()> {
+    let mut command = ssh::scp(ssh, workspace.timeout)?;
+    command.arg("-r");
+    if !progress::visible() {
+        command.arg("-q");
+    }
+    command.args([source, target]);
+    let status = runner::status(&mut command).context("run scp")?;
+    ensure!(status.success(), "copying {source:?} to {target:?} failed");
+    Ok(())
+}
+
+/// Returns the Git UI command of `workspace`
+///
+/// The workspace's `git.ui` takes precedence over the global one, `lazygit` is used if neither is
+/// set.
+fn git_ui_command(workspace: &Workspace) -> Result<String> {
+    let ui = workspace.git.as_ref().and_then(|git| git.ui.clone());
+    let ui = match ui {
+        Some(ui) => Some(ui),
+        None => config::read()?.and_then(|config| config.git?.ui),
+    };
+    match ui {
+        Some(ui) => template::expand(&ui, workspace).context("expanding git ui command"),
+        None => Ok(DEFAULT_GIT_UI.to_owned()),
+    }
+}
+
+/// Returns the window of the Git UI of `workspace`, which gets the variables in `env` and `secrets`
+fn git_ui_window(
+    workspace: &Workspace,
+    env: Vec<(String, String)>,
+    secrets: &[(String, String)],
+) -> Result<Window> {
+    let dir = &workspace.dir;
+    let ui_cmd = git_ui_command(workspace)?;
+    Ok(if let Some(ssh) = &workspace.ssh {
+        let script = remote_program_script(workspace, &ui_cmd)?;
+        Window {
+            title: Some(format!("{}: {ui_cmd} {dir}", ssh.host)),
+            ..ssh::session(ssh, workspace.timeout, &env, secrets, &script)?
+        }
+    } else {
+        Window {
+            title: Some(format!("{ui_cmd} {dir}")),
+            dir: Some(workspace::canonicalize(&workspace.local_dir()?)?),
+            env,
+            secrets: secrets.to_vec(),
+            ..Window::new(ui_cmd)
+        }
+    })
+}
+
+/// Returns the tabs of `workspace` in a kitty session
+///
+/// The windows of a tmuxp `tmux.layout` become tabs with a window for each pane. Otherwise a
+/// single tab holds the terminal and the editor, editors without a terminal window aren't part of
+/// the session.
+///
+/// Session files are written to disk, so the windows only get the workspace variables and not its
+/// secrets.
+fn kitty_tabs(workspace: &Workspace) -> Result<Vec<kitty::Tab>> {
+    let env = hooks::workspace_env(workspace)?;
+    if let Some(session) = tmux::tmuxp_layout(workspace)? {
+        return session
+            .windows
+            .iter()
+            .map(|window| {
+                let windows = session
+                    .panes(window)
+                    .iter()
+                    .map(|commands| terminal_window(workspace, commands, env.clone(), &[]))
+                    .collect::<Result<_>>()?;
+                Ok(kitty::Tab {
+                    title: window.window_name.clone(),
+                    layout: window.layout.as_deref().and_then(kitty::layout),
+                    windows,
+                })
+            })
+            .collect();
+    }
+    let mut windows = vec![terminal_window(workspace, &[], env.clone(), &[])?];
+    if editor_in_terminal(workspace) {
+        windows.push(editor_window(workspace, env, &[], &editor_args(None))?);
+    }
+    Ok(vec![kitty::Tab {
+        title: Some(workspace.name.clone()),
+        layout: None,
+        windows,
+    }])
+}
+
+/// Prints a kitty session file opening workspace `name`
+pub fn export_kitty_session(name: String) -> Result<()> {
+    let workspace = workspace::read(&name).context("reading workspace definition")?;
+    print!("{}", kitty::session(&[kitty_tabs(&workspace)?]));
+    Ok(())
+}
+
+/// Prints a WezTerm Lua module opening workspace `name`, see [`wezterm`]
+///
+/// Like session files it only sets the workspace variables and not the secrets.
+pub fn export_wezterm(name: String) -> Result<()> {
+    let workspace = workspace::read(&name).context("reading workspace definition")?;
+    let env = hooks::workspace_env(&workspace)?;
+    let label = |kind: &str| format!("{name}: {kind}");
+    let (terminal, editor) = match &workspace.ssh {
+        Some(ssh) => {
+            let domain = Some(wezterm::ssh_domain(&ssh.host));
+            let script = remote_terminal_script(&workspace, &[], &env, &[])?;
+            let terminal = wezterm::Spawn {
+                label: label("shell"),
+                args: wezterm::remote_args(&env, &script),
+                cwd: None,
+                env: Vec::new(),
+                domain: domain.clone(),
+            };
+            let editor = match editor_in_terminal(&workspace) {
+                false => None,
+                true => {
+                    let editor_cmd = editor_command(&workspace)?;
+                    let script = remote_editor_script(&workspace, &editor_cmd, &editor_args(None))?;
+                    Some(wezterm::Spawn {
+                        label: label("editor"),
+                        args: wezterm::remote_args(&env, &script),
+                        cwd: None,
+                        env: Vec::new(),
+                        domain,
+                    })
+                }
+            };
+            (terminal, editor)
+        }
+        None => {
+            let spawn = |kind: &str, window: Window| wezterm::Spawn {
+                label: label(kind),
+                args: std::iter::once(window.program).chain(window.args).collect(),
+                cwd: window.dir.map(|dir| dir.to_string_lossy().into_owned()),
+                env: window.env,
+                domain: None,
+            };
+            let terminal = spawn("shell", terminal_window(&workspace, &[], env.clone(), &[])?);
+            let editor = match editor_in_terminal(&workspace) {
+                false => None,
+                true => Some(spawn(
+                    "editor",
+                    editor_window(&workspace, env, &[], &editor_args(None))?,
+                )),
+            };
+            (terminal, editor)
+        }
+    };
+    print!("{}", wezterm::module(&name, &terminal, editor.as_ref()));
+    Ok(())
+}
+
+/// Opens `workspaces` in a single kitty instance with an OS window for each, see [`kitty`]
+///
+/// `name` is what they were started as. All workspaces record the same window, so closing any of
+/// them closes the whole session.
+fn start_session(name: &str, workspaces: &[Workspace]) -> Result<()> {
+    let mut os_windows = Vec::new();
+    for workspace in workspaces {
+        hooks::run(workspace, hooks::Event::Terminal)?;
+        // Editors outside the terminal run the hook when they're opened below, and a tmuxp layout
+        // doesn't open one at all.
+        if editor_in_terminal(workspace) && tmux::tmuxp_layout(workspace)?.is_none() {
+            hooks::run(workspace, hooks::Event::Editor)?;
+        }
+        let tabs = kitty_tabs(workspace)
+            .with_context(|| format!("building kitty session of workspace {:?}", workspace.name))?;
+        os_windows.push(tabs);
+        if !editor_in_terminal(workspace) {
+            if let Some((pid, program)) = open_editor(workspace, false, None)? {
+                windows::record(&workspace.name, pid, &program)
+                    .context("recording opened window")?;
+            }
+        }
+    }
+    let path = kitty::write(name, &kitty::session(&os_windows))?;
+    let mut command = Command::new("kitty");
+    command
+        .arg("--session")
+        .arg(path)
+        .envs(kitty::secrets(&os_windows));
+    let Some(child) = runner::spawn(&mut command).context("spawn kitty")? else {
+        return Ok(());
+    };
+    for workspace in workspaces {
+        let (pid, program) = started(workspace, (child.id(), "kitty".to_owned()));
+        windows::record(&workspace.name, pid, &program).context("recording opened window")?;
+    }
+    Ok(())
+}
+
+/// Opens workspace `name`, or every member of meta-workspace `@name`, each with a terminal and an
+/// editor window
+///
+/// Dependencies are started first unless `no_deps`. The first workspace of `name` becomes the
+/// currently open one. With `via_session` all windows are opened by a single kitty session.
+pub fn start(name: String, no_deps: bool, via_session: bool) -> Result<()> {
+    let requested = meta::resolve(&name)?;
+    let workspaces = if no_deps {
+        requested
+            .iter()
+            .map(|name| workspace::read(name).context("reading workspace definition"))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        meta::with_dependencies(&requested)?
+    };
+    for workspace in &workspaces {
+        let name = &workspace.name;
+        let mut transaction = Transaction::default();
+        history::record(&mut transaction, name);
+        if requested.first() == Some(name) {
+            session::open(&mut transaction, name, false)?;
+        }
+        transaction
+            .commit()
+            .context("setting currently open workspace")?;
+        events::emit(events::Event::WorkspaceOpened { workspace: name });
+        hooks::run(workspace, hooks::Event::Open)?;
+    }
+    if via_session {
+        return start_session(&name, &workspaces);
+    }
+
+    // Setting up a remote window can take a while, open all of them at once.
+    let bar = progress::bar(2 * workspaces.len() as u64, "opening windows");
+    let results = thread::scope(|scope| {
+        let handles = workspaces
+            .iter()
+            .flat_map(|workspace| {
+                [
+                    ("terminal", scope.spawn(|| open_terminal(workspace, false))),
+                    (
+                        "editor",
+                        scope.spawn(|| open_editor(workspace, false, None)),
+                    ),
+                ]
+                .map(|(kind, handle)| (&workspace.name, kind, handle))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|(name, kind, handle)| {
+                let result = handle.join().expect("window thread panicked");
+                bar.inc(1);
+                (name, kind, result)
+            })
+            .collect::<Vec<_>>()
+    });
+    bar.finish_and_clear();
+
+    let mut failed = 0;
+    for (name, kind, result) in results {
+        match result {
+            Ok(Some((pid, program))) => {
+                windows::record(name, pid, &program).context("recording opened window")?;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("ERR  opening {kind} of workspace {name:?}: {err:#}");
+                failed += 1;
+            }
+        }
+    }
+    ensure!(failed == 0, "{failed} window(s) failed to open");
+    Ok(())
+}
+
+/// Closes the windows `start` opened for workspace `name`, or every member of meta-workspace
+/// `@name`
+///
+/// A closed workspace which is currently open is no longer.
+pub fn close(name: String) -> Result<()> {
+    let names = match name.strip_prefix(meta::PREFIX) {
+        Some(meta) => meta::read(meta)?.workspaces,
+        None => vec![name],
+    };
+    for name in &names {
+        let mut transaction = Transaction::default();
+        let closed = windows::close(&mut transaction, name).context("closing windows")?;
+        session::forget(&mut transaction, name)?;
+        transaction
+            .commit()
+            .context("unsetting currently open workspace")?;
+        eprintln!("INFO closed {closed} window(s) of {name:?}");
+        events::emit(events::Event::WorkspaceClosed { workspace: name });
+        // Whatever happened to the workspace, its windows are closed by now.
+        match workspace::read(name) {
+            Ok(workspace) => hooks::run(&workspace, hooks::Event::Close)?,
+            Err(err) => eprintln!("WARN not running close hooks of {name:?}: {err:#}"),
+        }
+    }
+    Ok(())
+}
+
+pub fn secret_set(name: String, key: String) -> Result<()> {
+    select(Some(name.clone()))?;
+    secret::check_key(&key)?;
+    let value = if io::stdin().is_terminal() {
+        ensure!(
+            prompt::interactive(),
+            "can't prompt for the secret with --no-input, pass it on stdin",
+        );
+        rpassword::prompt_password(format!("value of {key}: "))
+            .context("reading secret from the terminal")?
+    } else {
+        let mut value = String::new();
+        io::stdin()
+            .read_to_string(&mut value)
+            .context("reading secret from stdin")?;
+        // Allow `echo value |` without the newline ending up in the secret.
+        value.strip_suffix('\n').unwrap_or(&value).to_owned()
+    };
+    secret::set(&name, &key, &value)?;
+    println!("set secret {key:?} of workspace {name:?}");
+    Ok(())
+}
+
+/// Prints the project config of workspace `name`, or of the current workspace, and whether it's
+/// allowed
+pub fn project_show(name: Option<String>) -> Result<()> {
+    let workspace = select(name)?;
+    let (path, buf) = project_contents(&workspace)?;
+    match project::is_allowed(&path, &buf)? {
+        true => eprintln!("INFO {path:?} is allowed"),
+        false => eprintln!("INFO {path:?} is not allowed"),
+    }
+    print!("{buf}");
+    Ok(())
+}
+
+/// Allows the project config of workspace `name`, or of the current workspace, as it is now
+pub fn project_allow(name: Option<String>) -> Result<()> {
+    let workspace = select(name)?;
+    let (path, buf) = project_contents(&workspace)?;
+    project::allow(&path, &buf)?;
+    println!("allowed project config {path:?}");
+    Ok(())
+}
+
+/// Stops using the project config of workspace `name`, or of the current workspace
+pub fn project_deny(name: Option<String>) -> Result<()> {
+    let workspace = select(name)?;
+    let (path, _) = project_contents(&workspace)?;
+    match project::deny(&path)? {
+        true => println!("denied project config {path:?}"),
+        false => println!("project config {path:?} wasn't allowed"),
+    }
+    Ok(())
+}
+
+/// Returns the path and contents of the project config of `workspace`, failing if there's none
+fn project_contents(workspace: &Workspace) -> Result<(PathBuf, String)> {
+    project::contents(workspace)?.with_context(|| {
+        format!(
+            "workspace {:?} has no {}, or it wasn't fetched yet",
+            workspace.name,
+            project::FILE_NAME,
+        )
+    })
+}
+
+pub fn secret_list(name: String) -> Result<()> {
+    select(Some(name.clone()))?;
+    for key in secret::list(&name)? {
+        println!("{key}");
+    }
+    Ok(())
+}
+
+pub fn secret_rm(name: String, key: String) -> Result<()> {
+    select(Some(name.clone()))?;
+    secret::remove(&name, &key)?;
+    println!("removed secret {key:?} of workspace {name:?}");
+    Ok(())
+}
+
+/// Returns the user's editor for files, `$VISUAL` or `$EDITOR`
+fn file_editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .find_map(|var| {
+            env::var(var)
+                .ok()
+                .filter(|editor| !editor.trim().is_empty())
+        })
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_owned())
+}
+
+/// Opens the global config in `$VISUAL` or `$EDITOR` and saves it once it's valid
+pub fn config_edit() -> Result<()> {
+    config::edit(&file_editor())
+}
+
+/// Prints the value of `key` in the global config, strings without quotes
+pub fn config_get(key: String) -> Result<()> {
+    match config::get(&key)? {
+        Some(toml::Value::String(value)) => println!("{value}"),
+        Some(value) => println!("{value}"),
+        None => bail!("{key:?} is not set in the config"),
+    }
+    Ok(())
+}
+
+/// Sets `key` in the global config, `value` is parsed like with [`set`]
+pub fn config_set(key: String, value: String) -> Result<()> {
+    let value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(value));
+    config::set(&key, value).with_context(|| format!("setting {key:?} in the config"))?;
+    println!("set {key:?} in the config");
+    Ok(())
+}
+
+/// Prints the global settings as a JSON object, including the defaults the config doesn't set
+///
+/// Defaults are those of local workspaces, remote ones detect their shell and editor on the host.
+/// Settings without a default are shown as `null`. With `origin` each value is an object with the
+/// `value` and its [`Origin`], like with `cat --resolve --origin`.
+pub fn config_show(origin: bool) -> Result<()> {
+    let config = config::read()?;
+    let mut settings = BTreeMap::new();
+    let file = serde_json::to_value(&config).context("serializing config")?;
+    for (key, value) in config_settings(file) {
+        if !value.is_null() {
+            settings.insert(key, (value, Origin::Config));
+        }
+    }
+    let (defaults, detected) = config_defaults(config.as_ref());
+    let defaults = serde_json::to_value(&defaults).context("serializing config defaults")?;
+    for (key, value) in config_settings(defaults) {
+        let from = match detected.contains(&key.as_str()) {
+            true => Origin::Detected,
+            false => Origin::Default,
+        };
+        settings.entry(key).or_insert((value, from));
+    }
+
+    let mut shown = serde_json::Map::new();
+    for (key, (value, from)) in settings {
+        let value = match origin {
+            true => json!({ "value": value, "origin": from.as_str() }),
+            false => value,
+        };
+        match key.split_once('.') {
+            Some((table, key)) => {
+                shown.entry(table).or_insert_with(|| json!({}))[key] = value;
+            }
+            None => {
+                shown.insert(key, value);
+            }
+        }
+    }
+    println!("{}", serde_json::Value::Object(shown));
+    Ok(())
+}
+
+/// Returns the config used without a config file, and the keys of the settings in it which were
+/// detected rather than defaults
+///
+/// Terminals are only detected when `config` doesn't set one.
+fn config_defaults(config: Option<&config::Config>) -> (config::Config, Vec<&'static str>) {
+    let mut detected = Vec::new();
+    let shell = match launch::login_shell() {
+        Some(shell) => {
+            detected.push("shell.command");
+            shell
+        }
+        None => launch::DEFAULT_SHELL.to_owned(),
+    };
+    let terminal = config.and_then(|config| config.terminal.as_ref());
+    let configured =
+        terminal.is_some_and(|terminal| terminal.backend.is_some() || terminal.command.is_some());
+    let terminal = match launch::installed().filter(|_| !configured) {
+        Some(launch::Installed::Backend(backend)) => {
+            detected.push("terminal.backend");
+            config::Terminal {
+                backend: Some(backend),
+                command: None,
+                args: None,
+            }
+        }
+        Some(launch::Installed::Custom(program, args)) => {
+            detected.extend(["terminal.command", "terminal.args"]);
+            config::Terminal {
+                backend: None,
+                command: Some(program),
+                args: Some(args),
+            }
+        }
+        None => config::Terminal {
+            backend: None,
+            command: None,
+            args: None,
+        },
+    };
+    let defaults = config::Config {
+        version: None,
+        editor: Some(workspace::Editor {
+            command: DEFAULT_EDITOR.to_owned(),
+            title: None,
+            gui: None,
+            kind: None,
+            product: None,
+        }),
+        shell: Some(workspace::Shell {
+            command: Some(shell),
+            title: None,
+            login: Some(false),
+        }),
+        list: Some(config::List {
+            sort: Some(Sort::Name),
+            follow_symlinks: Some(true),
+        }),
+        age: None,
+        ssh: Some(config::Ssh {
+            term: Some(ssh::DEFAULT_TERM.to_owned()),
+            cache_ttl: Some(detect::DEFAULT_TTL.as_secs()),
+            ..Default::default()
+        }),
+        terminal: Some(terminal),
+        hooks: None,
+        git: Some(config::Git {
+            ui: Some(DEFAULT_GIT_UI.to_owned()),
+        }),
+        timeout: Some(runtime::DEFAULT_TIMEOUT.as_secs()),
+        assume_yes: Some(false),
+        default_group: None,
+        // Terminals attach to tmux if the workspace has a `[tmux]` table.
+        multiplexer: None,
+    };
+    (defaults, detected)
+}
+
+/// Returns the settings of serialized config `config` keyed like `list.sort`
+fn config_settings(config: serde_json::Value) -> Vec<(String, serde_json::Value)> {
+    let serde_json::Value::Object(config) = config else {
+        return Vec::new();
+    };
+    let mut settings = Vec::new();
+    for (key, value) in config {
+        match value {
+            serde_json::Value::Object(table) => {
+                for (nested, value) in table {
+                    settings.push((format!("{key}.{nested}"), value));
+                }
+            }
+            value => settings.push((key, value)),
+        }
+    }
+    settings
+}
+
+/// Forgets the detected properties of remote host `host`, or of all hosts together with the
+/// project configs read from them
+pub fn cache_clear(host: Option<String>) -> Result<()> {
+    detect::clear(host.as_deref()).context("clearing cached remote host properties")?;
+    if host.is_none() {
+        cache::remove_projects().context("clearing cached remote project configs")?;
+    }
+    Ok(())
+}
+
+/// Removes state referring to workspaces, windows and hosts which no longer exist
+///
+/// That's history entries and Neovim sockets of deleted workspaces, windows whose process exited,
+/// the currently open and the scratch workspace when they're gone, the currently open workspaces of
+/// closed terminals, and the detected properties and profiles of hosts no workspace connects to.
+/// With `dry_run` it's only listed.
+pub fn gc(dry_run: bool) -> Result<()> {
+    let verb = if dry_run { "would remove" } else { "removed" };
+    let scratch = cache::read(Key::Scratch).ok();
+    let scratch_exists = scratch.as_ref().is_some_and(|dir| Path::new(dir).is_dir());
+    let exists = |name: &str| match name {
+        "~" => true,
+        workspace::SCRATCH => scratch_exists,
+        // Keep what can't be checked.
+        name => workspace::exists(name).unwrap_or(true),
+    };
+    let mut transaction = Transaction::default();
+
+    let removed = history::retain(&mut transaction, |entry| Ok(exists(&entry.name)))
+        .context("cleaning up workspace history")?;
+    let mut counts = BTreeMap::<String, usize>::new();
+    for entry in removed {
+        *counts.entry(entry.name).or_default() += 1;
+    }
+    for (name, count) in counts {
+        println!("{verb} {count} history record(s) of deleted workspace {name:?}");
+    }
+
+    let removed = windows::retain(&mut transaction, |entry| {
+        process::is_running(entry.pid, &entry.program)
+    })
+    .context("cleaning up windows")?;
+    for entry in removed {
+        println!(
+            "{verb} window of {:?}, its process {} ({}) exited",
+            entry.name, entry.pid, entry.program,
+        );
+    }
+
+    for name in cache::nvim_names()? {
+        let key = Key::Nvim(name.clone());
+        let socket = cache::read(key.clone())?;
+        if !exists(&name) {
+            println!("{verb} nvim socket of deleted workspace {name:?}");
+        } else if !cfg!(windows) && !Path::new(&socket).exists() {
+            println!("{verb} nvim socket {socket:?} of {name:?}, nvim is not listening anymore");
+        } else {
+            continue;
+        }
+        transaction.remove(key);
+    }
+
+    for name in cache::status_names()? {
+        if !exists(&name) {
+            println!("{verb} cached status of deleted workspace {name:?}");
+            transaction.remove(Key::Status(name));
+        }
+    }
+
+    for name in cache::project_names()? {
+        if !exists(&name) {
+            println!("{verb} cached project config of deleted workspace {name:?}");
+            transaction.remove(Key::Project(name));
+        }
+    }
+
+    for (key, current) in session::all()? {
+        if let Key::Session(session) = &key {
+            if session::ended(session) {
+                println!("{verb} currently open workspace of ended session {session:?}");
+                transaction.remove(key);
+                continue;
+            }
+        }
+        if exists(&current) {
+            continue;
+        }
+        match &key {
+            Key::Session(session) => println!(
+                "{verb} deleted workspace {current:?} as the currently open one of session \
+                 {session:?}"
+            ),
+            _ => println!("{verb} deleted workspace {current:?} as the currently open one"),
+        }
+        transaction.remove(key);
+    }
+    if let (Some(dir), false) = (&scratch, scratch_exists) {
+        println!("{verb} scratch workspace in deleted directory {dir:?}");
+        transaction.remove(Key::Scratch);
+    }
+
+    let mut profiles = Vec::new();
+    if let Some(used) = used_hosts() {
+        for host in cache::hosts()? {
+            if !used.contains(&host) {
+                println!("{verb} detected properties of unused host {host:?}");
+                transaction.remove(Key::Host(host));
+            }
+        }
+        for host in detect::profile_hosts()? {
+            if !used.contains(&host) {
+                println!("{verb} profile of unused host {host:?}");
+                profiles.push(host);
+            }
+        }
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+    transaction.commit().context("removing stale state")?;
+    for host in profiles {
+        detect::remove_profile(&host)?;
+    }
+    Ok(())
+}
+
+/// Returns the hosts workspaces connect to
+///
+/// Returns `None` if that can't be known because a workspace can't be read, or is encrypted and
+/// reading it would ask for its passphrase.
+fn used_hosts() -> Option<HashSet<String>> {
+    let mut hosts = HashSet::new();
+    for name in workspace::list() {
+        if workspace::is_encrypted(&name).unwrap_or(true) {
+            eprintln!("INFO keeping all host state, encrypted workspace {name:?} may use any host");
+            return None;
+        }
+        match workspace::read_own(&name) {
+            Ok(workspace) => hosts.extend(workspace.ssh.map(|ssh| ssh.host)),
+            Err(err) => {
+                eprintln!("WARN keeping all host state, reading workspace {name:?}: {err:#}");
+                return None;
+            }
+        }
+    }
+    Some(hosts)
+}
+
+/// Prints the capability profile of remote host `host`, detecting it first if it's not known or
+/// `refresh`
+pub fn host_info(host: String, refresh: bool) -> Result<()> {
+    let profile = match detect::profile(&host)? {
+        Some(profile) if !refresh => profile,
+        _ => {
+            detect::clear(Some(&host))?;
+            let ssh = workspace::Ssh {
+                host: host.clone(),
+                ..Default::default()
+            };
+            detect::host(&ssh, None)?
+        }
+    };
+    if let Some(Porcelain::V1) = porcelain::version() {
+        let list = |programs: Option<Vec<String>>| programs.map(|programs| programs.join(" "));
+        return porcelain::print_pairs(&[
+            ("host", host),
+            ("os", profile.os.unwrap_or_default()),
+            ("arch", profile.arch.unwrap_or_default()),
+            ("shell", profile.shell.unwrap_or_default()),
+            ("editor", profile.editor.unwrap_or_default()),
+            ("editors", list(profile.editors).unwrap_or_default()),
+            ("tools", list(profile.tools).unwrap_or_default()),
+        ]);
+    }
+    let unknown = || "unknown".to_owned();
+    let list = |programs: Option<Vec<String>>| match programs {
+        Some(programs) if programs.is_empty() => "none".to_owned(),
+        Some(programs) => programs.join(", "),
+        None => unknown(),
+    };
+    println!("{:<8} {host}", "host:");
+    println!("{:<8} {}", "os:", profile.os.unwrap_or_else(unknown));
+    println!("{:<8} {}", "arch:", profile.arch.unwrap_or_else(unknown));
+    println!("{:<8} {}", "shell:", profile.shell.unwrap_or_else(unknown));
+    println!(
+        "{:<8} {}",
+        "editor:",
+        profile.editor.unwrap_or_else(unknown)
+    );
+    println!("{:<8} {}", "editors:", list(profile.editors));
+    println!("{:<8} {}", "tools:", list(profile.tools));
+    Ok(())
+}
+
+/// Prints the recorded events as JSON lines, waiting for new ones if `follow`
+pub fn events(follow: bool) -> Result<()> {
+    events::print(follow)
+}
+
+/// Runs plugin subcommand `args[0]`, the program `wsctl-<name>` from `PATH`, with the rest of
+/// `args`
+///
+/// The plugin gets the environment described in [`hooks`] for the currently open workspace.
+pub fn plugin(args: Vec<String>) -> Result<()> {
+    let (name, args) = args.split_first().context("missing subcommand")?;
+    let program = format!("wsctl-{name}");
+    let path = process::find_program(&program)
+        .with_context(|| format!("unknown subcommand {name:?}, no {program:?} found in PATH"))?;
+    let workspace = workspace::current().ok();
+    let env = hooks::env(workspace.as_ref(), "plugin")?;
+    let mut command = Command::new(path);
+    command.args(args).envs(env);
+    let name = workspace.as_ref().map(|workspace| &*workspace.name);
+    let status = events::status(&mut command, name).with_context(|| format!("run {program}"))?;
+    exit_like(status)
+}
@@ -0,0 +1,159 @@
+//! Opinionated checks of workspace definitions
+//!
+//! A definition which parses can still be wrong in ways that only show once it's used, like an
+//! editor which isn't installed, or that never show, like two workspaces for the same directory.
+//! `lint` reports these as problems of the workspaces they're found in.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::workspace::{EditorKind, Workspace};
+use crate::{index, process, template, vscode};
+
+/// Something suspicious about a workspace definition
+#[derive(Debug)]
+pub struct Problem {
+    /// Name of the workspace
+    pub name: String,
+
+    /// What is suspicious about it
+    pub message: String,
+}
+
+impl Problem {
+    fn new(name: &str, message: String) -> Problem {
+        Problem {
+            name: name.to_owned(),
+            message,
+        }
+    }
+}
+
+/// Checks `workspaces` one by one and against each other
+///
+/// Problems are ordered by workspace, in the order of `workspaces`.
+pub fn check(workspaces: &[Workspace]) -> Vec<Problem> {
+    let home = dirs::home_dir();
+    let mut problems = Vec::new();
+    for workspace in workspaces {
+        if let Some(home) = &home {
+            problems.extend(foreign_home(workspace, home));
+            problems.extend(missing_identity_file(workspace, home));
+        }
+        problems.extend(missing_editor(workspace));
+    }
+    problems.extend(duplicate_dirs(workspaces));
+    problems.extend(case_collisions(workspaces));
+    // Stable, so each workspace's problems stay in the order they were checked.
+    let order = workspaces
+        .iter()
+        .enumerate()
+        .map(|(index, workspace)| (workspace.name.as_str(), index))
+        .collect::<HashMap<_, _>>();
+    problems.sort_by_key(|problem| order.get(problem.name.as_str()).copied());
+    problems
+}
+
+/// Flags a local `dir` in the home directory of another user
+fn foreign_home(workspace: &Workspace, home: &Path) -> Option<Problem> {
+    if workspace.ssh.is_some() {
+        return None;
+    }
+    let dir = Path::new(&workspace.dir);
+    // Homes directly in `/`, like `/root`, don't tell where other users' homes are.
+    let homes = home.parent().filter(|homes| homes.parent().is_some())?;
+    let user = dir.strip_prefix(homes).ok()?.components().next()?;
+    if dir.starts_with(home) {
+        return None;
+    }
+    let user = user.as_os_str().to_string_lossy();
+    Some(Problem::new(
+        &workspace.name,
+        format!("dir {dir:?} is in the home directory of user {user:?}"),
+    ))
+}
+
+/// Flags an ssh `identity_file` which doesn't exist
+///
+/// Paths starting with `~/` and relative paths are relative to the home directory.
+fn missing_identity_file(workspace: &Workspace, home: &Path) -> Option<Problem> {
+    let identity_file = workspace.ssh.as_ref()?.identity_file.as_ref()?;
+    let path = home.join(identity_file.strip_prefix("~/").unwrap_or(identity_file));
+    if path.exists() {
+        return None;
+    }
+    Some(Problem::new(
+        &workspace.name,
+        format!("ssh identity file {identity_file:?} does not exist"),
+    ))
+}
+
+/// Flags an editor command which isn't on `PATH`
+///
+/// Only editors which run locally are checked, those of remote workspaces usually run on the
+/// remote host.
+fn missing_editor(workspace: &Workspace) -> Option<Problem> {
+    let editor = workspace.editor.as_ref()?;
+    let command = match template::expand(&editor.command, workspace) {
+        Ok(command) => command,
+        Err(err) => {
+            return Some(Problem::new(
+                &workspace.name,
+                format!("editor command {:?} is invalid: {err:#}", editor.command),
+            ));
+        }
+    };
+    let runs_locally = workspace.ssh.is_none()
+        || vscode::is_code(&command)
+        || editor.kind == Some(EditorKind::JetbrainsGateway);
+    let program = command.split_whitespace().next()?;
+    if !runs_locally || process::find_program(program).is_some() {
+        return None;
+    }
+    Some(Problem::new(
+        &workspace.name,
+        format!("editor {program:?} is not on PATH"),
+    ))
+}
+
+/// Flags workspaces with the same directory on the same host
+fn duplicate_dirs(workspaces: &[Workspace]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for names in index::build(workspaces)
+        .values()
+        .filter(|names| names.len() > 1)
+    {
+        for name in names {
+            let others = names.iter().filter(|other| *other != name);
+            let others = others.map(|other| format!("{other:?}")).collect::<Vec<_>>();
+            problems.push(Problem::new(
+                name,
+                format!("dir is also the dir of {}", others.join(", ")),
+            ));
+        }
+    }
+    problems
+}
+
+/// Flags workspace names which differ only by case, they collide on case-insensitive file systems
+fn case_collisions(workspaces: &[Workspace]) -> Vec<Problem> {
+    let mut by_name = HashMap::<_, Vec<&str>>::new();
+    for workspace in workspaces {
+        by_name
+            .entry(workspace.name.to_lowercase())
+            .or_default()
+            .push(&workspace.name);
+    }
+    let mut problems = Vec::new();
+    for names in by_name.values().filter(|names| names.len() > 1) {
+        for name in names {
+            let others = names.iter().filter(|other| *other != name);
+            let others = others.map(|other| format!("{other:?}")).collect::<Vec<_>>();
+            problems.push(Problem::new(
+                name,
+                format!("name differs only by case from {}", others.join(", ")),
+            ));
+        }
+    }
+    problems
+}
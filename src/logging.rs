@@ -0,0 +1,82 @@
+//! Diagnostic logging for debugging `workspacectl` itself
+//!
+//! Off unless `--log-level` is given. Each command runs in a `command` span and every program run
+//! through the [`runner`](crate::runner) is logged with its arguments and how it ended. Logs go to
+//! stderr, or with `--log-file` are appended to `workspacectl.log` in the state directory, which
+//! helps with commands started in the background where stderr goes nowhere.
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+
+use crate::paths;
+
+/// Most verbose level of logged events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> LevelFilter {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Format of logged events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// One human readable line per event
+    Text,
+
+    /// One JSON object per line
+    Json,
+}
+
+/// Starts logging events up to `level` in `format`, to the log file if `to_file`
+///
+/// Nothing is logged without a `level`.
+pub fn init_logging(level: Option<LogLevel>, format: LogFormat, to_file: bool) -> Result<()> {
+    let Some(level) = level else {
+        return Ok(());
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::from(level))
+        .with_target(false);
+    if to_file {
+        let dir = paths::state_dir()?;
+        fs::create_dir_all(&dir).with_context(|| format!("creating directory {dir:?}"))?;
+        let path = dir.join("workspacectl.log");
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("opening log file {path:?}"))?;
+        let builder = builder.with_ansi(false).with_writer(Mutex::new(file));
+        match format {
+            LogFormat::Text => builder.init(),
+            LogFormat::Json => builder.json().init(),
+        }
+    } else {
+        let builder = builder.with_writer(io::stderr);
+        match format {
+            LogFormat::Text => builder.init(),
+            LogFormat::Json => builder.json().init(),
+        }
+    }
+    Ok(())
+}
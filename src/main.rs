@@ -1,7 +1,71 @@
 use clap::{Parser, Subcommand};
+use workspacectl::{Format, ListFormat, LogFormat, LogLevel, Porcelain, Sort};
 
 #[derive(Parser, Debug)]
 struct Opts {
+    /// Treat unknown keys in workspace and config files as errors
+    #[clap(long, global = true)]
+    strict: bool,
+
+    /// Ignore `.workspacectl.toml` project configs in workspace directories
+    #[clap(long, global = true)]
+    no_project_config: bool,
+
+    /// Don't show progress indicators
+    #[clap(long, short, global = true)]
+    quiet: bool,
+
+    /// Print stable output for scripts
+    ///
+    /// Records are lines of tab-separated fields which are never localized
+    /// or aligned. The format of a version never changes, `--porcelain`
+    /// alone means `v1`. Supported by `list`, `info` and `host info`.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "VERSION",
+        num_args = 0..=1,
+        default_missing_value = "v1",
+        require_equals = true,
+        verbatim_doc_comment
+    )]
+    porcelain: Option<Porcelain>,
+
+    /// Print the commands which would start programs instead of running them
+    ///
+    /// Covers terminals, editors, hooks, plugins and other programs run in
+    /// workspaces, one shell command line each. Commands which only query
+    /// something still run, and workspaces are still marked as opened or
+    /// closed.
+    #[clap(long, global = true, verbatim_doc_comment)]
+    print_cmd: bool,
+
+    /// Answer yes to all confirmations without asking
+    ///
+    /// Also set by `assume_yes = true` in the config.
+    #[clap(long, short, global = true, verbatim_doc_comment)]
+    yes: bool,
+
+    /// Never prompt, fail instead when input is needed
+    ///
+    /// Nothing is prompted for when stdin is not a terminal either.
+    #[clap(long, global = true, verbatim_doc_comment)]
+    no_input: bool,
+
+    /// Log diagnostics up to this level, nothing is logged by default
+    #[clap(long, global = true, value_enum)]
+    log_level: Option<LogLevel>,
+
+    /// Format of logged diagnostics
+    #[clap(long, global = true, value_enum, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Append logged diagnostics to `workspacectl.log` in the state
+    /// directory instead of printing them
+    #[clap(long, global = true, verbatim_doc_comment)]
+    log_file: bool,
+
     #[clap(subcommand)]
     cmd: Cmd,
 }
@@ -10,8 +74,8 @@ struct Opts {
 enum Cmd {
     /// Create a new workspace
     New {
-        /// SSH host
-        #[clap(long)]
+        /// SSH destination as `[user@]host[:port]`
+        #[clap(long, value_name = "DESTINATION")]
         ssh: Option<String>,
 
         /// Workspace path
@@ -19,47 +83,963 @@ enum Cmd {
         /// Path can be either relative or absolute. Relative paths are relative
         /// to the current working directory for local workspaces and to the
         /// remote `$HOME` for remote workspaces.
-        #[clap(default_value = ".", verbatim_doc_comment)]
-        path: String,
+        ///
+        /// Defaults to the current directory for local workspaces. Remote
+        /// workspaces without a PATH prompt for one with tab-completion of
+        /// remote directories.
+        #[clap(value_hint = clap::ValueHint::DirPath, verbatim_doc_comment)]
+        path: Option<String>,
+
+        /// Prompt for PATH with tab-completion of directories
+        #[clap(long, short, conflicts_with = "path")]
+        interactive: bool,
+
+        /// Clone a git repository into PATH first
+        ///
+        /// PATH defaults to the name of the repository, like with `git clone`.
+        /// Secrets the repository's `.workspacectl.toml` lists are asked for.
+        #[clap(
+            long,
+            value_name = "URL",
+            conflicts_with = "interactive",
+            verbatim_doc_comment
+        )]
+        clone: Option<String>,
 
         /// Name for the new workspace
         ///
         /// Defaults to the last segment of canonicalized PATH.
         name: Option<String>,
+
+        /// Group to create the workspace in, like `work/clients/acme`
+        ///
+        /// Defaults to `default_group` from the config, where `{{org}}` is the
+        /// owner of the directory's git remote `origin`.
+        #[clap(long, short, verbatim_doc_comment)]
+        group: Option<String>,
+
+        /// File format of the new workspace definition
+        #[clap(long, value_enum, default_value = "toml")]
+        format: Format,
+
+        /// Encrypt the definition with age
+        ///
+        /// The definition is encrypted to the identity configured by
+        /// `age.identity` and decrypted with it whenever it's read.
+        #[clap(long, verbatim_doc_comment)]
+        encrypt: bool,
     },
 
     /// List defined workspaces
-    List {},
+    List {
+        /// Only list workspaces matching a glob pattern
+        pattern: Option<String>,
+
+        /// Sort order
+        ///
+        /// Defaults to `list.sort` from the global config, or `name`.
+        #[clap(long, value_enum)]
+        sort: Option<Sort>,
+
+        /// Include archived workspaces
+        #[clap(long)]
+        all: bool,
+
+        /// Show the git status and reachability stored by `refresh`
+        #[clap(long, short)]
+        long: bool,
+
+        /// Output format
+        ///
+        /// `json` and `table` also show the directory and host of each
+        /// workspace, and mark the current one. Encrypted definitions aren't
+        /// decrypted, their directory and host are unknown.
+        #[clap(
+            long,
+            value_enum,
+            default_value = "plain",
+            conflicts_with = "long",
+            verbatim_doc_comment
+        )]
+        format: ListFormat,
+
+        /// Same as `--format json`
+        #[clap(long, conflicts_with_all = ["format", "long"])]
+        json: bool,
+    },
+
+    /// Move workspaces to a different name or group
+    #[clap(visible_alias = "rename")]
+    Move {
+        /// Workspace name or a glob pattern
+        ///
+        /// `*` and `?` don't match across groups, use `**` to match any number
+        /// of groups. Quote the pattern to prevent the shell from expanding it.
+        #[clap(verbatim_doc_comment)]
+        source: String,
+
+        /// New workspace name, or a group ending with `/`
+        ///
+        /// Workspaces moved into a group keep their name relative to the last
+        /// group in SOURCE which contains no glob metacharacters.
+        #[clap(verbatim_doc_comment)]
+        destination: String,
+
+        /// Only show which workspaces would be moved
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Modify locked workspaces too
+        #[clap(long)]
+        unlock: bool,
+    },
+
+    /// Delete workspace definitions
+    ///
+    /// Asks for confirmation first, once for all workspaces matching a glob
+    /// pattern. Only the definitions are deleted, the workspace directories
+    /// are left alone.
+    #[clap(verbatim_doc_comment)]
+    Delete {
+        /// Workspace name or a glob pattern
+        pattern: String,
+
+        /// Delete without asking for confirmation
+        #[clap(long, short)]
+        force: bool,
+
+        /// Only show which workspaces would be deleted
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Delete a locked workspace too
+        #[clap(long)]
+        unlock: bool,
+    },
+
+    /// Move workspaces into the archive, hiding them from `list`
+    Archive {
+        /// Workspace name or a glob pattern
+        pattern: String,
+
+        /// Only show which workspaces would be archived
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Modify locked workspaces too
+        #[clap(long)]
+        unlock: bool,
+    },
+
+    /// Move workspaces out of the archive
+    Unarchive {
+        /// Workspace name or a glob pattern, with or without the `archive/` group
+        pattern: String,
+
+        /// Only show which workspaces would be unarchived
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Modify locked workspaces too
+        #[clap(long)]
+        unlock: bool,
+    },
+
+    /// Lock workspaces against modification
+    Lock {
+        /// Workspace name or a glob pattern
+        pattern: String,
+    },
+
+    /// Unlock workspaces locked with `lock`
+    Unlock {
+        /// Workspace name or a glob pattern
+        pattern: String,
+    },
+
+    /// Set a value in workspace definitions
+    Set {
+        /// Workspace name or a glob pattern
+        pattern: String,
+
+        /// Key to set, nested keys are separated by `.`
+        key: String,
+
+        /// New value
+        ///
+        /// Parsed as a TOML value, anything which isn't valid TOML is taken as
+        /// a string.
+        #[clap(verbatim_doc_comment)]
+        value: String,
+
+        /// Only show which workspaces would be changed
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Modify locked workspaces too
+        #[clap(long)]
+        unlock: bool,
+    },
+
+    /// Edit a workspace definition in `$VISUAL` or `$EDITOR`
+    ///
+    /// The definition is only saved once it's valid, an invalid one can be
+    /// edited again or is kept as a draft for the next edit.
+    #[clap(verbatim_doc_comment)]
+    Edit {
+        /// Workspace name, defaults to the current workspace
+        name: Option<String>,
+
+        /// Edit a locked workspace too
+        #[clap(long)]
+        unlock: bool,
+    },
+
+    /// Upgrade workspace and config files on disk to the current schema version
+    Migrate {
+        /// Only show which files would be upgraded
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Check git status and reachability of workspaces for `list --long`
+    ///
+    /// Workspaces are checked concurrently and the results are cached.
+    /// Workspaces checked less than --max-age seconds ago are skipped, so
+    /// it can run often from a timer like cron or a systemd timer.
+    #[clap(verbatim_doc_comment)]
+    Refresh {
+        /// Skip workspaces checked less than this many seconds ago
+        #[clap(long, default_value = "60")]
+        max_age: u64,
+    },
+
+    /// Check workspace definitions for suspicious settings
+    ///
+    /// Reports local directories in another user's home, missing ssh
+    /// identity files, local editors which aren't on PATH, workspaces
+    /// sharing a directory and names differing only by case. Exits with an
+    /// error if anything was found.
+    #[clap(verbatim_doc_comment)]
+    Lint,
 
     /// Open a workspace
+    #[clap(visible_alias = "pick")]
     Open {
+        /// Workspace name
+        ///
+        /// Without one the workspace is picked from a fuzzy searchable list.
+        name: Option<String>,
+
+        /// Don't open the workspaces listed in `depends_on`
+        #[clap(long)]
+        no_deps: bool,
+
+        /// Make the workspace current in all terminals, not only in this one
+        ///
+        /// Terminals are told apart by `$WORKSPACECTL_SESSION` or their tty,
+        /// a terminal which opened another workspace otherwise keeps it.
+        #[clap(long, conflicts_with = "and_exec", verbatim_doc_comment)]
+        global: bool,
+
+        /// Check out the branch pinned by `git.branch` if the repository is on
+        /// a different one
+        #[clap(long, verbatim_doc_comment)]
+        checkout: bool,
+
+        /// Run the command given after `--` in the workspace, then switch back
+        ///
+        /// The previously open workspace is open again afterwards, like in
+        /// `open foo --and-exec -- make test`. Remote workspaces run the
+        /// command over ssh. Exits with the command's exit code.
+        #[clap(long, requires = "command", verbatim_doc_comment)]
+        and_exec: bool,
+
+        /// Command run with `--and-exec`
+        #[clap(last = true, requires = "and_exec")]
+        command: Vec<String>,
+    },
+
+    /// Open a directory as a scratch workspace, without a definition file
+    ///
+    /// Terminals, editors and other commands use the scratch workspace
+    /// like any other until a different workspace is opened.
+    #[clap(verbatim_doc_comment)]
+    Scratch {
+        /// Directory of the workspace
+        ///
+        /// Defaults to the current directory, or with `--save` to the
+        /// directory of the last scratch workspace.
+        #[clap(value_hint = clap::ValueHint::DirPath, verbatim_doc_comment)]
+        dir: Option<String>,
+
+        /// Save the scratch workspace as workspace NAME instead
+        #[clap(long, value_name = "NAME")]
+        save: Option<String>,
+    },
+
+    /// Open a workspace with a terminal and an editor window
+    ///
+    /// NAME can also be `@META` to open every workspace listed by the
+    /// meta-workspace file `meta/META.toml` in the config directory, as
+    /// `workspaces = ["frontend", "backend"]`.
+    #[clap(verbatim_doc_comment)]
+    Start {
+        /// Workspace or `@` and meta-workspace name
+        name: String,
+
+        /// Don't start the workspaces listed in `depends_on`
+        #[clap(long)]
+        no_deps: bool,
+
+        /// Open all windows in a single kitty instance using a session file
+        ///
+        /// Each workspace gets its own OS window, laid out like
+        /// `export-kitty-session` prints it.
+        #[clap(long, verbatim_doc_comment)]
+        via_session: bool,
+    },
+
+    /// Close the windows opened by `start`
+    Close {
+        /// Workspace or `@` and meta-workspace name
+        name: String,
+    },
+
+    /// Open a workspace and print shell code entering it
+    ///
+    /// Use as `eval "$(wsctl activate NAME)"` to change into a local
+    /// workspace with its environment and `WS_NAME` exported, or to
+    /// start an ssh session for a remote one.
+    #[clap(verbatim_doc_comment)]
+    Activate {
         /// Workspace name
         name: String,
     },
 
     /// Print the workspace config as JSON
     Cat {
+        /// Workspace name or a glob pattern
+        ///
+        /// Defaults to the current open workspace.
+        name: Option<String>,
+
+        /// Print the settings the workspace is opened with instead
+        ///
+        /// Defaults from the global config and detected on remote hosts are
+        /// filled in and templates are expanded.
+        #[clap(long, verbatim_doc_comment)]
+        resolve: bool,
+
+        /// Annotate each resolved value with where it comes from
+        ///
+        /// One of `workspace`, `config`, `detected` or `default`.
+        #[clap(long, requires = "resolve", verbatim_doc_comment)]
+        origin: bool,
+    },
+
+    /// Print a tmuxp session file for the workspace
+    ///
+    /// The session starts in the workspace directory with its shell and
+    /// environment, and its windows come from `tmux.layout` if that's a
+    /// tmuxp file. Secrets are left out. Remote workspaces are exported for
+    /// running tmuxp on their host.
+    #[clap(verbatim_doc_comment)]
+    ExportTmuxp {
+        /// Workspace name
+        name: String,
+    },
+
+    /// Print a kitty session file for the workspace
+    ///
+    /// Windows of a tmuxp `tmux.layout` become tabs with a kitty window for
+    /// each pane, otherwise a single tab holds the terminal and the editor.
+    /// Use it with `kitty --session`.
+    #[clap(verbatim_doc_comment)]
+    ExportKittySession {
+        /// Workspace name
+        name: String,
+    },
+
+    /// Print a WezTerm Lua module for the workspace
+    ///
+    /// The module returns spawn commands for the terminal and the editor and
+    /// a `SwitchToWorkspace` action to bind to a key. Remote workspaces use
+    /// WezTerm's `SSH:<host>` domain. Secrets are left out.
+    #[clap(verbatim_doc_comment)]
+    ExportWezterm {
+        /// Workspace name
+        name: String,
+    },
+
+    /// Print a human readable summary of a workspace
+    Info {
         /// Workspace name
         ///
         /// Defaults to the current open workspace.
         name: Option<String>,
     },
 
+    /// Print the workspaces of a directory
+    ///
+    /// Looks up the closest directory containing PATH which has a
+    /// workspace. Local workspaces only.
+    #[clap(verbatim_doc_comment)]
+    Which {
+        /// Path, defaults to the current directory
+        #[clap(value_hint = clap::ValueHint::AnyPath)]
+        path: Option<String>,
+    },
+
+    /// Print the URI of a workspace directory
+    ///
+    /// Remote workspaces get an `sftp://[user@]host[:port]/dir` URI for file
+    /// managers like Nautilus or Dolphin, local ones a `file://` URI.
+    #[clap(verbatim_doc_comment)]
+    Uri {
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        name: Option<String>,
+
+        /// Open the URI in the default application instead of printing it
+        #[clap(long)]
+        open: bool,
+    },
+
+    /// Search names, directories, hosts, tags and notes of all workspaces
+    Search {
+        /// Case-insensitive text to search for
+        query: String,
+    },
+
+    /// Search file contents in a workspace
+    ///
+    /// Runs `rg` in the workspace directory, over ssh for remote workspaces.
+    /// Falls back to `grep -r` when `rg` is not installed.
+    #[clap(verbatim_doc_comment)]
+    Grep {
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        #[clap(short, long = "workspace")]
+        workspace: Option<String>,
+
+        /// Pattern to search for
+        pattern: String,
+
+        /// Additional arguments passed to `rg`
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Find files by name in a workspace
+    ///
+    /// Runs `fd` in the workspace directory, over ssh for remote workspaces.
+    /// Falls back to `find` when `fd` is not installed. Printed paths are
+    /// relative to the workspace directory.
+    #[clap(verbatim_doc_comment)]
+    Find {
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        #[clap(short, long = "workspace")]
+        workspace: Option<String>,
+
+        /// Glob pattern matched against file names
+        glob: String,
+    },
+
     /// Open a terminal in the current workspace
-    Terminal {},
+    Terminal {
+        /// Run the shell in the current terminal instead of opening a window
+        #[clap(long)]
+        here: bool,
+    },
 
     /// Open an editor in the current workspace
-    Editor {},
+    Editor {
+        /// Run the editor in the current terminal instead of opening a window
+        #[clap(long)]
+        here: bool,
+
+        /// Open FILE[:LINE] instead of the workspace directory
+        ///
+        /// Relative files are relative to the workspace directory. Neovim
+        /// already running for a local workspace opens the file itself instead
+        /// of a new window.
+        #[clap(long, value_name = "FILE[:LINE]", verbatim_doc_comment)]
+        at: Option<String>,
+    },
+
+    /// Open a Git UI in a workspace
+    ///
+    /// Runs `lazygit`, or the `git.ui` command, in the workspace directory,
+    /// over ssh for remote workspaces.
+    #[clap(verbatim_doc_comment)]
+    GitUi {
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        #[clap(short, long = "workspace")]
+        workspace: Option<String>,
+
+        /// Run the Git UI in the current terminal instead of opening a window
+        #[clap(long)]
+        here: bool,
+    },
+
+    /// Run a command in a workspace
+    ///
+    /// The command runs in the workspace directory with the environment of
+    /// its terminals, over ssh for remote workspaces, like in
+    /// `exec -- make test`. Exits with the command's exit code.
+    #[clap(verbatim_doc_comment)]
+    Exec {
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        #[clap(short, long = "workspace")]
+        workspace: Option<String>,
+
+        /// Run the command in a terminal, for interactive programs
+        ///
+        /// Remote commands get a pseudo-terminal from ssh, like `htop` or a
+        /// REPL need. Without it remote output is piped, for scripting.
+        #[clap(long, short, visible_alias = "interactive", verbatim_doc_comment)]
+        tty: bool,
+
+        /// Command and its arguments
+        #[clap(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Print the environment variables set for processes run in a workspace
+    ///
+    /// Terminals, editors and `exec` get the workspace variables, secrets,
+    /// locale and virtualenv on top of the environment `workspacectl` runs
+    /// in. Secret values are hidden.
+    #[clap(verbatim_doc_comment)]
+    Env {
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        #[clap(short, long = "workspace")]
+        workspace: Option<String>,
+
+        /// Print the whole environment of local processes, marking added
+        /// variables with `+` and changed ones with `~`
+        #[clap(long, verbatim_doc_comment)]
+        resolved: bool,
+    },
+
+    /// Copy a local file or directory into a remote workspace
+    ///
+    /// The file is copied into the workspace directory with scp, using the
+    /// workspace's ssh settings.
+    #[clap(verbatim_doc_comment)]
+    PushFile {
+        /// Local file or directory
+        #[clap(value_hint = clap::ValueHint::AnyPath)]
+        file: String,
+
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        #[clap(short, long = "workspace")]
+        workspace: Option<String>,
+    },
+
+    /// Copy a file or directory from a remote workspace
+    ///
+    /// The file is copied into the current directory with scp, using the
+    /// workspace's ssh settings.
+    #[clap(verbatim_doc_comment)]
+    PullFile {
+        /// Remote path, relative paths are relative to the workspace directory
+        remote_path: String,
+
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        #[clap(short, long = "workspace")]
+        workspace: Option<String>,
+    },
+
+    /// Print changes to files in a remote workspace as they happen
+    ///
+    /// Runs inotifywait from inotify-tools on the remote host, one line is
+    /// printed per change with its events and the changed path.
+    #[clap(verbatim_doc_comment)]
+    WatchRemote {
+        /// Remote path, relative paths are relative to the workspace directory
+        path: String,
+
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        #[clap(short, long = "workspace")]
+        workspace: Option<String>,
+
+        /// Also show a desktop notification for each change with notify-send
+        #[clap(long)]
+        notify: bool,
+    },
+
+    /// Review and allow project configs
+    ///
+    /// A project config committed to a workspace directory can set the
+    /// commands the workspace runs, so it's ignored until it's allowed.
+    /// Every change to the file has to be allowed again.
+    #[clap(verbatim_doc_comment)]
+    Project {
+        #[clap(subcommand)]
+        cmd: ProjectCmd,
+    },
+
+    /// Manage secrets of a workspace
+    ///
+    /// Secrets are stored in the platform keyring and exported as
+    /// environment variables to terminals and editors opened in the
+    /// workspace.
+    #[clap(verbatim_doc_comment)]
+    Secret {
+        #[clap(subcommand)]
+        cmd: SecretCmd,
+    },
+
+    /// Inspect remote hosts
+    ///
+    /// A profile of each host's system, shell, editors and tools is stored
+    /// whenever it's detected, on first use or when the cache expires.
+    #[clap(verbatim_doc_comment)]
+    Host {
+        #[clap(subcommand)]
+        cmd: HostCmd,
+    },
+
+    /// Show and change the global config
+    Config {
+        #[clap(subcommand)]
+        cmd: ConfigCmd,
+    },
+
+    /// Manage cached state
+    ///
+    /// Shells and editors of remote users are detected on first use and
+    /// reused for `ssh.cache_ttl` seconds from the global config, a day by
+    /// default.
+    #[clap(verbatim_doc_comment)]
+    Cache {
+        #[clap(subcommand)]
+        cmd: CacheCmd,
+    },
+
+    /// Remove state left behind by what no longer exists
+    ///
+    /// Cleans up history records and Neovim sockets of deleted workspaces,
+    /// windows whose process exited, a deleted currently open or scratch
+    /// workspace, the currently open workspaces of closed terminals, and
+    /// detected properties and profiles of hosts no workspace connects to.
+    #[clap(verbatim_doc_comment)]
+    Gc {
+        /// Only show what would be removed
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Print workspace events as JSON lines
+    ///
+    /// Events are `workspace-opened`, `workspace-closed`, `spawn-started`
+    /// and `spawn-exited`, each with the unix `time` it happened. Only the
+    /// last thousand or so are kept.
+    #[clap(verbatim_doc_comment)]
+    Events {
+        /// Keep printing new events as they happen
+        #[clap(long, short)]
+        follow: bool,
+    },
+
+    /// Any other subcommand runs the plugin `wsctl-<subcommand>` from PATH
+    #[clap(external_subcommand)]
+    Plugin(Vec<String>),
+}
+
+#[derive(Subcommand, Debug)]
+enum HostCmd {
+    /// Show what is known about a remote host
+    Info {
+        /// SSH host
+        host: String,
+
+        /// Detect the host again instead of showing the stored profile
+        #[clap(long)]
+        refresh: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCmd {
+    /// Open the config in `$VISUAL` or `$EDITOR`
+    ///
+    /// The config is saved once the editor exits and it's valid. Unknown
+    /// keys are errors, an invalid config can be edited again.
+    #[clap(verbatim_doc_comment)]
+    Edit,
+
+    /// Print a value of the config
+    Get {
+        /// Key to print, nested keys are separated by `.`
+        key: String,
+    },
+
+    /// Print the effective global settings as JSON
+    ///
+    /// Includes the defaults of everything the config doesn't set, as used
+    /// by local workspaces.
+    #[clap(verbatim_doc_comment)]
+    Show {
+        /// Print where each value comes from, the config, detected from
+        /// the environment or a built-in default
+        #[clap(long, verbatim_doc_comment)]
+        origin: bool,
+    },
+
+    /// Set a value in the config
+    Set {
+        /// Key to set, nested keys are separated by `.`
+        key: String,
+
+        /// New value
+        ///
+        /// Parsed as a TOML value, anything which isn't valid TOML is taken as
+        /// a string.
+        #[clap(verbatim_doc_comment)]
+        value: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCmd {
+    /// Forget detected remote shells and editors, and remote project configs
+    Clear {
+        /// Only forget what was detected for this host
+        host: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProjectCmd {
+    /// Print the project config of a workspace and whether it's allowed
+    Show {
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        name: Option<String>,
+    },
+
+    /// Allow the project config of a workspace as it is now
+    Allow {
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        name: Option<String>,
+    },
+
+    /// Stop using the project config of a workspace
+    Deny {
+        /// Workspace name
+        ///
+        /// Defaults to the current open workspace.
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SecretCmd {
+    /// Set a secret, the value is prompted for or read from stdin
+    Set {
+        /// Workspace name
+        name: String,
+
+        /// Secret name, exported as an environment variable of the same name
+        key: String,
+    },
+
+    /// List the names of secrets set for a workspace
+    List {
+        /// Workspace name
+        name: String,
+    },
+
+    /// Remove a secret
+    Rm {
+        /// Workspace name
+        name: String,
+
+        /// Secret name
+        key: String,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
     let opts = Opts::parse();
+    workspacectl::init_logging(opts.log_level, opts.log_format, opts.log_file)?;
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let _span = tracing::info_span!("command", ?args).entered();
+    let result = run(opts);
+    if let Err(err) = &result {
+        tracing::error!(err = format!("{err:#}"), "command failed");
+    }
+    result
+}
+
+fn run(opts: Opts) -> anyhow::Result<()> {
+    workspacectl::set_strict(opts.strict);
+    workspacectl::set_ignore_project_config(opts.no_project_config);
+    workspacectl::set_quiet(opts.quiet);
+    workspacectl::set_porcelain(opts.porcelain);
+    workspacectl::set_input(opts.yes, opts.no_input);
+    if opts.print_cmd {
+        workspacectl::set_runner(workspacectl::Print)?;
+    }
     match opts.cmd {
-        Cmd::New { ssh, path, name } => workspacectl::init(ssh, path, name),
-        Cmd::List {} => workspacectl::list(),
-        Cmd::Open { name } => workspacectl::open(name),
-        Cmd::Cat { name } => workspacectl::cat(name),
-        Cmd::Terminal {} => workspacectl::terminal(),
-        Cmd::Editor {} => workspacectl::editor(),
+        Cmd::New {
+            ssh,
+            path,
+            interactive,
+            clone,
+            name,
+            group,
+            format,
+            encrypt,
+        } => workspacectl::init(ssh, path, interactive, clone, name, group, format, encrypt),
+        Cmd::List {
+            pattern,
+            sort,
+            all,
+            long,
+            format,
+            json,
+        } => {
+            let format = if json { ListFormat::Json } else { format };
+            workspacectl::list(pattern, sort, all, long, format)
+        }
+        Cmd::Move {
+            source,
+            destination,
+            dry_run,
+            unlock,
+        } => workspacectl::mv(source, destination, dry_run, unlock),
+        Cmd::Delete {
+            pattern,
+            force,
+            dry_run,
+            unlock,
+        } => workspacectl::delete(pattern, force, dry_run, unlock),
+        Cmd::Archive {
+            pattern,
+            dry_run,
+            unlock,
+        } => workspacectl::archive(pattern, dry_run, unlock),
+        Cmd::Unarchive {
+            pattern,
+            dry_run,
+            unlock,
+        } => workspacectl::unarchive(pattern, dry_run, unlock),
+        Cmd::Lock { pattern } => workspacectl::lock(pattern),
+        Cmd::Unlock { pattern } => workspacectl::unlock(pattern),
+        Cmd::Set {
+            pattern,
+            key,
+            value,
+            dry_run,
+            unlock,
+        } => workspacectl::set(pattern, key, value, dry_run, unlock),
+        Cmd::Edit { name, unlock } => workspacectl::edit(name, unlock),
+        Cmd::Migrate { dry_run } => workspacectl::migrate(dry_run),
+        Cmd::Refresh { max_age } => workspacectl::refresh(max_age),
+        Cmd::Lint => workspacectl::lint(),
+        Cmd::Open {
+            name,
+            no_deps,
+            checkout,
+            global,
+            and_exec: _,
+            command,
+        } => workspacectl::open(name, no_deps, checkout, global, command),
+        Cmd::Scratch { dir, save } => workspacectl::scratch(dir, save),
+        Cmd::Start {
+            name,
+            no_deps,
+            via_session,
+        } => workspacectl::start(name, no_deps, via_session),
+        Cmd::Close { name } => workspacectl::close(name),
+        Cmd::Activate { name } => workspacectl::activate(name),
+        Cmd::Cat {
+            name,
+            resolve,
+            origin,
+        } => workspacectl::cat(name, resolve, origin),
+        Cmd::ExportTmuxp { name } => workspacectl::export_tmuxp(name),
+        Cmd::ExportKittySession { name } => workspacectl::export_kitty_session(name),
+        Cmd::ExportWezterm { name } => workspacectl::export_wezterm(name),
+        Cmd::Info { name } => workspacectl::info(name),
+        Cmd::Which { path } => workspacectl::which(path),
+        Cmd::Uri { name, open } => workspacectl::uri(name, open),
+        Cmd::Search { query } => workspacectl::search(query),
+        Cmd::Grep {
+            workspace,
+            pattern,
+            args,
+        } => workspacectl::grep(workspace, pattern, args),
+        Cmd::Find { workspace, glob } => workspacectl::find(workspace, glob),
+        Cmd::Terminal { here } => workspacectl::terminal(here),
+        Cmd::Editor { here, at } => workspacectl::editor(here, at),
+        Cmd::GitUi { workspace, here } => workspacectl::git_ui(workspace, here),
+        Cmd::Exec {
+            workspace,
+            tty,
+            command,
+        } => workspacectl::exec(workspace, command, tty),
+        Cmd::Env {
+            workspace,
+            resolved,
+        } => workspacectl::env(workspace, resolved),
+        Cmd::PushFile { file, workspace } => workspacectl::push_file(workspace, file),
+        Cmd::PullFile {
+            remote_path,
+            workspace,
+        } => workspacectl::pull_file(workspace, remote_path),
+        Cmd::WatchRemote {
+            path,
+            workspace,
+            notify,
+        } => workspacectl::watch_remote(workspace, path, notify),
+        Cmd::Project { cmd } => match cmd {
+            ProjectCmd::Show { name } => workspacectl::project_show(name),
+            ProjectCmd::Allow { name } => workspacectl::project_allow(name),
+            ProjectCmd::Deny { name } => workspacectl::project_deny(name),
+        },
+        Cmd::Secret { cmd } => match cmd {
+            SecretCmd::Set { name, key } => workspacectl::secret_set(name, key),
+            SecretCmd::List { name } => workspacectl::secret_list(name),
+            SecretCmd::Rm { name, key } => workspacectl::secret_rm(name, key),
+        },
+        Cmd::Host { cmd } => match cmd {
+            HostCmd::Info { host, refresh } => workspacectl::host_info(host, refresh),
+        },
+        Cmd::Config { cmd } => match cmd {
+            ConfigCmd::Edit => workspacectl::config_edit(),
+            ConfigCmd::Get { key } => workspacectl::config_get(key),
+            ConfigCmd::Show { origin } => workspacectl::config_show(origin),
+            ConfigCmd::Set { key, value } => workspacectl::config_set(key, value),
+        },
+        Cmd::Cache { cmd } => match cmd {
+            CacheCmd::Clear { host } => workspacectl::cache_clear(host),
+        },
+        Cmd::Gc { dry_run } => workspacectl::gc(dry_run),
+        Cmd::Events { follow } => workspacectl::events(follow),
+        Cmd::Plugin(args) => workspacectl::plugin(args),
     }
 }
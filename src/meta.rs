@@ -0,0 +1,116 @@
+//! Meta-workspaces which stand for several workspaces at once
+//!
+//! A meta-workspace is defined by a file `meta/<name>.toml` in the config directory listing its
+//! member workspaces, for example `workspaces = ["acme/frontend", "acme/backend"]`. Commands
+//! accepting them take the name prefixed with [`PREFIX`], like `@acme`.
+//!
+//! Workspaces also pull in the workspaces listed in their `depends_on`, see [`with_dependencies`].
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use serde_derive::Deserialize;
+
+use crate::workspace::{self, Workspace};
+use crate::{config, parse};
+
+/// Prefix distinguishing meta-workspace names from workspace names
+pub const PREFIX: char = '@';
+
+/// Definition of a meta-workspace
+#[derive(Debug, Deserialize)]
+pub struct Meta {
+    /// Names of the member workspaces, in the order they are opened
+    pub workspaces: Vec<String>,
+}
+
+impl parse::Schema for Meta {
+    fn known_keys(path: &[String]) -> &'static [&'static str] {
+        match path {
+            [] => parse::fields::<Meta>(),
+            _ => &[],
+        }
+    }
+}
+
+/// Returns path to the directory used to store meta-workspace definition files
+fn dir_path() -> Result<PathBuf> {
+    Ok(config::dir_path()?.join("meta"))
+}
+
+/// Reads the definition of meta-workspace `name`, given without the prefix
+pub fn read(name: &str) -> Result<Meta> {
+    let path = dir_path()?.join(format!("{name}.toml"));
+    let buf = match fs::read_to_string(&path) {
+        Ok(buf) => buf,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            bail!("meta-workspace {name:?} not found, define it in {path:?}")
+        }
+        Err(err) => return Err(err).with_context(|| format!("reading {path:?}")),
+    };
+    parse::file(&path, &buf, parse::Format::Toml).with_context(|| format!("parsing {path:?}"))
+}
+
+/// Returns the workspaces `reference` stands for
+///
+/// A meta-workspace reference resolves to its members, anything else is a single workspace name.
+/// All returned workspaces exist.
+pub fn resolve(reference: &str) -> Result<Vec<String>> {
+    let Some(name) = reference.strip_prefix(PREFIX) else {
+        workspace::read(reference).context("reading workspace definition")?;
+        return Ok(vec![reference.to_owned()]);
+    };
+    let meta = read(name)?;
+    for member in &meta.workspaces {
+        if !workspace::exists(member)? {
+            bail!("meta-workspace {name:?} lists workspace {member:?} which doesn't exist");
+        }
+    }
+    Ok(meta.workspaces)
+}
+
+/// Reads the workspaces `names` with the dependencies of each workspace in front of it
+///
+/// Dependencies are ordered so every workspace comes after everything it depends on, and each
+/// workspace appears only once. Every definition is read once, so encrypted ones only ask for
+/// their passphrase once. Dependency cycles are an error.
+pub fn with_dependencies(names: &[String]) -> Result<Vec<Workspace>> {
+    let mut ordered = Vec::new();
+    for name in names {
+        visit(name, &mut Vec::new(), &mut ordered)?;
+    }
+    Ok(ordered)
+}
+
+/// Adds the dependencies of `name` and then `name` itself to `ordered`
+///
+/// `path` holds the workspaces whose dependencies are being added, leading to `name`.
+fn visit(name: &str, path: &mut Vec<String>, ordered: &mut Vec<Workspace>) -> Result<()> {
+    if ordered.iter().any(|done| done.name == name) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|visiting| visiting == name) {
+        let cycle = path[start..]
+            .iter()
+            .map(String::as_str)
+            .chain([name])
+            .collect::<Vec<_>>();
+        bail!(
+            "workspace dependencies form a cycle: {}",
+            cycle.join(" -> ")
+        );
+    }
+    let workspace = workspace::read(name).with_context(|| match path.last() {
+        Some(parent) => format!("reading dependency {name:?} of workspace {parent:?}"),
+        None => "reading workspace definition".to_owned(),
+    })?;
+    path.push(name.to_owned());
+    for dependency in workspace.depends_on.iter().flatten() {
+        visit(dependency, path, ordered)?;
+    }
+    path.pop();
+    ordered.push(workspace);
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Schema versions of workspace and config files
+//!
+//! Every file carries a `version` key, files without one predate versioning and are version 1.
+//! Files older than the current version are upgraded in memory whenever they are read, the
+//! `migrate` command writes the upgraded files back to disk. Migrations edit the files through
+//! [`toml_edit`] so comments and formatting survive.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use atomicwrites::AtomicFile;
+use toml_edit::{Document, Item};
+
+/// Current version of workspace definition files
+pub const WORKSPACE_VERSION: i64 = 1;
+
+/// Current version of the global config file
+pub const CONFIG_VERSION: i64 = 1;
+
+/// Version of files written before versioning was introduced
+const UNVERSIONED: i64 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    Workspace,
+    Config,
+}
+
+/// Upgrades a document from version `to - 1` to version `to`
+struct Migration {
+    to: i64,
+    apply: fn(&mut Document) -> Result<()>,
+}
+
+/// Migrations of workspace definition files, ordered by version
+const WORKSPACE_MIGRATIONS: &[Migration] = &[];
+
+/// Migrations of the global config file, ordered by version
+const CONFIG_MIGRATIONS: &[Migration] = &[];
+
+impl Kind {
+    fn current(self) -> i64 {
+        match self {
+            Kind::Workspace => WORKSPACE_VERSION,
+            Kind::Config => CONFIG_VERSION,
+        }
+    }
+
+    fn migrations(self) -> &'static [Migration] {
+        match self {
+            Kind::Workspace => WORKSPACE_MIGRATIONS,
+            Kind::Config => CONFIG_MIGRATIONS,
+        }
+    }
+}
+
+/// Returns the schema version of `document`
+fn version(document: &Document) -> Result<i64> {
+    match document.get("version") {
+        None => Ok(UNVERSIONED),
+        Some(item) => item.as_integer().context("`version` must be an integer"),
+    }
+}
+
+/// Upgrades `document` to the current version, returns the version it was upgraded from
+fn upgrade_document(document: &mut Document, kind: Kind) -> Result<i64> {
+    let from = version(document)?;
+    let current = kind.current();
+    if from > current {
+        bail!(
+            "file has version {from} but this workspacectl only understands up to version {current}, upgrade workspacectl",
+        );
+    }
+    for migration in kind.migrations().iter().filter(|m| m.to > from) {
+        (migration.apply)(document)
+            .with_context(|| format!("migrating to version {}", migration.to))?;
+    }
+    Ok(from)
+}
+
+/// Returns contents of file `buf` upgraded to the current version
+///
+/// Files which are already current are returned as they are.
+pub fn upgrade(buf: String, kind: Kind) -> Result<String> {
+    let mut document = buf.parse::<Document>()?;
+    if version(&document)? == kind.current() {
+        return Ok(buf);
+    }
+    upgrade_document(&mut document, kind)?;
+    Ok(document.to_string())
+}
+
+/// Upgrades file at `path` on disk and stamps it with the current version
+///
+/// Returns the version the file was upgraded from, or `None` if it didn't need any changes.
+pub fn rewrite(path: &Path, kind: Kind, dry_run: bool) -> Result<Option<i64>> {
+    let buf = fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+    let mut document = buf
+        .parse::<Document>()
+        .with_context(|| format!("parsing {path:?}"))?;
+    let stamped = document.contains_key("version");
+    let from =
+        upgrade_document(&mut document, kind).with_context(|| format!("upgrading {path:?}"))?;
+    if stamped && from == kind.current() {
+        return Ok(None);
+    }
+    if !dry_run {
+        document.insert("version", Item::Value(kind.current().into()));
+        let buf = document.to_string();
+        AtomicFile::new(path, atomicwrites::AllowOverwrite)
+            .write(|file| file.write_all(buf.as_bytes()))
+            .with_context(|| format!("atomically write {path:?}"))?;
+    }
+    Ok(Some(from))
+}
@@ -0,0 +1,46 @@
+//! Running workspace shells in Nix development environments
+//!
+//! Workspaces with a `[nix]` table start their shells inside `nix develop` for a flake, or inside
+//! `nix-shell` for a `shell.nix` or `default.nix`, both evaluated in the workspace directory.
+
+use crate::shell;
+use crate::workspace::{Nix, NixCommand};
+
+/// Flake used when `nix.flake` isn't set, the one in the workspace directory
+const DEFAULT_FLAKE: &str = ".";
+
+/// Returns `program` with `args` wrapped in the environment of `nix`, as a program and arguments
+pub fn wrap(nix: &Nix, program: &str, args: &[String]) -> (String, Vec<String>) {
+    match nix.command.unwrap_or_default() {
+        NixCommand::Develop => {
+            let flake = nix.flake.as_deref().unwrap_or(DEFAULT_FLAKE);
+            let mut wrapped = vec![
+                "develop".to_owned(),
+                flake.to_owned(),
+                "--command".to_owned(),
+                program.to_owned(),
+            ];
+            wrapped.extend(args.iter().cloned());
+            ("nix".to_owned(), wrapped)
+        }
+        // `--run` takes a command line for bash.
+        NixCommand::NixShell => {
+            let line = shell::join(std::iter::once(program).chain(args.iter().map(String::as_str)));
+            let run = vec!["--run".to_owned(), format!("exec {line}")];
+            ("nix-shell".to_owned(), run)
+        }
+    }
+}
+
+/// Returns POSIX command line `line` wrapped in the environment of `nix`
+pub fn wrap_line(nix: &Nix, line: &str) -> String {
+    match nix.command.unwrap_or_default() {
+        NixCommand::Develop => {
+            let flake = nix.flake.as_deref().unwrap_or(DEFAULT_FLAKE);
+            format!("nix develop {} --command {line}", shell::quote(flake))
+        }
+        NixCommand::NixShell => {
+            format!("nix-shell --run {}", shell::quote(&format!("exec {line}")))
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Reusing running Neovim instances
+//!
+//! Neovim opened as the editor of a local workspace listens on a new socket, which is recorded in
+//! the cache as [`Key::Nvim`]. `editor --at` opens the file in that instance through
+//! `nvim --server` and only opens a new editor when nothing listens on the socket anymore.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, process};
+
+use anyhow::{Context, Result};
+
+use crate::cache::{self, Key};
+use crate::launch::Window;
+use crate::{runner, shell};
+
+/// Number of sockets created by this process, keeps their paths unique
+static SOCKETS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns whether editor command `command` runs Neovim, whatever arguments it passes
+pub fn is_nvim(command: &str) -> bool {
+    shell::program(command) == "nvim"
+}
+
+/// Makes the Neovim opened by `window` listen on a new socket, recorded for workspace `name`
+///
+/// The socket isn't recorded while commands are only printed, nothing would listen on it.
+pub fn listen(window: &mut Window, name: &str) -> Result<()> {
+    let id = format!(
+        "{}-{}",
+        process::id(),
+        SOCKETS.fetch_add(1, Ordering::Relaxed)
+    );
+    let socket = if cfg!(windows) {
+        format!(r"\\.\pipe\workspacectl-nvim-{id}")
+    } else {
+        let dir = dirs::runtime_dir().unwrap_or_else(env::temp_dir);
+        dir.join(format!("workspacectl-nvim-{id}.sock"))
+            .to_string_lossy()
+            .into_owned()
+    };
+    window
+        .args
+        .splice(0..0, ["--listen".to_owned(), socket.clone()]);
+    if runner::is_dry_run() {
+        return Ok(());
+    }
+    cache::write(Key::Nvim(name.to_owned()), socket).context("recording nvim socket")
+}
+
+/// Opens `path` at `line` in the running Neovim of workspace `name`, using the program of editor
+/// command `command` as the client
+///
+/// Returns `false` if there's no running instance to open it in.
+pub fn open(name: &str, command: &str, path: &Path, line: Option<u32>) -> Result<bool> {
+    let key = Key::Nvim(name.to_owned());
+    // Nothing was recorded if the workspace never had Neovim open.
+    let Ok(socket) = cache::read(key.clone()) else {
+        return Ok(false);
+    };
+    if !cfg!(windows) && !Path::new(&socket).exists() {
+        cache::remove(key)?;
+        return Ok(false);
+    }
+
+    let path = path.to_str().context("file path is not valid utf-8")?;
+    let line = line.map(|line| format!("+{line} ")).unwrap_or_default();
+    // Vim strings in single quotes only escape the quote itself.
+    let expr = format!(
+        "execute('edit {line}' .. fnameescape('{}'))",
+        path.replace('\'', "''"),
+    );
+    // The editor's own arguments don't apply to the client.
+    let program = command.split_whitespace().next().unwrap_or(command);
+    let mut command = Command::new(program);
+    command
+        .args(["--server", &socket, "--remote-expr", &expr])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let status = runner::status(&mut command).context("run nvim")?;
+    if !status.success() {
+        eprintln!("INFO nvim is not listening on {socket:?} anymore, opening a new editor");
+        cache::remove(key)?;
+        return Ok(false);
+    }
+    Ok(true)
+}
@@ -0,0 +1,347 @@
+//! Parsing of workspace and config files
+//!
+//! Errors are rendered pointing at the offending line and column of the file, with a hint for
+//! common mistakes where one is known. Unknown keys are reported as warnings, or as errors in
+//! strict mode.
+//!
+//! Workspace definitions can also be written in JSON or YAML, see [`Format`]. These are parsed
+//! into the same TOML value model so the rest of the pipeline is shared, but errors in them are
+//! reported without the source snippet.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::Serialize;
+use toml::{Table, Value};
+
+use crate::template;
+
+/// Whether unknown keys are errors instead of warnings
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Make unknown keys in parsed files errors instead of warnings
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Format of a file on disk, determined by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl Format {
+    /// All formats, in the order they are looked for
+    pub const ALL: [Format; 3] = [Format::Toml, Format::Json, Format::Yaml];
+
+    /// Returns the extensions recognized for files in this format, the first one is used for new
+    /// files
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::Toml => &["toml"],
+            Format::Json => &["json"],
+            Format::Yaml => &["yaml", "yml"],
+        }
+    }
+
+    /// Returns the format of a file with extension `extension`
+    pub fn from_extension(extension: &str) -> Option<Format> {
+        Format::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension))
+    }
+}
+
+/// Types parsed from files which know their keys, used to suggest fixes for unknown keys
+pub trait Schema: DeserializeOwned {
+    /// Returns the keys known in the table at `path`, the root table has an empty path
+    fn known_keys(path: &[String]) -> &'static [&'static str];
+}
+
+/// Parses file contents `buf` read from `path` in `format` into `T`
+///
+/// Environment variable references in string values are expanded, see [`template::expand_env`].
+pub fn file<T>(path: &Path, buf: &str, format: Format) -> Result<T>
+where
+    T: Schema,
+{
+    let mut value = match format {
+        Format::Toml => {
+            // Deserialize straight from the source first, only that keeps the spans for error
+            // messages. Expanding environment variables only ever changes string contents so
+            // once this succeeds the expanded value deserializes as well.
+            check::<T>(path, buf)?;
+            Value::Table(table(path, buf)?)
+        }
+        Format::Json | Format::Yaml => {
+            let value = value(path, buf, format)?;
+            check_value::<T>(path, value.clone())?;
+            value
+        }
+    };
+    template::expand_env_value(&mut value)
+        .with_context(|| format!("expanding environment variables in {path:?}"))?;
+    value
+        .try_into()
+        .with_context(|| format!("deserializing {path:?} after expanding environment variables"))
+}
+
+/// Checks TOML file contents `buf` read from `path` deserialize into `T`
+///
+/// Environment variable references are not expanded. Unknown keys are reported here.
+pub fn check<T>(path: &Path, buf: &str) -> Result<()>
+where
+    T: Schema,
+{
+    check_with::<T>(path, buf, STRICT.load(Ordering::Relaxed))
+}
+
+/// Checks TOML file contents `buf` like [`check`], with unknown keys as errors even outside of
+/// strict mode
+///
+/// For files being edited, where a typo is easiest to fix right away.
+pub fn check_strict<T>(path: &Path, buf: &str) -> Result<()>
+where
+    T: Schema,
+{
+    check_with::<T>(path, buf, true)
+}
+
+fn check_with<T>(path: &Path, buf: &str, strict: bool) -> Result<()>
+where
+    T: Schema,
+{
+    let mut unknown = Vec::new();
+    serde_ignored::deserialize::<_, _, T>(toml::Deserializer::new(buf), |ignored| {
+        let mut keys = Vec::new();
+        collect_keys(&ignored, &mut keys);
+        unknown.push(keys);
+    })
+    .map_err(|error| anyhow!(render(path, buf, &error)))?;
+    report_unknown::<T>(path, unknown, strict)
+}
+
+/// Checks an already parsed `value` read from `path` deserializes into `T`
+///
+/// Like [`check`] but for files not written in TOML, errors don't point into the source.
+fn check_value<T>(path: &Path, value: Value) -> Result<()>
+where
+    T: Schema,
+{
+    let mut unknown = Vec::new();
+    serde_ignored::deserialize::<_, _, T>(value, |ignored| {
+        let mut keys = Vec::new();
+        collect_keys(&ignored, &mut keys);
+        unknown.push(keys);
+    })
+    .map_err(|error| anyhow!("{}\n --> {}", error.message().trim(), path.display()))?;
+    report_unknown::<T>(path, unknown, STRICT.load(Ordering::Relaxed))
+}
+
+/// Reports `unknown` keys found in `path`, as errors if `strict` and warnings otherwise
+fn report_unknown<T>(path: &Path, unknown: Vec<Vec<String>>, strict: bool) -> Result<()>
+where
+    T: Schema,
+{
+    let messages = unknown
+        .into_iter()
+        .map(|keys| unknown_key::<T>(path, &keys))
+        .collect::<Vec<_>>();
+    if strict && !messages.is_empty() {
+        bail!("{}", messages.join("\n"));
+    }
+    for message in messages {
+        eprintln!("WARN {message}");
+    }
+    Ok(())
+}
+
+/// Flattens an ignored path into the keys leading to it
+fn collect_keys(path: &serde_ignored::Path, keys: &mut Vec<String>) {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => {}
+        Path::Seq { parent, index } => {
+            collect_keys(parent, keys);
+            keys.push(index.to_string());
+        }
+        Path::Map { parent, key } => {
+            collect_keys(parent, keys);
+            keys.push(key.clone());
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => collect_keys(parent, keys),
+    }
+}
+
+/// Describes an unknown key, suggesting the closest known key
+fn unknown_key<T>(path: &Path, keys: &[String]) -> String
+where
+    T: Schema,
+{
+    let (key, parent) = keys.split_last().expect("ignored keys are never the root");
+    let suggestion = T::known_keys(parent)
+        .iter()
+        .map(|known| (strsim::levenshtein(key, known), known))
+        .filter(|(distance, _)| *distance <= 2)
+        .min();
+    let mut message = format!("unknown key {:?} in {path:?}", keys.join("."));
+    if let Some((_, known)) = suggestion {
+        message.push_str(&format!(", did you mean {known:?}?"));
+    }
+    message
+}
+
+/// Returns the field names of struct `T`
+///
+/// Works by asking `T` to deserialize itself from a deserializer which only records the fields
+/// it's asked for.
+pub fn fields<T>() -> &'static [&'static str]
+where
+    T: DeserializeOwned,
+{
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for FieldNames<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            *self.0 = fields;
+            Err(de::Error::custom("fields recorded"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+/// Parses TOML file contents `buf` read from `path` into a table, as written without any expansion
+pub fn table(path: &Path, buf: &str) -> Result<Table> {
+    toml::from_str(buf).map_err(|error| anyhow!(render(path, buf, &error)))
+}
+
+/// Parses file contents `buf` read from `path` in `format` into a value, without any expansion
+pub fn value(path: &Path, buf: &str, format: Format) -> Result<Value> {
+    match format {
+        Format::Toml => table(path, buf).map(Value::Table),
+        Format::Json => {
+            serde_json::from_str(buf).with_context(|| format!("parsing JSON in {path:?}"))
+        }
+        Format::Yaml => {
+            serde_yaml::from_str(buf).with_context(|| format!("parsing YAML in {path:?}"))
+        }
+    }
+}
+
+/// Serializes `value` into a string in `format`
+///
+/// Missing optional values are left out in all formats rather than written as `null`.
+pub fn to_string<T>(value: &T, format: Format) -> Result<String>
+where
+    T: Serialize,
+{
+    match format {
+        Format::Toml => toml::to_string_pretty(value).context("serializing to TOML"),
+        Format::Json => {
+            // Going through TOML values drops `None`s which JSON would write as `null`.
+            let value = Value::try_from(value).context("serializing to JSON")?;
+            let mut buf = serde_json::to_string_pretty(&value).context("serializing to JSON")?;
+            buf.push('\n');
+            Ok(buf)
+        }
+        Format::Yaml => {
+            let value = Value::try_from(value).context("serializing to YAML")?;
+            serde_yaml::to_string(&value).context("serializing to YAML")
+        }
+    }
+}
+
+/// Renders a TOML error in the style of rustc diagnostics
+fn render(path: &Path, buf: &str, error: &toml::de::Error) -> String {
+    let message = error.message().trim();
+    let mut rendered = message.to_owned();
+
+    if let Some(span) = error.span() {
+        let start = span.start.min(buf.len());
+        let line_start = buf[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = buf[start..].find('\n').map_or(buf.len(), |i| start + i);
+        let line = &buf[line_start..line_end];
+        let line_number = buf[..line_start].matches('\n').count() + 1;
+        let column = buf[line_start..start].chars().count() + 1;
+        let width = buf[start..span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        let gutter = " ".repeat(line_number.to_string().len());
+        let padding = " ".repeat(column - 1);
+        let carets = "^".repeat(width);
+        rendered.push_str(&format!(
+            "\n{gutter}--> {path}:{line_number}:{column}\n{gutter} |\n{line_number} | {line}\n{gutter} | {padding}{carets}",
+            path = path.display(),
+        ));
+    } else {
+        rendered.push_str(&format!("\n --> {}", path.display()));
+    }
+
+    if let Some(hint) = hint(message) {
+        rendered.push_str(&format!("\n = hint: {hint}"));
+    }
+    rendered
+}
+
+/// Returns a suggestion for fixing common mistakes based on the error message
+fn hint(message: &str) -> Option<String> {
+    if message.contains("expected `\"`, `'`") || message.contains("expected a string") {
+        return Some("strings must be quoted, for example `command = \"vim\"`".to_owned());
+    }
+    if message.contains("expected a boolean") {
+        return Some("booleans are written as `true` or `false` without quotes".to_owned());
+    }
+    if message.contains("expected u16") || message.contains("expected an integer") {
+        return Some("numbers are written without quotes, for example `port = 22`".to_owned());
+    }
+    if message.contains("expected a sequence") {
+        return Some(
+            "lists are written in brackets, for example `tags = [\"a\", \"b\"]`".to_owned(),
+        );
+    }
+    if let Some(field) = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next())
+    {
+        return Some(format!("add the required `{field}` key to this table"));
+    }
+    if message.contains("duplicate key") {
+        return Some("each key can only be set once per table".to_owned());
+    }
+    None
+}
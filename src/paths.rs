@@ -0,0 +1,134 @@
+//! Directories `workspacectl` keeps its files in
+//!
+//! By default these are the `workspacectl` subdirectories of the user's config, cache and state
+//! directories. Each can be overridden by an environment variable, `WORKSPACECTL_CONFIG_DIR`,
+//! `WORKSPACECTL_CACHE_DIR` and `WORKSPACECTL_STATE_DIR`, the same variables hooks and plugins get.
+//! Programs using the library can set all of them at once with [`set_paths`], for example to
+//! [`Paths::in_dir`] a temporary directory.
+//!
+//! The user's directories are resolved once per process. The variables are read on every call,
+//! which doesn't touch the file system, so library users changing them see the new ones. None of
+//! the directories are canonicalized, which would stat every component on slow network file
+//! systems.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+use anyhow::{Context, Result};
+
+/// Directories of the config, cache and state of `workspacectl`
+#[derive(Debug, Clone)]
+pub struct Paths {
+    /// Global config, workspace and meta-workspace definitions
+    pub config: PathBuf,
+
+    /// State which can be recreated or lost, see `cache clear`
+    pub cache: PathBuf,
+
+    /// State which is kept when the cache is cleared
+    pub state: PathBuf,
+}
+
+impl Paths {
+    /// Returns the directories from the environment variables, or the user's directories
+    pub fn from_env() -> Result<Paths> {
+        Ok(Paths {
+            config: config_from_env()?,
+            cache: cache_from_env()?,
+            state: state_from_env()?,
+        })
+    }
+
+    /// Returns directories `config`, `cache` and `state` in `root`
+    pub fn in_dir(root: impl AsRef<Path>) -> Paths {
+        let root = root.as_ref();
+        Paths {
+            config: root.join("config"),
+            cache: root.join("cache"),
+            state: root.join("state"),
+        }
+    }
+}
+
+/// Directories set by [`set_paths`]
+static PATHS: RwLock<Option<Paths>> = RwLock::new(None);
+
+/// Use `paths` instead of the directories from the environment
+pub fn set_paths(paths: Paths) {
+    *PATHS.write().unwrap_or_else(|err| err.into_inner()) = Some(paths);
+}
+
+/// Returns the directory `select`ed out of the ones set by [`set_paths`], if they were
+fn injected(select: fn(Paths) -> PathBuf) -> Option<PathBuf> {
+    let paths = PATHS.read().unwrap_or_else(|err| err.into_inner());
+    paths.clone().map(select)
+}
+
+/// Returns the directory set by environment variable `variable`, if it's set and not empty
+fn from_var(variable: &str) -> Option<PathBuf> {
+    env::var_os(variable)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Returns path to the config directory
+pub fn config_dir() -> Result<PathBuf> {
+    match injected(|paths| paths.config) {
+        Some(dir) => Ok(dir),
+        None => config_from_env(),
+    }
+}
+
+/// Returns path to the cache directory
+pub fn cache_dir() -> Result<PathBuf> {
+    match injected(|paths| paths.cache) {
+        Some(dir) => Ok(dir),
+        None => cache_from_env(),
+    }
+}
+
+/// Returns path to the state directory
+pub fn state_dir() -> Result<PathBuf> {
+    match injected(|paths| paths.state) {
+        Some(dir) => Ok(dir),
+        None => state_from_env(),
+    }
+}
+
+fn config_from_env() -> Result<PathBuf> {
+    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    if let Some(dir) = from_var("WORKSPACECTL_CONFIG_DIR") {
+        return Ok(dir);
+    }
+    DIR.get_or_init(|| Some(dirs::config_dir()?.join("workspacectl")))
+        .clone()
+        .context("could not determine user config directory")
+}
+
+fn cache_from_env() -> Result<PathBuf> {
+    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    if let Some(dir) = from_var("WORKSPACECTL_CACHE_DIR") {
+        return Ok(dir);
+    }
+    DIR.get_or_init(|| Some(dirs::cache_dir()?.join("workspacectl")))
+        .clone()
+        .context("could not determine user cache directory")
+}
+
+fn state_from_env() -> Result<PathBuf> {
+    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    if let Some(dir) = from_var("WORKSPACECTL_STATE_DIR") {
+        return Ok(dir);
+    }
+    // Only Linux has a separate state directory.
+    DIR.get_or_init(|| {
+        Some(
+            dirs::state_dir()
+                .or_else(dirs::data_local_dir)?
+                .join("workspacectl"),
+        )
+    })
+    .clone()
+    .context("could not determine user state directory")
+}
@@ -0,0 +1,79 @@
+//! Output for scripts, `--porcelain`
+//!
+//! Porcelain output is versioned: the format of a version never changes, new fields or commands
+//! come with a new version. Each line is one record of tab-separated fields. Fields are never
+//! localized or aligned, times are unix timestamps, sizes are bytes and flags are `0` or `1`. An
+//! unknown value is an empty field. Tabs, newlines and backslashes in values are escaped as `\t`,
+//! `\n` and `\\`.
+//!
+//! Version `v1` covers these commands:
+//!
+//! - `list` prints `name`, `archived`, `encrypted` and `last opened` of each workspace
+//! - `info` prints `key` and `value` lines with keys `name`, `dir`, `ssh`, `editor`, `shell`,
+//!   `tags` (comma-separated), `last-opened`, `git-branch`, `size`, `files`, `complete` (whether
+//!   size and files counted everything) and `notes`
+//! - `host info` prints `key` and `value` lines with keys `host`, `os`, `arch`, `shell`, `editor`,
+//!   `editors` and `tools` (space-separated)
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+/// Versions of the porcelain format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Porcelain {
+    V1,
+}
+
+/// Requested porcelain version, `0` for human-readable output
+static VERSION: AtomicU8 = AtomicU8::new(0);
+
+/// Print porcelain output of `version`, or human-readable output when `None`
+pub fn set_porcelain(version: Option<Porcelain>) {
+    let version = match version {
+        None => 0,
+        Some(Porcelain::V1) => 1,
+    };
+    VERSION.store(version, Ordering::Relaxed);
+}
+
+/// Returns the requested porcelain version
+pub fn version() -> Option<Porcelain> {
+    match VERSION.load(Ordering::Relaxed) {
+        1 => Some(Porcelain::V1),
+        _ => None,
+    }
+}
+
+/// Escapes `field` so it can't be confused with field or record separators
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', r"\\")
+        .replace('\t', r"\t")
+        .replace('\n', r"\n")
+}
+
+/// Writes one record of `fields` to `out`
+pub fn write<W: Write, S: AsRef<str>>(out: &mut W, fields: &[S]) -> Result<()> {
+    let fields = fields
+        .iter()
+        .map(|field| escape(field.as_ref()))
+        .collect::<Vec<_>>();
+    writeln!(out, "{}", fields.join("\t")).context("writing to stdout")
+}
+
+/// Writes `key` and `value` records to stdout
+pub fn print_pairs(pairs: &[(&str, String)]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    for (key, value) in pairs {
+        write(&mut stdout, &[key, value.as_str()])?;
+    }
+    Ok(())
+}
+
+/// Returns `value` as a porcelain flag
+pub fn flag(value: bool) -> String {
+    if value { "1" } else { "0" }.to_owned()
+}
@@ -0,0 +1,113 @@
+//! Helpers for running child processes
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::runner;
+
+/// How long querying the state of a process may take
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns the path `program` would be run from, if it's installed
+///
+/// Programs given as a path are only checked to exist, bare names are looked up in `PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains(['/', std::path::MAIN_SEPARATOR]) {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .flat_map(|dir| {
+            let candidate = dir.join(program);
+            // Windows finds programs without their `.exe` extension.
+            let exe = cfg!(windows).then(|| candidate.with_extension("exe"));
+            std::iter::once(candidate).chain(exe)
+        })
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Returns `true` if `path` is a file which can be executed
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+/// Runs `command` to completion capturing its output, killing it after `timeout`
+///
+/// Returns `None` if the command didn't finish in time. Runs through [`runner::output`].
+pub fn output_with_timeout(command: Command, timeout: Duration) -> Result<Option<Output>> {
+    runner::output(command, timeout)
+}
+
+/// Returns whether process `pid` is still running `program`
+///
+/// The program is compared by file name, and only as far as the system reports it, so that a pid
+/// reused by an unrelated process doesn't count.
+#[cfg(unix)]
+pub fn is_running(pid: u32, program: &str) -> Result<bool> {
+    let mut command = Command::new("ps");
+    command.args(["-o", "comm=", "-p", &pid.to_string()]);
+    let output = output_with_timeout(command, QUERY_TIMEOUT)?.context("timed out running ps")?;
+    let running = String::from_utf8_lossy(&output.stdout);
+    let running = running.trim();
+    // Linux truncates the command name to 15 bytes.
+    Ok(!running.is_empty() && file_name(program).starts_with(running))
+}
+
+/// Returns whether process `pid` is still running `program`
+#[cfg(not(unix))]
+pub fn is_running(pid: u32, program: &str) -> Result<bool> {
+    let mut command = Command::new("tasklist");
+    command.args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"]);
+    let output =
+        output_with_timeout(command, QUERY_TIMEOUT)?.context("timed out running tasklist")?;
+    Ok(String::from_utf8_lossy(&output.stdout).contains(&file_name(program)))
+}
+
+/// Returns the file name of `program`, or `program` itself if it has none
+fn file_name(program: &str) -> String {
+    Path::new(program)
+        .file_name()
+        .map_or(program.into(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Asks process `pid` to terminate if it's still running `program`
+///
+/// Returns `false` if the process already exited. See [`is_running`] for how the program is
+/// compared.
+#[cfg(unix)]
+pub fn terminate(pid: u32, program: &str) -> Result<bool> {
+    if !is_running(pid, program)? {
+        return Ok(false);
+    }
+    let status = runner::status(Command::new("kill").arg(pid.to_string())).context("spawn kill")?;
+    Ok(status.success())
+}
+
+/// Asks process `pid` to terminate if it's still running `program`
+///
+/// Returns `false` if the process already exited.
+#[cfg(not(unix))]
+pub fn terminate(pid: u32, program: &str) -> Result<bool> {
+    if !is_running(pid, program)? {
+        return Ok(false);
+    }
+    let status = runner::status(Command::new("taskkill").args(["/PID", &pid.to_string()]))
+        .context("spawn taskkill")?;
+    Ok(status.success())
+}
@@ -0,0 +1,49 @@
+//! Progress indicators for operations which wait on remote machines
+//!
+//! Drawn on stderr, and only when stdout is a terminal, so scripts capturing the output don't get
+//! them. `--quiet` hides them too.
+
+use std::borrow::Cow;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Whether progress indicators are hidden
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Hide all progress indicators
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Returns `true` if progress indicators should be drawn
+pub fn visible() -> bool {
+    !QUIET.load(Ordering::Relaxed) && io::stdout().is_terminal()
+}
+
+/// Returns a spinner showing `message` until it's finished
+pub fn spinner(message: impl Into<Cow<'static, str>>) -> ProgressBar {
+    if !visible() {
+        return ProgressBar::hidden();
+    }
+    let spinner = ProgressBar::new_spinner().with_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+/// Returns a progress bar counting up to `len` steps, labeled with `message`
+pub fn bar(len: u64, message: impl Into<Cow<'static, str>>) -> ProgressBar {
+    if !visible() {
+        return ProgressBar::hidden();
+    }
+    let style = ProgressStyle::with_template("{spinner} {msg} [{bar:20}] {pos}/{len}")
+        .expect("progress template is valid")
+        .progress_chars("=> ");
+    let bar = ProgressBar::new(len)
+        .with_style(style)
+        .with_message(message);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar
+}
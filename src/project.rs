@@ -0,0 +1,311 @@
+//! Project config committed to workspace directories
+//!
+//! A project can commit a [`FILE_NAME`] file to its root directory to share editor, shell, tmux,
+//! Nix and Python settings with everyone working on it, see [`Project`]. It's merged into the
+//! workspace definitions of that directory, and settings are taken from the first of these which
+//! has them:
+//!
+//! 1. the workspace definition
+//! 2. the project config
+//! 3. the global config
+//! 4. detected or built-in defaults
+//!
+//! Tables are merged key by key, so a workspace setting only `editor.gui` still gets the project's
+//! `editor.command`.
+//!
+//! A project config can change the commands a workspace runs, so it's only used once it's allowed
+//! with `project allow`, like direnv does with `.envrc` files. The allow list in the state
+//! directory keeps a hash of the contents of each allowed file, a changed file has to be allowed
+//! again.
+//!
+//! Project configs of remote workspaces are read over ssh when the workspace is created and by
+//! `refresh`, once they're older than `ssh.cache_ttl` seconds. Everything else uses the cached
+//! copy, so reading a definition never connects to its host. `--no-project-config` or
+//! `project_config = false` in a workspace definition ignore them.
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use atomicwrites::AtomicFile;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::task::JoinSet;
+
+use crate::cache::{self, Key};
+use crate::parse::{self, Format};
+use crate::workspace::{Editor, Nix, Python, Shell, Tmux, Workspace};
+use crate::{config, detect, runtime, shell, ssh};
+
+/// Name of the project config file in the workspace directory
+pub const FILE_NAME: &str = ".workspacectl.toml";
+
+/// Whether project configs are ignored for every workspace
+static IGNORE: AtomicBool = AtomicBool::new(false);
+
+/// Ignore project configs of all workspaces
+pub fn set_ignore(ignore: bool) {
+    IGNORE.store(ignore, Ordering::Relaxed);
+}
+
+/// Settings a project config can provide, the same tables as in workspace definitions
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Project {
+    /// Editor configuration
+    pub editor: Option<Editor>,
+
+    /// Shell configuration
+    pub shell: Option<Shell>,
+
+    /// tmux session, a relative `layout` is relative to the project directory
+    pub tmux: Option<Tmux>,
+
+    /// Nix environment
+    pub nix: Option<Nix>,
+
+    /// Python environment
+    pub python: Option<Python>,
+
+    /// Names of the secrets the project needs, see [`crate::secret`]
+    ///
+    /// They're asked for when a workspace is created for the project.
+    pub secrets: Option<Vec<String>>,
+}
+
+impl parse::Schema for Project {
+    fn known_keys(path: &[String]) -> &'static [&'static str] {
+        match path {
+            [] => parse::fields::<Project>(),
+            [table] if table == "editor" => parse::fields::<Editor>(),
+            [table] if table == "shell" => parse::fields::<Shell>(),
+            [table] if table == "tmux" => parse::fields::<Tmux>(),
+            [table] if table == "nix" => parse::fields::<Nix>(),
+            [table] if table == "python" => parse::fields::<Python>(),
+            _ => &[],
+        }
+    }
+}
+
+/// Merges the project config of `workspace` into it, settings of the workspace take precedence
+pub fn fill(workspace: Workspace) -> Result<Workspace> {
+    let Some(mut project) = read(&workspace)? else {
+        return Ok(workspace);
+    };
+    // Workspaces only know their secrets from the keyring.
+    project.secrets = None;
+    let name = workspace.name.clone();
+    let mut workspace = config::fill(workspace, project)?;
+    workspace.name = name;
+    Ok(workspace)
+}
+
+/// Returns whether the project config of `workspace` is used at all
+pub fn is_used(workspace: &Workspace) -> bool {
+    !IGNORE.load(Ordering::Relaxed) && workspace.project_config != Some(false)
+}
+
+/// Reads the project config in the directory of `workspace`
+///
+/// Returns `None` if there is none, it's ignored or it isn't allowed. Remote project configs are
+/// taken from the cache, `None` if they were never fetched, see [`fetch`].
+pub fn read(workspace: &Workspace) -> Result<Option<Project>> {
+    if !is_used(workspace) {
+        return Ok(None);
+    }
+    let Some((path, buf)) = contents(workspace)? else {
+        return Ok(None);
+    };
+    if !is_allowed(&path, &buf)? {
+        eprintln!(
+            "WARN ignoring project config {path:?} until it's allowed, review it with \
+             `wsctl project show {name}` and run `wsctl project allow {name}`",
+            name = workspace.name,
+        );
+        return Ok(None);
+    }
+    let mut project = parse::file::<Project>(&path, &buf, Format::Toml)
+        .with_context(|| format!("parsing project config {path:?}"))?;
+
+    if let Some(tmux) = &mut project.tmux {
+        tmux.layout = match (&tmux.layout, &workspace.ssh) {
+            (None, _) => None,
+            // Layouts are read locally, the project directory isn't.
+            (Some(_), Some(_)) => {
+                eprintln!(
+                    "WARN ignoring tmux.layout of project config {path:?}, \
+                     layouts of remote projects can't be read",
+                );
+                None
+            }
+            (Some(layout), None) => {
+                let dir = path.parent().expect("project config is in a directory");
+                Some(dir.join(layout).to_string_lossy().into_owned())
+            }
+        };
+    }
+    Ok(Some(project))
+}
+
+/// Returns the path and contents of the project config of `workspace`, `None` if there is none
+///
+/// Remote paths are given as `host:path`.
+pub fn contents(workspace: &Workspace) -> Result<Option<(PathBuf, String)>> {
+    let (path, buf) = match &workspace.ssh {
+        Some(ssh) => {
+            let path = PathBuf::from(format!("{}:{}/{FILE_NAME}", ssh.host, workspace.dir));
+            (path, cached(workspace))
+        }
+        None => {
+            let path = workspace.local_dir()?.join(FILE_NAME);
+            match fs::read_to_string(&path) {
+                Ok(buf) => (path, Some(buf)),
+                Err(err) if err.kind() == ErrorKind::NotFound => (path, None),
+                Err(err) => {
+                    return Err(err).with_context(|| format!("reading project config {path:?}"));
+                }
+            }
+        }
+    };
+    Ok(buf.map(|buf| (path, buf)))
+}
+
+/// Returns path to the allow list of project configs
+///
+/// Each line is the SHA-256 hash of an allowed file's contents and the file's path, separated by a
+/// tab.
+fn allowed_path() -> Result<PathBuf> {
+    Ok(cache::state_dir_path()?.join("allowed-projects"))
+}
+
+/// Returns the allow list entry of project config `path` with contents `buf`
+fn allow_entry(path: &Path, buf: &str) -> String {
+    let hash = Sha256::digest(buf.as_bytes());
+    format!("{hash:x}\t{}", path.to_string_lossy())
+}
+
+/// Reads the lines of the allow list
+fn allowed() -> Result<Vec<String>> {
+    let path = allowed_path()?;
+    match fs::read_to_string(&path) {
+        Ok(buf) => Ok(buf.lines().map(str::to_owned).collect()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("reading {path:?}")),
+    }
+}
+
+/// Returns whether project config `path` is allowed with contents `buf`
+pub fn is_allowed(path: &Path, buf: &str) -> Result<bool> {
+    let entry = allow_entry(path, buf);
+    Ok(allowed()?.contains(&entry))
+}
+
+/// Allows project config `path` with contents `buf`, replacing what was allowed for it before
+pub fn allow(path: &Path, buf: &str) -> Result<()> {
+    let entry = allow_entry(path, buf);
+    let mut lines = without(path)?;
+    lines.push(entry);
+    write_allowed(&lines)
+}
+
+/// Removes project config `path` from the allow list
+///
+/// Returns whether it was allowed.
+pub fn deny(path: &Path) -> Result<bool> {
+    let before = allowed()?.len();
+    let lines = without(path)?;
+    let removed = lines.len() < before;
+    if removed {
+        write_allowed(&lines)?;
+    }
+    Ok(removed)
+}
+
+/// Returns the allow list without the entries of project config `path`
+fn without(path: &Path) -> Result<Vec<String>> {
+    let path = path.to_string_lossy();
+    Ok(allowed()?
+        .into_iter()
+        .filter(|line| line.split_once('\t').map(|(_, allowed)| allowed) != Some(&path))
+        .collect())
+}
+
+/// Replaces the allow list with `lines`
+fn write_allowed(lines: &[String]) -> Result<()> {
+    let path = allowed_path()?;
+    let dir = path.parent().expect("allow list is in a directory");
+    fs::create_dir_all(dir).with_context(|| format!("creating directory {dir:?}"))?;
+    let buf = lines
+        .iter()
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+    AtomicFile::new(&path, atomicwrites::AllowOverwrite)
+        .write(|file| file.write_all(buf.as_bytes()))
+        .with_context(|| format!("writing {path:?}"))
+}
+
+/// Returns the cached contents of the project config of remote `workspace`, `None` if there is
+/// none or it was never fetched
+fn cached(workspace: &Workspace) -> Option<String> {
+    // A cache which can't be read is the same as one which was never written.
+    let cached = cache::read(Key::Project(workspace.name.clone())).ok()?;
+    serde_json::from_str(&cached).ok().flatten()
+}
+
+/// Returns whether the project config of remote `workspace` should be fetched again
+fn is_stale(workspace: &Workspace) -> Result<bool> {
+    let key = Key::Project(workspace.name.clone());
+    Ok(cache::read_fresh(key, detect::ttl()?)
+        .ok()
+        .flatten()
+        .is_none())
+}
+
+/// Fetches the project configs of the remote workspaces among `workspaces` whose cached copy is
+/// stale, concurrently
+///
+/// Returns the workspaces whose project config couldn't be fetched, with the error.
+pub async fn fetch_stale(workspaces: Vec<Arc<Workspace>>) -> Result<Vec<(String, anyhow::Error)>> {
+    let mut tasks = JoinSet::new();
+    for workspace in workspaces {
+        if workspace.ssh.is_none() || !is_used(&workspace) || !is_stale(&workspace)? {
+            continue;
+        }
+        tasks.spawn(async move {
+            let result = fetch(&workspace).await;
+            (workspace.name.clone(), result)
+        });
+    }
+    let mut failed = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        if let (name, Err(err)) = result.context("fetching project config")? {
+            failed.push((name, err));
+        }
+    }
+    Ok(failed)
+}
+
+/// Reads the project config of remote `workspace` over ssh and caches it
+pub async fn fetch(workspace: &Workspace) -> Result<()> {
+    let ssh = workspace.ssh.as_ref().expect("workspace is remote");
+    let key = Key::Project(workspace.name.clone());
+    let mut command = ssh::background(ssh, workspace.timeout)?;
+    let dir = shell::quote_dir(&workspace.dir);
+    // A missing file reads as empty, only ssh itself fails.
+    command.arg(format!(
+        "cd {dir} 2>/dev/null && cat {FILE_NAME} 2>/dev/null; exit 0"
+    ));
+    let output = runtime::output(command, runtime::timeout(workspace.timeout)?)
+        .await
+        .context("read remote project config")?
+        .with_context(|| format!("timed out connecting to {:?}", ssh.host))?;
+    if !output.status.success() {
+        return Err(ssh::failure(&ssh.host, &output)).context("read remote project config");
+    }
+    let buf = String::from_utf8(output.stdout).context("project config is not valid utf-8")?;
+    let buf = Some(buf).filter(|buf| !buf.trim().is_empty());
+    let json = serde_json::to_string(&buf).context("serializing project config")?;
+    cache::write(key, json).context("caching project config")
+}
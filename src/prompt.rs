@@ -0,0 +1,237 @@
+//! Interactive prompts on the terminal
+//!
+//! Paths are read with line editing and tab-completion of directories. Remote directories are
+//! listed over a background `ssh` connection, one connection per completed directory. Choices
+//! from a list are made in a fuzzy searchable list.
+//!
+//! Nothing is asked when stdin isn't a terminal or with `--no-input`, so scripts never hang on a
+//! prompt. Confirmations are then only given by `--yes` or `assume_yes` in the global config,
+//! everything else which needs an answer fails.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use dialoguer::FuzzySelect;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context as LineContext, Editor, Helper};
+
+use crate::workspace::Ssh;
+use crate::{config, process, runner, shell, ssh};
+
+/// Confirm everything without asking, `--yes`
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Never ask anything, `--no-input`
+static NO_INPUT: AtomicBool = AtomicBool::new(false);
+
+/// Confirm everything without asking with `yes`, never ask anything with `no_input`
+pub fn set_input(yes: bool, no_input: bool) {
+    ASSUME_YES.store(yes, Ordering::Relaxed);
+    NO_INPUT.store(no_input, Ordering::Relaxed);
+}
+
+/// Returns `true` if questions can be asked, stdin is a terminal and `--no-input` isn't set
+pub fn interactive() -> bool {
+    !NO_INPUT.load(Ordering::Relaxed) && io::stdin().is_terminal()
+}
+
+/// Asks a yes/no question on stderr, anything but an explicit yes is a no
+///
+/// `--yes` and `assume_yes` answer yes without asking. Fails if the question can't be asked.
+pub fn confirm(question: &str) -> Result<bool> {
+    let assume_yes = ASSUME_YES.load(Ordering::Relaxed)
+        || config::read()?
+            .and_then(|config| config.assume_yes)
+            .unwrap_or(false);
+    if assume_yes {
+        eprintln!("{question} [y/N] yes");
+        return Ok(true);
+    }
+    ensure!(
+        interactive(),
+        "can't ask {question:?} without a terminal, pass --yes to confirm",
+    );
+    eprint!("{question} [y/N] ");
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .context("reading confirmation from stdin")?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// How long listing a remote directory may take before completion gives up
+const LIST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Completes directory paths, locally or on a remote host
+enum PathHelper {
+    Local(FilenameCompleter),
+    Remote {
+        ssh: Box<Ssh>,
+        /// Subdirectories of already listed remote directories
+        listed: RefCell<HashMap<String, Vec<String>>>,
+    },
+}
+
+impl PathHelper {
+    /// Returns the subdirectories of remote directory `parent`, with a trailing `/`
+    ///
+    /// Failures, including timeouts, complete nothing.
+    fn remote_dirs(ssh: &Ssh, parent: &str, hidden: bool) -> Vec<String> {
+        let cd = match parent {
+            "" => "cd".to_owned(),
+            // Relative paths are relative to the remote `$HOME` like in the workspace definition.
+            parent => format!("cd && cd {}", shell::quote_dir(parent)),
+        };
+        let pattern = if hidden { ".*/" } else { "*/" };
+        let script =
+            format!("{cd} && for d in {pattern}; do [ -d \"$d\" ] && printf '%s\\n' \"$d\"; done");
+        let output = ssh::background(ssh, None).and_then(|mut command| {
+            command.arg(script);
+            process::output_with_timeout(command, LIST_TIMEOUT)
+        });
+        match output {
+            Ok(Some(output)) => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter(|dir| !matches!(*dir, "./" | "../"))
+                .map(str::to_owned)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Completer for PathHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &LineContext<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (ssh, listed) = match self {
+            PathHelper::Local(completer) => {
+                let (start, mut candidates) = completer.complete(line, pos, ctx)?;
+                candidates.retain(|pair| pair.replacement.ends_with(std::path::MAIN_SEPARATOR));
+                return Ok((start, candidates));
+            }
+            PathHelper::Remote { ssh, listed } => (ssh, listed),
+        };
+        let line = &line[..pos];
+        let (parent, prefix) = match line.rfind('/') {
+            Some(slash) => line.split_at(slash + 1),
+            None => ("", line),
+        };
+        let hidden = prefix.starts_with('.');
+        let mut listed = listed.borrow_mut();
+        let dirs = listed
+            .entry(format!("{hidden}:{parent}"))
+            .or_insert_with(|| PathHelper::remote_dirs(ssh, parent, hidden));
+        let candidates = dirs
+            .iter()
+            .filter(|dir| dir.starts_with(prefix))
+            .map(|dir| Pair {
+                display: dir.clone(),
+                replacement: dir.clone(),
+            })
+            .collect();
+        Ok((parent.len(), candidates))
+    }
+}
+
+impl Hinter for PathHelper {
+    type Hint = String;
+}
+
+impl Highlighter for PathHelper {}
+
+impl Validator for PathHelper {}
+
+impl Helper for PathHelper {}
+
+/// Reads a path with tab-completion of directories on `ssh`'s host, or local ones without one
+pub fn path(prompt: &str, ssh: Option<&Ssh>) -> Result<String> {
+    ensure!(
+        interactive(),
+        "can't prompt for a path without a terminal, pass it as an argument"
+    );
+    let helper = match ssh {
+        Some(ssh) => PathHelper::Remote {
+            ssh: Box::new(ssh.clone()),
+            listed: RefCell::default(),
+        },
+        None => PathHelper::Local(FilenameCompleter::new()),
+    };
+    let mut editor = Editor::<PathHelper, DefaultHistory>::new().context("open terminal")?;
+    editor.set_helper(Some(helper));
+    match editor.readline(prompt) {
+        Ok(path) if path.trim().is_empty() => bail!("no path given"),
+        Ok(path) => Ok(path.trim().to_owned()),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => bail!("aborted"),
+        Err(err) => Err(err).context("reading path"),
+    }
+}
+
+/// Asks to pick one of `items` from a fuzzy searchable list on stderr, returns its index
+///
+/// The first item is selected initially.
+pub fn pick(prompt: &str, items: &[String]) -> Result<usize> {
+    ensure!(!items.is_empty(), "nothing to pick from");
+    ensure!(
+        interactive(),
+        "can't show a picker without a terminal, pass a name instead"
+    );
+    let picked = FuzzySelect::new()
+        .with_prompt(prompt)
+        .items(items)
+        .default(0)
+        .interact_opt()
+        .context("showing picker")?;
+    picked.context("aborted")
+}
+
+/// Opens `draft` in `editor` until its contents pass `check`, returns them
+///
+/// An invalid draft is opened again if the user wants to fix it, or else kept for the next edit.
+/// `what` names the edited file in messages, like `config`.
+pub fn edit(
+    editor: &str,
+    draft: &Path,
+    what: &str,
+    check: impl Fn(&Path, &str) -> Result<()>,
+) -> Result<String> {
+    loop {
+        let mut args = editor.split_whitespace();
+        let program = args.next().context("editor command is empty")?;
+        let mut command = Command::new(program);
+        command.args(args).arg(draft);
+        let status = runner::status(&mut command).with_context(|| format!("run {program}"))?;
+        ensure!(
+            status.success(),
+            "{program} failed, the draft is kept at {draft:?}"
+        );
+        let buf =
+            fs::read_to_string(draft).with_context(|| format!("reading draft at {draft:?}"))?;
+        match check(draft, &buf) {
+            Ok(()) => return Ok(buf),
+            Err(err) => {
+                eprintln!("ERR  {err:#}");
+                if !interactive() || !confirm(&format!("edit {what} again?"))? {
+                    bail!("{what} not saved, the draft is kept at {draft:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Python environments of workspaces
+//!
+//! Workspaces with a `[python]` table run their terminals and editors with a virtualenv activated,
+//! the way its `activate` script would, or inside a conda environment through `conda run`.
+
+use std::path::Path;
+use std::{env, iter};
+
+use anyhow::{Context, Result};
+
+use crate::shell;
+
+/// Returns the variables activating virtualenv `venv` of local workspace directory `dir`
+pub fn venv_env(venv: &str, dir: &Path) -> Result<Vec<(String, String)>> {
+    let venv = dir.join(venv);
+    let bin = venv.join(if cfg!(windows) { "Scripts" } else { "bin" });
+    let path = env::var_os("PATH").unwrap_or_default();
+    let path = env::join_paths(iter::once(bin).chain(env::split_paths(&path)))
+        .context("adding virtualenv to PATH")?;
+    Ok(vec![
+        (
+            "VIRTUAL_ENV".to_owned(),
+            venv.to_string_lossy().into_owned(),
+        ),
+        ("PATH".to_owned(), path.to_string_lossy().into_owned()),
+    ])
+}
+
+/// Returns a POSIX script activating virtualenv `venv`, relative to the current directory
+pub fn venv_script(venv: &str) -> String {
+    let venv = match venv.starts_with('/') {
+        true => shell::quote(venv),
+        false => format!("\"$PWD\"/{}", shell::quote(venv)),
+    };
+    format!("export VIRTUAL_ENV={venv}; export PATH=\"$VIRTUAL_ENV/bin:$PATH\"; ")
+}
+
+/// Returns `program` with `args` run in conda environment `name`, as a program and arguments
+pub fn conda_wrap(name: &str, program: &str, args: &[String]) -> (String, Vec<String>) {
+    let mut wrapped = ["run", "-n", name, "--no-capture-output", program]
+        .map(str::to_owned)
+        .to_vec();
+    wrapped.extend(args.iter().cloned());
+    ("conda".to_owned(), wrapped)
+}
+
+/// Returns POSIX command line `line` run in conda environment `name`
+pub fn conda_wrap_line(name: &str, line: &str) -> String {
+    format!(
+        "conda run -n {} --no-capture-output {line}",
+        shell::quote(name)
+    )
+}
@@ -0,0 +1,189 @@
+//! Running external programs
+//!
+//! Programs are run through the [`Runner`] set with [`set_runner`], so the commands built for
+//! terminals, editors, `ssh` and the rest can be inspected without running them. `--print-cmd`
+//! sets [`Print`], which prints the commands starting programs instead. Commands which only query
+//! something, like detecting the shell of a remote host, still run so the printed commands are the
+//! ones which would really run. Nothing else changes state while commands are printed either,
+//! see [`is_dry_run`].
+//!
+//! Only `age` is run directly, it may have to ask for a passphrase on the terminal.
+
+use std::io;
+use std::process::{Child, Command, ExitStatus, Output};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{runtime, shell};
+
+/// Runs commands built by `workspacectl`
+pub trait Runner: Send + Sync {
+    /// Starts `command` without waiting for it to finish
+    ///
+    /// Returns `None` if nothing was started.
+    fn spawn(&self, command: &mut Command) -> io::Result<Option<Child>>;
+
+    /// Runs query `command` to completion capturing its output, killing it after `timeout`
+    ///
+    /// The command's stdin is closed. Returns `None` if the command didn't finish in time.
+    fn output(&self, command: Command, timeout: Duration) -> Result<Option<Output>>;
+
+    /// Runs `command` in place of this process
+    ///
+    /// Returns only if `command` wasn't run in place of this process.
+    fn exec(&self, command: &mut Command) -> Result<()>;
+
+    /// Whether commands are only shown instead of run, so nothing may change state
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+}
+
+/// Runs commands on this machine
+#[derive(Debug, Clone, Copy)]
+pub struct System;
+
+impl Runner for System {
+    fn spawn(&self, command: &mut Command) -> io::Result<Option<Child>> {
+        let child = command.spawn();
+        match &child {
+            Ok(child) => tracing::debug!(pid = child.id(), "spawned"),
+            Err(err) => tracing::warn!(%err, "spawn failed"),
+        }
+        child.map(Some)
+    }
+
+    fn output(&self, command: Command, timeout: Duration) -> Result<Option<Output>> {
+        let output = runtime::block_on(runtime::output(command, timeout))?;
+        match &output {
+            Ok(Some(output)) => tracing::debug!(status = %output.status, "finished"),
+            Ok(None) => tracing::warn!(?timeout, "timed out"),
+            Err(err) => tracing::warn!(err = format!("{err:#}"), "failed"),
+        }
+        output
+    }
+
+    fn exec(&self, command: &mut Command) -> Result<()> {
+        let program = command.get_program().to_string_lossy().into_owned();
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            // Only returns on failure.
+            let err = command.exec();
+            Err(err).with_context(|| format!("exec {program:?}"))
+        }
+        #[cfg(not(unix))]
+        {
+            let status = command
+                .status()
+                .with_context(|| format!("run {program:?}"))?;
+            std::process::exit(status.code().unwrap_or(1));
+        }
+    }
+}
+
+/// Prints commands starting programs to stdout instead of running them, one per line, as POSIX
+/// shell command lines
+#[derive(Debug, Clone, Copy)]
+pub struct Print;
+
+impl Print {
+    fn print(command: &Command) {
+        let args = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy());
+        println!("{}", shell::join(args));
+    }
+}
+
+impl Runner for Print {
+    fn spawn(&self, command: &mut Command) -> io::Result<Option<Child>> {
+        Print::print(command);
+        Ok(None)
+    }
+
+    fn output(&self, command: Command, timeout: Duration) -> Result<Option<Output>> {
+        System.output(command, timeout)
+    }
+
+    fn exec(&self, command: &mut Command) -> Result<()> {
+        Print::print(command);
+        Ok(())
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+/// Runner set by [`set_runner`]
+static RUNNER: OnceLock<Box<dyn Runner>> = OnceLock::new();
+
+/// Run all commands through `runner`, instead of running them on this machine
+///
+/// Fails if the runner was already set or used.
+pub fn set_runner(runner: impl Runner + 'static) -> Result<()> {
+    RUNNER
+        .set(Box::new(runner))
+        .map_err(|_| anyhow!("the runner is already set"))
+}
+
+fn runner() -> &'static dyn Runner {
+    &**RUNNER.get_or_init(|| Box::new(System))
+}
+
+/// Whether commands are only shown instead of run, see [`Runner::is_dry_run`]
+pub fn is_dry_run() -> bool {
+    runner().is_dry_run()
+}
+
+/// Returns a span for running `command`, recording its program and number of arguments
+///
+/// The arguments themselves aren't recorded, remote commands and editor invocations can carry
+/// values which don't belong in a log file.
+fn span(kind: &'static str, command: &Command) -> tracing::Span {
+    tracing::debug_span!(
+        "process",
+        kind,
+        program = %command.get_program().to_string_lossy(),
+        args = command.get_args().len(),
+    )
+}
+
+/// Starts `command` without waiting for it, see [`Runner::spawn`]
+pub fn spawn(command: &mut Command) -> io::Result<Option<Child>> {
+    let _span = span("spawn", command).entered();
+    runner().spawn(command)
+}
+
+/// Runs `command` to completion like [`Command::status`]
+///
+/// A command which isn't run succeeds.
+pub fn status(command: &mut Command) -> io::Result<ExitStatus> {
+    let span = span("status", command);
+    let child = span.in_scope(|| runner().spawn(command))?;
+    let _span = span.entered();
+    match child {
+        Some(mut child) => {
+            let status = child.wait()?;
+            tracing::debug!(%status, "finished");
+            Ok(status)
+        }
+        None => Ok(ExitStatus::default()),
+    }
+}
+
+/// Runs query `command` capturing its output, see [`Runner::output`]
+pub fn output(command: Command, timeout: Duration) -> Result<Option<Output>> {
+    let _span = span("output", &command).entered();
+    runner().output(command, timeout)
+}
+
+/// Runs `command` in place of this process, see [`Runner::exec`]
+pub fn exec(command: &mut Command) -> Result<()> {
+    let _span = span("exec", command).entered();
+    tracing::debug!("exec");
+    runner().exec(command)
+}
@@ -0,0 +1,110 @@
+//! Execution core for commands which talk to the network
+//!
+//! Everything which may wait on a remote machine, ssh invocations above all, runs its child
+//! processes through [`output`] on a tokio runtime instead of blocking on them one by one. This
+//! gives every invocation the same handling:
+//!
+//! - a timeout after which the child is killed, configured by `timeout` in the global config or
+//!   the workspace, see [`timeout`]
+//! - at most [`MAX_CONCURRENT`] children at a time, so probing many workspaces at once doesn't
+//!   open dozens of ssh connections
+//! - cancellation, a child is killed as soon as the operation waiting on it is dropped, whether
+//!   it's because it timed out or because a sibling operation failed
+//!
+//! Ctrl-C interrupts the whole foreground process group, which the children are part of, so they
+//! exit together with `workspacectl` without any extra handling.
+
+use std::future::Future;
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+use anyhow::{ensure, Context, Result};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+
+use crate::config;
+
+/// Maximum number of child processes run at the same time
+pub const MAX_CONCURRENT: usize = 8;
+
+/// Timeout of non-interactive commands when none is configured
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Permits for running child processes
+static PERMITS: Semaphore = Semaphore::const_new(MAX_CONCURRENT);
+
+/// Returns how long non-interactive commands may take
+///
+/// `configured` is the workspace's `timeout`, the global `timeout` is used without one. Fails for
+/// a timeout of 0, which would give up on everything right away.
+pub fn timeout(configured: Option<u64>) -> Result<Duration> {
+    let configured = match configured {
+        Some(secs) => Some(secs),
+        None => config::read()?.and_then(|config| config.timeout),
+    };
+    ensure!(configured != Some(0), "timeout must be at least 1 second");
+    Ok(configured.map_or(DEFAULT_TIMEOUT, Duration::from_secs))
+}
+
+/// Runs `future` to completion on a new runtime
+pub fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("starting async runtime")?;
+    Ok(runtime.block_on(future))
+}
+
+/// Runs `command` to completion capturing its output, killing it after `timeout`
+///
+/// The command's stdin is closed. Returns `None` if the command didn't finish in time.
+pub async fn output(command: Command, timeout: Duration) -> Result<Option<Output>> {
+    run(command, None, timeout).await
+}
+
+/// Runs `command` like [`output`], writing `input` to its stdin and then closing it
+pub async fn output_with_input(
+    command: Command,
+    input: &[u8],
+    timeout: Duration,
+) -> Result<Option<Output>> {
+    run(command, Some(input), timeout).await
+}
+
+async fn run(command: Command, input: Option<&[u8]>, timeout: Duration) -> Result<Option<Output>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut command = tokio::process::Command::from(command);
+    command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let _permit = PERMITS.acquire().await.expect("semaphore is never closed");
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("spawn {program}"))?;
+    let stdin = child.stdin.take();
+    let finish = async {
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            stdin
+                .write_all(input)
+                .await
+                .with_context(|| format!("write to {program}"))?;
+            // Dropping stdin closes it.
+        }
+        child
+            .wait_with_output()
+            .await
+            .with_context(|| format!("wait for {program}"))
+    };
+    match tokio::time::timeout(timeout, finish).await {
+        Ok(output) => output.map(Some),
+        // Dropping the child kills it.
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,207 @@
+//! Per-workspace secrets stored in the platform keyring
+//!
+//! Each secret is a keyring entry with service `workspacectl:<workspace>` and the secret key as the
+//! user. Keyrings can't be enumerated portably so the names of the keys set for a workspace are kept
+//! in an index entry with service `workspacectl` and the workspace name as the user.
+//!
+//! Secrets are exported as environment variables to everything spawned in the workspace. They are
+//! never put on a command line, where other users could see them. Local programs get them in their
+//! environment. Shells which can only be given a command line, remote ones above all, source them
+//! from a file only the user can read, which deletes itself once sourced, see [`private_file`].
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{ensure, Context, Result};
+use keyring::Entry;
+
+use crate::{runner, shell};
+
+/// Keyring service under which the index entries are stored
+const SERVICE: &str = "workspacectl";
+
+/// Returns the keyring entry of secret `key` of workspace `workspace`
+fn entry(workspace: &str, key: &str) -> Result<Entry> {
+    Entry::new(&format!("{SERVICE}:{workspace}"), key)
+        .with_context(|| format!("opening keyring entry for secret {key:?}"))
+}
+
+/// Returns the keyring entry holding the names of secrets of workspace `workspace`
+fn index_entry(workspace: &str) -> Result<Entry> {
+    Entry::new(SERVICE, workspace).context("opening keyring index entry")
+}
+
+/// Checks `key` is usable as an environment variable name
+pub fn check_key(key: &str) -> Result<()> {
+    ensure!(
+        shell::is_name(key),
+        "secret key {key:?} is not a valid environment variable name",
+    );
+    Ok(())
+}
+
+/// Lists the names of secrets set for workspace `workspace`
+pub fn list(workspace: &str) -> Result<Vec<String>> {
+    match index_entry(workspace)?.get_password() {
+        Ok(index) => Ok(index.lines().map(str::to_owned).collect()),
+        Err(keyring::Error::NoEntry) => Ok(Vec::new()),
+        Err(err) => Err(err).context("reading keyring index entry"),
+    }
+}
+
+/// Lists the names of secrets set for workspace `workspace`, if there's a keyring at all
+///
+/// Without a keyring no secrets could have been set, so this doesn't fail on machines which
+/// don't have one.
+fn stored_keys(workspace: &str) -> Result<Vec<String>> {
+    match list(workspace) {
+        Err(err) if matches!(err.downcast_ref(), Some(keyring::Error::PlatformFailure(_))) => {
+            Ok(Vec::new())
+        }
+        result => result,
+    }
+}
+
+/// Replaces the names of secrets set for workspace `workspace`
+fn write_list(workspace: &str, keys: &[String]) -> Result<()> {
+    let entry = index_entry(workspace)?;
+    if keys.is_empty() {
+        return match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(err).context("removing keyring index entry"),
+        };
+    }
+    entry
+        .set_password(&keys.join("\n"))
+        .context("writing keyring index entry")
+}
+
+/// Sets secret `key` of workspace `workspace` to `value`
+pub fn set(workspace: &str, key: &str, value: &str) -> Result<()> {
+    check_key(key)?;
+    entry(workspace, key)?
+        .set_password(value)
+        .with_context(|| format!("storing secret {key:?} in the keyring"))?;
+    let mut keys = list(workspace)?;
+    if !keys.iter().any(|existing| existing == key) {
+        keys.push(key.to_owned());
+        keys.sort();
+        write_list(workspace, &keys)?;
+    }
+    Ok(())
+}
+
+/// Removes secret `key` of workspace `workspace`
+pub fn remove(workspace: &str, key: &str) -> Result<()> {
+    let mut keys = list(workspace)?;
+    ensure!(
+        keys.iter().any(|existing| existing == key),
+        "workspace {workspace:?} has no secret {key:?}",
+    );
+    match entry(workspace, key)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(err) => {
+            return Err(err).with_context(|| format!("removing secret {key:?} from the keyring"))
+        }
+    }
+    keys.retain(|existing| existing != key);
+    write_list(workspace, &keys)
+}
+
+/// Returns all secrets of workspace `workspace` as environment variable assignments
+pub fn env(workspace: &str) -> Result<Vec<(String, String)>> {
+    stored_keys(workspace)?
+        .into_iter()
+        .map(|key| {
+            let value = entry(workspace, &key)?
+                .get_password()
+                .with_context(|| format!("reading secret {key:?} from the keyring"))?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Moves all secrets of workspace `old` to workspace `new`
+pub fn rename(old: &str, new: &str) -> Result<()> {
+    let keys = stored_keys(old)?;
+    for key in &keys {
+        let value = entry(old, key)?
+            .get_password()
+            .with_context(|| format!("reading secret {key:?} from the keyring"))?;
+        set(new, key, &value)?;
+    }
+    for key in &keys {
+        remove(old, key)?;
+    }
+    Ok(())
+}
+
+/// Removes all secrets of workspace `workspace`
+pub fn clear(workspace: &str) -> Result<()> {
+    for key in stored_keys(workspace)? {
+        remove(workspace, &key)?;
+    }
+    Ok(())
+}
+
+/// Stands for the file holding the exports in commands which are only printed
+const PLACEHOLDER: &str = "<secrets>";
+
+/// Number of private files written by this process, to keep their names unique
+static FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns POSIX shell code exporting `env`, one variable per line
+pub fn exports(env: &[(String, String)]) -> String {
+    env.iter()
+        .map(|(key, value)| format!("export {key}={}\n", shell::quote(value)))
+        .collect()
+}
+
+/// Returns a POSIX shell command sourcing the file at `path`
+pub fn source(path: &str) -> String {
+    format!(". {}", shell::quote(path))
+}
+
+/// Returns the command sourcing a private file in commands which are only printed
+pub fn placeholder() -> String {
+    source(PLACEHOLDER)
+}
+
+/// Writes code exporting `env` to a new file only the user can read, which deletes itself once
+/// sourced
+///
+/// Returns the command sourcing the file. Nothing is written while commands are only printed, the
+/// command then sources a placeholder.
+pub fn private_file(env: &[(String, String)]) -> Result<String> {
+    if runner::is_dry_run() {
+        return Ok(placeholder());
+    }
+    let id = format!(
+        "{}-{}",
+        process::id(),
+        FILES.fetch_add(1, Ordering::Relaxed)
+    );
+    let dir = dirs::runtime_dir().unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!("workspacectl-env-{id}"));
+    let path_str = path.to_string_lossy().into_owned();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("creating {path:?}"))?;
+    let written = file
+        .write_all(exports(env).as_bytes())
+        .and_then(|()| writeln!(file, "rm -f {}", shell::quote(&path_str)));
+    if let Err(err) = written {
+        let _ = fs::remove_file(&path);
+        return Err(err).with_context(|| format!("writing {path:?}"));
+    }
+    Ok(source(&path_str))
+}
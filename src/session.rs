@@ -0,0 +1,201 @@
+//! Current workspace of each terminal session
+//!
+//! Opening a workspace makes it the current one of the terminal session it's opened from, so
+//! `terminal` and `editor` in another terminal keep using the workspace opened there. Sessions are
+//! identified by `$WORKSPACECTL_SESSION`, or else by the controlling terminal, and their current
+//! workspace is stored as [`Key::Session`].
+//!
+//! Every `open` also sets the global current workspace, [`Key::Current`]. Sessions which haven't
+//! opened anything, and commands run without a terminal like from a keyboard shortcut, use that.
+//! `open --global` forgets the workspaces of all sessions, so every session uses the global one.
+//!
+//! Terminal devices are reused once closed, a workspace stored for a device before it was
+//! allocated again belongs to an earlier session and is ignored, and removed by `gc` like those
+//! of devices which don't exist anymore.
+
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use anyhow::Result;
+
+use crate::cache::{self, Key, Transaction};
+
+/// Prefix of the names of sessions identified by their controlling terminal
+const TTY_PREFIX: &str = "tty-";
+
+/// Prefix of the names of sessions identified by `$WORKSPACECTL_SESSION`
+const ENV_PREFIX: &str = "env-";
+
+/// The terminal session `workspacectl` runs in
+struct Session {
+    /// Name of the session's key
+    name: String,
+
+    /// Controlling terminal the session is identified by, if not by `$WORKSPACECTL_SESSION`
+    tty: Option<PathBuf>,
+}
+
+/// Returns the session `workspacectl` runs in, `None` outside of one
+fn this() -> Option<&'static Session> {
+    static SESSION: OnceLock<Option<Session>> = OnceLock::new();
+    SESSION
+        .get_or_init(|| {
+            // Only the names of terminal sessions are device paths `ended` may stat.
+            let session = match std::env::var("WORKSPACECTL_SESSION") {
+                Ok(id) if !id.is_empty() => Session {
+                    name: format!("{ENV_PREFIX}{}", cache::encode_file_name(&id)),
+                    tty: None,
+                },
+                _ => {
+                    let tty = tty()?;
+                    let id = cache::encode_file_name(&tty.to_string_lossy());
+                    Session {
+                        name: format!("{TTY_PREFIX}{id}"),
+                        tty: Some(tty),
+                    }
+                }
+            };
+            Some(session)
+        })
+        .as_ref()
+}
+
+/// Returns the path of the controlling terminal, `None` if stdin isn't one
+fn tty() -> Option<PathBuf> {
+    if cfg!(windows) || !io::stdin().is_terminal() {
+        return None;
+    }
+    if let Ok(path) = fs::read_link("/proc/self/fd/0") {
+        return Some(path);
+    }
+    let output = Command::new("tty")
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let path = String::from_utf8(output.stdout).ok()?;
+    output.status.success().then(|| PathBuf::from(path.trim()))
+}
+
+/// Returns `true` if session `name` was identified by a terminal which has been closed since its
+/// workspace was stored
+///
+/// Sessions identified by `$WORKSPACECTL_SESSION` never end, nothing tells when they do. Sessions
+/// named by earlier versions, which nothing reads anymore, count as ended.
+pub fn ended(name: &str) -> bool {
+    if name.starts_with(ENV_PREFIX) {
+        return false;
+    }
+    let Some(device) = name.strip_prefix(TTY_PREFIX) else {
+        return true;
+    };
+    if !cfg!(unix) {
+        return false;
+    }
+    let tty = PathBuf::from(cache::decode_file_name(device));
+    if !tty.is_absolute() || !tty.exists() {
+        return true;
+    }
+    // Keep what can't be checked.
+    let key = Key::Session(name.to_owned());
+    matches!(cache::read_since(key, allocated(&tty)), Ok(None))
+}
+
+/// Returns when terminal device `tty` was allocated
+#[cfg(unix)]
+fn allocated(tty: &std::path::Path) -> Option<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Writing to a terminal changes its modification time but not its change time.
+    let ctime = fs::metadata(tty).ok()?.ctime();
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(ctime).ok()?))
+}
+
+#[cfg(not(unix))]
+fn allocated(_tty: &std::path::Path) -> Option<SystemTime> {
+    None
+}
+
+/// Returns the name of the current workspace, of this session or else the global one
+pub fn current() -> Result<String> {
+    if let Some(session) = this() {
+        let key = Key::Session(session.name.clone());
+        let since = session.tty.as_deref().and_then(allocated);
+        // A cache which can't be read falls back like a missing one.
+        if let Some(name) = cache::read_since(key, since).ok().flatten() {
+            return Ok(name);
+        }
+    }
+    cache::read(Key::Current)
+}
+
+/// Makes `name` the current workspace of this session and the global one
+///
+/// With `global` the workspaces of all sessions are forgotten instead.
+pub fn open(transaction: &mut Transaction, name: &str, global: bool) -> Result<()> {
+    transaction.write(Key::Current, name);
+    if global {
+        for session in cache::sessions()? {
+            transaction.remove(Key::Session(session));
+        }
+    } else if let Some(session) = this() {
+        transaction.write(Key::Session(session.name.clone()), name);
+    }
+    Ok(())
+}
+
+/// Returns the current workspaces `open` changes without `global`, to [`restore`] them later
+pub fn snapshot() -> Vec<(Key, Option<String>)> {
+    let mut keys = vec![Key::Current];
+    keys.extend(this().map(|session| Key::Session(session.name.clone())));
+    keys.into_iter()
+        .map(|key| (key.clone(), cache::read(key).ok()))
+        .collect()
+}
+
+/// Restores the current workspaces saved by [`snapshot`]
+pub fn restore(transaction: &mut Transaction, snapshot: Vec<(Key, Option<String>)>) {
+    for (key, name) in snapshot {
+        match name {
+            Some(name) => transaction.write(key, &name),
+            None => transaction.remove(key),
+        };
+    }
+}
+
+/// Returns the global current workspace and that of every session, with the keys storing them
+///
+/// Sessions which [`ended`] are included.
+pub fn all() -> Result<Vec<(Key, String)>> {
+    let mut keys = vec![Key::Current];
+    keys.extend(cache::sessions()?.into_iter().map(Key::Session));
+    Ok(keys
+        .into_iter()
+        .filter_map(|key| Some((key.clone(), cache::read(key).ok()?)))
+        .collect())
+}
+
+/// Makes workspace `new` current wherever `old` is
+pub fn rename(transaction: &mut Transaction, old: &str, new: &str) -> Result<()> {
+    for (key, name) in all()? {
+        if name == old {
+            transaction.write(key, new);
+        }
+    }
+    Ok(())
+}
+
+/// Unsets workspace `name` wherever it's current
+pub fn forget(transaction: &mut Transaction, name: &str) -> Result<()> {
+    for (key, current) in all()? {
+        if current == name {
+            transaction.remove(key);
+        }
+    }
+    Ok(())
+}
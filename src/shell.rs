@@ -0,0 +1,144 @@
+//! Helpers for building shell command lines
+//!
+//! Used to build the commands which are run on remote hosts through `ssh`, and to invoke the
+//! shells of workspaces, which don't all take the same flags. Scripts `workspacectl` runs itself
+//! are POSIX, only command lines run by the workspace shell are written for its [`Kind`].
+
+use std::path::Path;
+
+/// Quotes `arg` so a POSIX shell treats it as a single word
+///
+/// Arguments consisting only of characters without a special meaning are left as they are.
+pub fn quote(arg: &str) -> String {
+    let is_plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || "_-./=:,+@%".contains(ch));
+    if is_plain {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Quotes directory `dir` like [`quote`], leaving a leading `~/` unquoted so it's still expanded
+pub fn quote_dir(dir: &str) -> String {
+    match dir.strip_prefix("~/") {
+        Some("") => "~/".to_owned(),
+        Some(rest) => format!("~/{}", quote(rest)),
+        None if dir == "~" => dir.to_owned(),
+        None => quote(dir),
+    }
+}
+
+/// Returns `true` if `name` can be used as a shell variable name
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// Quotes each argument and joins them into a single command line
+pub fn join<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    args.into_iter()
+        .map(|arg| quote(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the flag making shell `command` a login shell
+///
+/// `-l` is understood by most shells, those which spell it differently are listed.
+pub fn login_flag(command: &str) -> &'static str {
+    match program(command) {
+        "bash" | "zsh" | "fish" | "nu" => "--login",
+        "pwsh" | "powershell" => "-Login",
+        _ => "-l",
+    }
+}
+
+/// Syntax of a shell's command lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `sh` and the shells extending it
+    Posix,
+
+    /// fish, whose single quotes take backslash escapes
+    Fish,
+
+    /// Nushell, whose single quotes don't take any escapes
+    Nushell,
+
+    /// PowerShell, whose single quotes escape a quote by doubling it
+    PowerShell,
+}
+
+impl Kind {
+    /// Returns the syntax of shell `command`
+    pub fn of(command: &str) -> Kind {
+        match program(command) {
+            "fish" => Kind::Fish,
+            "nu" => Kind::Nushell,
+            "pwsh" | "powershell" => Kind::PowerShell,
+            _ => Kind::Posix,
+        }
+    }
+
+    /// Quotes `arg` so the shell treats it as a single word
+    pub fn quote(self, arg: &str) -> String {
+        let is_plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || "_-./=:+%".contains(ch));
+        match self {
+            Kind::Posix => quote(arg),
+            _ if is_plain => arg.to_owned(),
+            Kind::Fish => format!("'{}'", arg.replace('\\', r"\\").replace('\'', r"\'")),
+            // Raw strings can hold any quote, their delimiter grows until it's not in `arg`.
+            Kind::Nushell if arg.contains('\'') => {
+                let mut hashes = "#".to_owned();
+                while arg.contains(&format!("'{hashes}")) {
+                    hashes.push('#');
+                }
+                format!("r{hashes}'{arg}'{hashes}")
+            }
+            Kind::Nushell => format!("'{arg}'"),
+            Kind::PowerShell => format!("'{}'", arg.replace('\'', "''")),
+        }
+    }
+
+    /// Quotes each argument and joins them into a single command line
+    pub fn join<I, S>(self, args: I) -> String
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        args.into_iter()
+            .map(|arg| self.quote(arg.as_ref()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns the flag making the shell run a command line and exit
+    pub fn command_flag(self) -> &'static str {
+        match self {
+            Kind::PowerShell => "-Command",
+            Kind::Posix | Kind::Fish | Kind::Nushell => "-c",
+        }
+    }
+}
+
+/// Returns the name of the program shell `command` runs, without its directory and extension
+pub fn program(command: &str) -> &str {
+    let program = command.split_whitespace().next().unwrap_or_default();
+    Path::new(program)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(program)
+}
@@ -0,0 +1,544 @@
+//! Building `ssh` invocations
+//!
+//! Background invocations, whose output is captured by `workspacectl`, can't prompt for a
+//! passphrase or password on the terminal. They run in batch mode and fail fast instead of hanging,
+//! unless an askpass program is configured with `ssh.askpass` in the global config, in which case
+//! it's asked for credentials.
+//!
+//! All invocations apply the host key verification policy of the workspace, or the global
+//! `ssh.host_key`, instead of depending on what the user's ssh config does. They also give up
+//! connecting after the configured `timeout`.
+//!
+//! Interactive sessions opened in a terminal also get the terminfo and locale handling configured
+//! by `ssh.terminfo`, `ssh.term` and `ssh.locale`.
+//!
+//! Every invocation connects with the `user`, `port` and `identity_file` of the workspace's
+//! `[ssh]` table, and runs its `command` instead of `ssh` if set. The command can be a wrapper like
+//! `tsh ssh` or `gcloud compute ssh`, whose flag syntax is given by the `args` templates, see
+//! [`Ssh::args`]. Files are only copied with commands which take ssh's arguments.
+//!
+//! Workspaces with `kind = "teleport"` connect with `tsh ssh` and copy files with `tsh scp`
+//! instead. They need a valid Teleport session, which is checked with `tsh status` before the first
+//! connection and reported with a hint to run `tsh login`.
+
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::launch::Window;
+use crate::workspace::{HostKey, Ssh, SshKind, Terminfo};
+use crate::{config, process, runner, runtime, secret, shell, template};
+
+/// Messages `ssh` prints when it couldn't authenticate
+const AUTH_ERRORS: &[&str] = &[
+    "Permission denied",
+    "Authentication failed",
+    "Too many authentication failures",
+];
+
+/// Messages `ssh` prints when it refused the host key
+const HOST_KEY_ERRORS: &[&str] = &[
+    "REMOTE HOST IDENTIFICATION HAS CHANGED",
+    "Host key verification failed",
+];
+
+/// Messages `ssh` prints when it couldn't reach the host at all
+const UNREACHABLE_ERRORS: &[&str] = &[
+    "Could not resolve hostname",
+    "Connection refused",
+    "Connection timed out",
+    "No route to host",
+    "Network is unreachable",
+];
+
+/// Returns the ssh section of the global config
+fn global() -> Result<config::Ssh> {
+    Ok(config::read()?
+        .and_then(|config| config.ssh)
+        .unwrap_or_default())
+}
+
+/// Returns `true` if `ssh` connects through Teleport
+fn is_teleport(ssh: &Ssh) -> bool {
+    ssh.kind == Some(SshKind::Teleport)
+}
+
+/// Whether [`teleport_login`] found a valid session
+static TELEPORT_LOGGED_IN: AtomicBool = AtomicBool::new(false);
+
+/// Fails with a hint to run `tsh login` unless there's a valid Teleport session for `ssh`'s host
+///
+/// The session is checked with `tsh status`, once per run, and not at all while commands are only
+/// printed.
+fn teleport_login(ssh: &Ssh) -> Result<()> {
+    if runner::is_dry_run() || TELEPORT_LOGGED_IN.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let mut command = Command::new("tsh");
+    command.arg("status");
+    let output = process::output_with_timeout(command, runtime::timeout(None)?)
+        .context("checking the Teleport session")?
+        .context("timed out running tsh status")?;
+    let expired = String::from_utf8_lossy(&output.stdout).contains("EXPIRED");
+    ensure!(
+        output.status.success() && !expired,
+        "not logged in to Teleport, run `tsh login` before connecting to {:?}\n{}",
+        ssh.host,
+        String::from_utf8_lossy(&output.stderr).trim(),
+    );
+    TELEPORT_LOGGED_IN.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Returns `ssh` options applying the host key verification policy of `ssh`, or the global
+/// default policy, and the connection `timeout` of the workspace, or the global one
+///
+/// Teleport takes care of both itself.
+fn options(ssh: &Ssh, timeout: Option<u64>) -> Result<Vec<String>> {
+    if is_teleport(ssh) {
+        return Ok(Vec::new());
+    }
+    let policy = match ssh.host_key {
+        Some(policy) => Some(policy),
+        None => global()?.host_key,
+    };
+    let mut options = host_key_options(policy);
+    options.extend(timeout_options(runtime::timeout(timeout)?));
+    Ok(options)
+}
+
+/// Returns the program connecting to `ssh`'s host and its arguments up to the remote command
+///
+/// The program is `ssh`, or `tsh ssh` for Teleport, unless `command` is set. ssh `options`, like
+/// `-t`, go before the destination or where `args` places them.
+fn connect(ssh: &Ssh, options: Vec<String>) -> Result<(String, Vec<String>)> {
+    if is_teleport(ssh) {
+        teleport_login(ssh)?;
+    }
+    let mut args = match (&ssh.command, is_teleport(ssh)) {
+        (Some(command), _) => command.split_whitespace().map(str::to_owned).collect(),
+        (None, true) => vec!["tsh".to_owned(), "ssh".to_owned()],
+        (None, false) => vec!["ssh".to_owned()],
+    };
+    ensure!(!args.is_empty(), "ssh.command is empty");
+    let program = args.remove(0);
+    match &ssh.args {
+        Some(templates) => args.extend(expand_args(ssh, templates, options)?),
+        None => {
+            args.extend(options);
+            args.extend(destination(ssh));
+        }
+    }
+    Ok((program, args))
+}
+
+/// Returns a command connecting to `ssh`'s host with ssh `options`, see [`connect`]
+fn command(ssh: &Ssh, options: Vec<String>) -> Result<Command> {
+    let (program, args) = connect(ssh, options)?;
+    let mut command = Command::new(program);
+    command.args(args);
+    Ok(command)
+}
+
+/// Expands the `args` `templates` of `ssh`, an argument `{{options}}` into ssh `options`
+///
+/// Arguments with a placeholder whose setting isn't set are left out.
+fn expand_args(ssh: &Ssh, templates: &[String], options: Vec<String>) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut options = Some(options);
+    for template in templates {
+        let whole = template
+            .strip_prefix("{{")
+            .and_then(|template| template.strip_suffix("}}"));
+        if whole.map(str::trim) == Some("options") {
+            args.extend(options.take().unwrap_or_default());
+            continue;
+        }
+        let mut missing = false;
+        let arg = template::expand_with(template, |variable| {
+            let value = match variable {
+                "host" => Some(ssh.host.clone()),
+                "user" => ssh.user.clone(),
+                "port" => ssh.port.map(|port| port.to_string()),
+                "identity_file" => ssh.identity_file.clone(),
+                "options" => bail!("`{{{{options}}}}` must be a whole argument"),
+                _ => bail!("unknown ssh placeholder {variable:?}"),
+            };
+            missing |= value.is_none();
+            Ok(value.unwrap_or_default())
+        })
+        .context("expanding ssh.args")?;
+        if !missing {
+            args.push(arg);
+        }
+    }
+    Ok(args)
+}
+
+/// Returns `ssh` options giving up connecting after `timeout`
+fn timeout_options(timeout: Duration) -> Vec<String> {
+    // ssh only takes whole seconds, and 0 would mean no timeout at all.
+    let secs = timeout.as_secs().max(1);
+    vec!["-o".to_owned(), format!("ConnectTimeout={secs}")]
+}
+
+/// Returns `ssh` options applying host key verification `policy`
+fn host_key_options(policy: Option<HostKey>) -> Vec<String> {
+    policy
+        .map(|policy| {
+            vec![
+                "-o".to_owned(),
+                format!("StrictHostKeyChecking={}", policy.ssh_option()),
+            ]
+        })
+        .unwrap_or_default()
+}
+
+/// Parses `destination` given as `[user@]host[:port]` into connection settings
+///
+/// IPv6 addresses with a port are written in brackets, like `[::1]:2222`.
+pub fn parse(destination: &str) -> Result<Ssh> {
+    let (user, rest) = match destination.rsplit_once('@') {
+        Some((user, rest)) => (Some(user), rest),
+        None => (None, destination),
+    };
+    let (host, port) = match rest.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, rest) = bracketed
+                .split_once(']')
+                .with_context(|| format!("missing ']' in ssh destination {destination:?}"))?;
+            let port = match rest {
+                "" => None,
+                rest => Some(rest.strip_prefix(':').with_context(|| {
+                    format!("expected ':' after ']' in ssh destination {destination:?}")
+                })?),
+            };
+            (host, port)
+        }
+        None => match rest.split_once(':') {
+            // More than one `:` is an IPv6 address without a port.
+            Some((host, port)) if !port.contains(':') => (host, Some(port)),
+            _ => (rest, None),
+        },
+    };
+    ensure!(
+        user != Some(""),
+        "missing user before '@' in ssh destination {destination:?}",
+    );
+    ensure!(
+        !host.is_empty(),
+        "missing host in ssh destination {destination:?}"
+    );
+    let port = port
+        .map(|port| {
+            port.parse::<u16>().with_context(|| {
+                format!("invalid port {port:?} in ssh destination {destination:?}")
+            })
+        })
+        .transpose()?;
+    Ok(Ssh {
+        user: user.map(str::to_owned),
+        host: host.to_owned(),
+        port,
+        ..Default::default()
+    })
+}
+
+/// Returns the `ssh` arguments selecting the user, port and identity file of `ssh`, followed by
+/// its host
+///
+/// The identity file is left out for Teleport, `-i` takes a Teleport identity there.
+fn destination(ssh: &Ssh) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(user) = &ssh.user {
+        args.extend(["-l".to_owned(), user.clone()]);
+    }
+    if let Some(port) = ssh.port {
+        args.extend(["-p".to_owned(), port.to_string()]);
+    }
+    if let Some(identity_file) = ssh.identity_file.as_ref().filter(|_| !is_teleport(ssh)) {
+        args.extend(["-i".to_owned(), identity_file.clone()]);
+    }
+    args.push(ssh.host.clone());
+    args
+}
+
+/// Returns an `ssh` command connecting to `ssh`'s host in the foreground, with a terminal if `tty`
+///
+/// The remote command is added by the caller. ssh may prompt for passphrases and passwords.
+pub fn foreground(ssh: &Ssh, timeout: Option<u64>, tty: bool) -> Result<Command> {
+    let mut options = options(ssh, timeout)?;
+    if tty {
+        options.push("-t".to_owned());
+    }
+    command(ssh, options)
+}
+
+/// Returns an `scp` command copying from or to `ssh`'s host, see [`remote_file`]
+///
+/// The sources and the target are added by the caller. Fails for an `ssh.command` which doesn't
+/// take ssh's arguments, scp can't run it. Teleport workspaces copy with `tsh scp`.
+pub fn scp(ssh: &Ssh, timeout: Option<u64>) -> Result<Command> {
+    let mut command = Command::new("scp");
+    if is_teleport(ssh) {
+        teleport_login(ssh)?;
+        command = Command::new("tsh");
+        command.arg("scp");
+    } else if let Some(program) = &ssh.command {
+        ensure!(
+            ssh.args.is_none() && !program.trim().contains(char::is_whitespace),
+            "can't copy files through ssh.command {program:?}, scp only runs ssh compatible \
+             programs without arguments",
+        );
+        command.arg("-S").arg(program.trim());
+    }
+    command.args(options(ssh, timeout)?);
+    // Unlike ssh, scp and tsh scp take the port with `-P` and the user as part of the file.
+    if let Some(port) = ssh.port {
+        command.arg("-P").arg(port.to_string());
+    }
+    if let Some(identity_file) = ssh.identity_file.as_ref().filter(|_| !is_teleport(ssh)) {
+        command.arg("-i").arg(identity_file);
+    }
+    Ok(command)
+}
+
+/// Returns the `scp` argument referring to `path` on `ssh`'s host, like `user@host:path`
+pub fn remote_file(ssh: &Ssh, path: &str) -> String {
+    let user = ssh
+        .user
+        .as_ref()
+        .map(|user| format!("{user}@"))
+        .unwrap_or_default();
+    let host = match ssh.host.contains(':') {
+        true => format!("[{}]", ssh.host),
+        false => ssh.host.clone(),
+    };
+    format!("{user}{host}:{path}")
+}
+
+/// Returns an `ssh` command connecting to `ssh`'s host which never prompts on the terminal
+///
+/// The remote command is added by the caller. The host key policy of `ssh` and `timeout` are the
+/// workspace's settings. Teleport takes no connection timeout, callers bound the whole command
+/// with [`process::output_with_timeout`] or [`runtime::output`] unless it runs for as long as the
+/// user wants, like remote hooks and `watch`.
+pub fn background(ssh: &Ssh, timeout: Option<u64>) -> Result<Command> {
+    let global = global()?;
+    let mut options = Vec::new();
+    if !is_teleport(ssh) {
+        if global.askpass.is_none() {
+            options.extend(["-o".to_owned(), "BatchMode=yes".to_owned()]);
+        }
+        options.extend(host_key_options(ssh.host_key.or(global.host_key)));
+        options.extend(timeout_options(runtime::timeout(timeout)?));
+    }
+    let mut command = command(ssh, options)?;
+    if let Some(program) = global.askpass {
+        command
+            .env("SSH_ASKPASS", program)
+            .env("SSH_ASKPASS_REQUIRE", "force");
+    }
+    command.stdin(Stdio::null());
+    Ok(command)
+}
+
+/// Returns the error for a failed background invocation, explaining common failures
+pub fn failure(host: &str, output: &Output) -> anyhow::Error {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.trim();
+    // 255 is reserved for errors of ssh itself, everything else comes from the remote command.
+    let ssh_failed = output.status.code() == Some(255);
+    if ssh_failed && UNREACHABLE_ERRORS.iter().any(|msg| stderr.contains(msg)) {
+        anyhow!("host {host:?} is unreachable\n{stderr}")
+    } else if ssh_failed && HOST_KEY_ERRORS.iter().any(|msg| stderr.contains(msg)) {
+        anyhow!(
+            "host key of {host:?} was refused, run `ssh {host}` once to verify a new host or \
+             remove a changed key with `ssh-keygen -R {host}`\n{stderr}"
+        )
+    } else if ssh_failed && AUTH_ERRORS.iter().any(|msg| stderr.contains(msg)) {
+        anyhow!(
+            "authentication required, run `ssh {host}` once or configure `ssh.askpass`\n{stderr}"
+        )
+    } else {
+        anyhow!("{stderr}")
+    }
+}
+
+/// Copies shell code `exports` to a new file on `ssh`'s host which only the user can read, and
+/// which deletes itself once sourced
+///
+/// Returns the command sourcing the file, for the remote command. The code is sent over ssh's
+/// stdin, so unlike the remote command it never appears on a command line on either machine.
+/// Costs a connection, none is made for empty `exports` or while commands are only printed.
+pub fn private_exports(ssh: &Ssh, timeout: Option<u64>, exports: &str) -> Result<Option<String>> {
+    if exports.is_empty() {
+        return Ok(None);
+    }
+    if runner::is_dry_run() {
+        return Ok(Some(secret::placeholder()));
+    }
+    let mut command = background(ssh, timeout)?;
+    command
+        .arg(r#"umask 077 && f=$(mktemp) && cat >"$f" && echo "rm -f '$f'" >>"$f" && echo "$f""#);
+    let output = runtime::block_on(runtime::output_with_input(
+        command,
+        exports.as_bytes(),
+        runtime::timeout(timeout)?,
+    ))?
+    .context("copy environment to remote host")?
+    .with_context(|| format!("timed out copying environment to {:?}", ssh.host))?;
+    if !output.status.success() {
+        return Err(failure(&ssh.host, &output)).context("copy environment to remote host");
+    }
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    ensure!(
+        !path.is_empty(),
+        "remote host didn't name the environment file"
+    );
+    Ok(Some(secret::source(&path)))
+}
+
+/// `TERM` of the terminals remote sessions are opened in
+const KITTY_TERM: &str = "xterm-kitty";
+
+/// `TERM` used with `terminfo = "term"` unless configured otherwise
+pub const DEFAULT_TERM: &str = "xterm-256color";
+
+/// Returns a window running `script` in an interactive session on `ssh`'s host
+///
+/// Variables in `env` and `secrets` are exported to the remote session, through `SendEnv` if listed
+/// in `send_env`. Secrets, and variables the server didn't accept through `SendEnv`, are exported
+/// from a file copied over beforehand, see [`private_exports`]. Settings of the workspace,
+/// including its connection `timeout`, take precedence over the global ones.
+pub fn session(
+    ssh: &Ssh,
+    timeout: Option<u64>,
+    env: &[(String, String)],
+    secrets: &[(String, String)],
+    script: &str,
+) -> Result<Window> {
+    let global = global()?;
+    let mut terminfo = ssh.terminfo.or(global.terminfo);
+    if cfg!(windows) && terminfo == Some(Terminfo::Kitten) {
+        eprintln!("WARN the ssh kitten is not available on Windows, using plain ssh");
+        terminfo = None;
+    }
+    if let (Some(program), Some(Terminfo::Kitten)) = (&ssh.command, terminfo) {
+        eprintln!("WARN the ssh kitten can't run {program:?}, using it without the kitten");
+        terminfo = None;
+    }
+    if is_teleport(ssh) && terminfo == Some(Terminfo::Kitten) {
+        eprintln!("WARN the ssh kitten can't connect through Teleport, using tsh ssh");
+        terminfo = None;
+    }
+
+    let mut prelude = String::new();
+    match terminfo {
+        Some(Terminfo::Term) => {
+            let term = ssh.term.as_ref().or(global.term.as_ref());
+            let term = term.map_or(DEFAULT_TERM, String::as_str);
+            prelude.push_str(&format!("export TERM={}; ", shell::quote(term)));
+        }
+        Some(Terminfo::Push) => prelude.push_str(&push_terminfo()?),
+        Some(Terminfo::Kitten) | None => {}
+    }
+    if let Some(locale) = ssh.locale.as_ref().or(global.locale.as_ref()) {
+        let locale = shell::quote(locale);
+        prelude.push_str(&format!("export LANG={locale} LC_ALL={locale}; "));
+    }
+
+    // Teleport doesn't send variables, they're all exported in the remote command.
+    let send_env = match is_teleport(ssh) {
+        true => &[],
+        false => ssh.send_env.as_deref().unwrap_or_default(),
+    };
+    let mut send_options = Vec::new();
+    let mut private = String::new();
+    let mut sent = Vec::new();
+    let mut sent_secrets = Vec::new();
+    for name in send_env {
+        if !shell::is_name(name) {
+            eprintln!("WARN ignoring invalid environment variable name {name:?} in ssh.send_env");
+            continue;
+        }
+        // Values from outside the workspace's own variables may be secrets too.
+        let is_public = env.iter().any(|(key, _)| key == name);
+        let value = match env.iter().chain(secrets).find(|(key, _)| key == name) {
+            Some((_, value)) => value.clone(),
+            None => match std::env::var(name) {
+                Ok(value) => value,
+                Err(_) => continue,
+            },
+        };
+        // Only used when the server didn't accept the variable.
+        private.push_str(&format!(
+            "[ -n \"${{{name}+set}}\" ] || export {name}={}\n",
+            shell::quote(&value),
+        ));
+        match is_public {
+            true => sent.push((name.clone(), value)),
+            false => sent_secrets.push((name.clone(), value)),
+        }
+        send_options.extend(["-o".to_owned(), format!("SendEnv={name}")]);
+    }
+    for (key, value) in env {
+        if !send_env.contains(key) {
+            prelude.push_str(&format!("export {key}={}; ", shell::quote(value)));
+        }
+    }
+    let unsent = secrets
+        .iter()
+        .filter(|(key, _)| !send_env.contains(key))
+        .cloned()
+        .collect::<Vec<_>>();
+    private.push_str(&secret::exports(&unsent));
+    if let Some(source) = private_exports(ssh, timeout, &private)? {
+        prelude.push_str(&format!("{source}; "));
+    }
+
+    let mut options = Vec::new();
+    // The kitten copies the terminfo over and allocates a tty itself.
+    if terminfo != Some(Terminfo::Kitten) {
+        options.push("-t".to_owned());
+    }
+    if !is_teleport(ssh) {
+        options.extend(host_key_options(ssh.host_key.or(global.host_key)));
+        options.extend(timeout_options(runtime::timeout(timeout)?));
+    }
+    options.extend(send_options);
+    let (program, args) = connect(ssh, options)?;
+    let mut window = match terminfo {
+        Some(Terminfo::Kitten) => {
+            let mut window = Window::new("kitten");
+            window.args.push(program);
+            window
+        }
+        _ => Window::new(program),
+    };
+    window.args.extend(args);
+    window.args.push(format!("{prelude}{script}"));
+    window.env = sent;
+    window.secrets = sent_secrets;
+    Ok(window)
+}
+
+/// Returns a shell snippet installing the local terminal's terminfo entry if the remote lacks it
+fn push_terminfo() -> Result<String> {
+    let mut command = Command::new("infocmp");
+    command.args(["-x", KITTY_TERM]);
+    let output = process::output_with_timeout(command, runtime::timeout(None)?)?
+        .context("timed out running infocmp")?;
+    ensure!(
+        output.status.success(),
+        "reading terminfo entry for {KITTY_TERM:?}: {}",
+        String::from_utf8_lossy(&output.stderr).trim(),
+    );
+    let source = String::from_utf8(output.stdout).context("terminfo source is not utf-8")?;
+    Ok(format!(
+        "infocmp {KITTY_TERM} >/dev/null 2>&1 || printf %s {} | tic -x - >/dev/null 2>&1; ",
+        shell::quote(&source),
+    ))
+}
@@ -0,0 +1,142 @@
+//! Cached git status and reachability of workspaces
+//!
+//! Checking a workspace takes a `git` invocation, over ssh for remote ones, which is too slow for
+//! listings that should show up instantly. `refresh` checks workspaces concurrently, at most
+//! [`runtime::MAX_CONCURRENT`] at a time, and stores the results in the cache as
+//! [`Key::Status`]. `list --long` shows them however old they are.
+//!
+//! `refresh` skips workspaces checked less than `--max-age` seconds ago, so a timer can run it
+//! often without checking every workspace every time.
+
+use std::process::Command;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::cache::{self, Key};
+use crate::workspace::Workspace;
+use crate::{history, runtime, shell, ssh};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Status {
+    /// Unix time in seconds when the workspace was checked
+    pub time: u64,
+
+    /// Whether the host could be reached, `None` for local workspaces
+    pub reachable: Option<bool>,
+
+    /// Checked out branch, `None` outside of a git repository or if it couldn't be checked
+    pub branch: Option<String>,
+
+    /// Number of changed and untracked files
+    pub changes: Option<usize>,
+}
+
+impl Status {
+    /// Returns a short human readable description, like `main, 2 changes, checked just now`
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if self.reachable == Some(false) {
+            parts.push("unreachable".to_owned());
+        }
+        if let Some(branch) = &self.branch {
+            parts.push(branch.clone());
+        }
+        match self.changes {
+            Some(0) => parts.push("clean".to_owned()),
+            Some(1) => parts.push("1 change".to_owned()),
+            Some(changes) => parts.push(format!("{changes} changes")),
+            None => {}
+        }
+        parts.push(format!("checked {}", history::ago(self.time)));
+        parts.join(", ")
+    }
+}
+
+/// Returns the last stored status of workspace `name`
+pub fn read(name: &str) -> Option<Status> {
+    let cached = cache::read(Key::Status(name.to_owned())).ok()?;
+    serde_json::from_str(&cached).ok()
+}
+
+/// Stores `status` of workspace `name`
+pub fn write(name: &str, status: &Status) -> Result<()> {
+    let json = serde_json::to_string(status).context("serializing workspace status")?;
+    cache::write(Key::Status(name.to_owned()), json)
+}
+
+/// Checks all `workspaces` concurrently
+///
+/// Returns the status of each workspace by name, in no particular order.
+pub async fn check_all(workspaces: Vec<Arc<Workspace>>) -> Result<Vec<(String, Result<Status>)>> {
+    let mut tasks = JoinSet::new();
+    for workspace in workspaces {
+        tasks.spawn(async move {
+            let status = check(&workspace).await;
+            (workspace.name.clone(), status)
+        });
+    }
+    let mut statuses = Vec::new();
+    while let Some(status) = tasks.join_next().await {
+        statuses.push(status.context("checking workspace status")?);
+    }
+    Ok(statuses)
+}
+
+/// Checks git status and reachability of `workspace`
+async fn check(workspace: &Workspace) -> Result<Status> {
+    let command = match &workspace.ssh {
+        Some(ssh) => {
+            let mut command = ssh::background(ssh, workspace.timeout)?;
+            let dir = shell::quote_dir(&workspace.dir);
+            command.arg(format!(
+                "cd {dir} && git status --porcelain=v1 --branch 2>/dev/null"
+            ));
+            command
+        }
+        None => {
+            let mut command = Command::new("git");
+            command.arg("-C").arg(workspace.local_dir()?).args([
+                "status",
+                "--porcelain=v1",
+                "--branch",
+            ]);
+            command
+        }
+    };
+    let time = history::now();
+    let output = runtime::output(command, runtime::timeout(workspace.timeout)?).await?;
+    // ssh exits with 255 when it can't connect, everything else comes from git.
+    let reachable = workspace.ssh.as_ref().map(|_| {
+        output
+            .as_ref()
+            .is_some_and(|output| output.status.code() != Some(255))
+    });
+    let Some(output) = output.filter(|output| output.status.success()) else {
+        return Ok(Status {
+            time,
+            reachable,
+            branch: None,
+            changes: None,
+        });
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    // The header looks like `## main...origin/main [ahead 1]` or `## No commits yet on main`.
+    let branch = lines
+        .next()
+        .and_then(|header| header.strip_prefix("## "))
+        .map(|header| header.strip_prefix("No commits yet on ").unwrap_or(header))
+        .and_then(|header| header.split("...").next())
+        .and_then(|branch| branch.split_whitespace().next())
+        .map(str::to_owned);
+    Ok(Status {
+        time,
+        reachable,
+        branch,
+        changes: Some(lines.count()),
+    })
+}
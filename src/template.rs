@@ -0,0 +1,117 @@
+//! Expansion of `{{variable}}` placeholders in workspace values
+//!
+//! Values like commands and window titles are expanded right before spawning so definitions and
+//! defaults in the global config can stay generic. The supported variables are:
+//!
+//! - `name` workspace name
+//! - `dir` workspace directory, absolute for local workspaces and as written for remote ones
+//! - `host` ssh host, empty for local workspaces
+//! - `home` local user's home directory
+//!
+//! Separately `${VAR}` references to the local environment are expanded in all string values when
+//! workspace and config files are read, `$$` is a literal `$`.
+
+use std::env;
+
+use anyhow::{bail, Context, Result};
+use toml::Value;
+
+use crate::workspace::Workspace;
+
+/// Expands all `{{variable}}` placeholders in `template` with values from `workspace`
+///
+/// Whitespace around the variable name is ignored, unknown variables are an error.
+pub fn expand(template: &str, workspace: &Workspace) -> Result<String> {
+    expand_with(template, |variable| value(variable, workspace))
+}
+
+/// Expands all `{{variable}}` placeholders in `template` with values returned by `value`
+///
+/// Used for placeholders other than the workspace variables, whitespace around the variable name
+/// is ignored.
+pub fn expand_with(
+    template: &str,
+    mut value: impl FnMut(&str) -> Result<String>,
+) -> Result<String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .with_context(|| format!("unterminated placeholder in {template:?}"))?;
+        let variable = after[..end].trim();
+        expanded.push_str(&value(variable)?);
+        rest = &after[end + 2..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn value(variable: &str, workspace: &Workspace) -> Result<String> {
+    Ok(match variable {
+        "name" => workspace.name.clone(),
+        "dir" if workspace.ssh.is_some() => workspace.dir.clone(),
+        "dir" => workspace
+            .local_dir()?
+            .to_str()
+            .context("workspace directory path is not valid utf-8")?
+            .to_owned(),
+        "host" => workspace
+            .ssh
+            .as_ref()
+            .map(|ssh| ssh.host.clone())
+            .unwrap_or_default(),
+        "home" => dirs::home_dir()
+            .context("could not determine user home directory")?
+            .to_str()
+            .context("home directory path is not valid utf-8")?
+            .to_owned(),
+        _ => bail!("unknown template variable {variable:?}"),
+    })
+}
+
+/// Expands `${VAR}` references to local environment variables in `value`
+///
+/// `$$` is an escaped `$`, a `$` followed by anything else is kept as is.
+pub fn expand_env(value: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(after) = after.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .with_context(|| format!("unterminated ${{...}} in {value:?}"))?;
+            let name = &after[..end];
+            let var = env::var(name).with_context(|| {
+                format!("environment variable {name:?} referenced in {value:?} is not set")
+            })?;
+            expanded.push_str(&var);
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = after;
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expands environment variable references in all strings nested in `value`
+pub fn expand_env_value(value: &mut Value) -> Result<()> {
+    match value {
+        Value::String(string) => *string = expand_env(string)?,
+        Value::Array(array) => array.iter_mut().try_for_each(expand_env_value)?,
+        Value::Table(table) => table
+            .iter_mut()
+            .try_for_each(|(_, value)| expand_env_value(value))?,
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) | Value::Datetime(_) => {}
+    }
+    Ok(())
+}
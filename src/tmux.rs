@@ -0,0 +1,409 @@
+//! Running workspace shells in tmux sessions
+//!
+//! Workspaces with a `[tmux]` table, or with `multiplexer = "tmux"` in their definition or the
+//! global config, open their terminal attached to a tmux session named after the workspace. It's
+//! created on the first attach and survives closing the window, for remote workspaces it runs on
+//! their host and survives disconnects too. `multiplexer = "none"` opens plain shells even with a
+//! `[tmux]` table. `tmux.layout` is a layout file applied to the session when it's created,
+//! relative to the config directory:
+//!
+//! - `.yaml` and `.yml` files describe windows and panes like [tmuxp] session files, see
+//!   [`Session`]
+//! - any other file is a list of tmux commands, as read by `tmux source-file`
+//!
+//! The layout is read locally and sent along with the commands creating the session, so the same
+//! file works for local and remote workspaces.
+//!
+//! `export-tmuxp` goes the other way and writes the session of a workspace as a tmuxp session file,
+//! see [`export`].
+//!
+//! [tmuxp]: https://tmuxp.git-pull.com/
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::workspace::{self, Workspace};
+use crate::{config, shell};
+
+/// Layout in the format of a tmuxp session file
+///
+/// Only the keys describing windows and panes are used, everything else is ignored.
+#[derive(Debug, Deserialize)]
+pub struct Session {
+    /// Directory the panes change into, relative to the workspace directory
+    pub start_directory: Option<String>,
+
+    /// Commands run in every pane before its own
+    pub shell_command_before: Option<Commands>,
+
+    /// Windows of the session, in order
+    #[serde(default)]
+    pub windows: Vec<Window>,
+}
+
+/// Window of a tmuxp session
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Window {
+    /// Window name, tmux names it after the running program if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_name: Option<String>,
+
+    /// tmux layout the panes are arranged in, `tiled` if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+
+    /// Directory the panes change into, overrides the session's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_directory: Option<String>,
+
+    /// Commands run in every pane of the window before its own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell_command_before: Option<Commands>,
+
+    /// Panes of the window, a window without any has a single shell
+    #[serde(default)]
+    pub panes: Vec<Option<Pane>>,
+}
+
+/// Pane of a tmuxp window
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Pane {
+    /// Commands typed into the pane
+    Commands(Commands),
+
+    /// Pane with options
+    Pane {
+        /// Commands typed into the pane
+        #[serde(skip_serializing_if = "Option::is_none")]
+        shell_command: Option<Commands>,
+
+        /// Directory the pane changes into, overrides the window's
+        #[serde(skip_serializing_if = "Option::is_none")]
+        start_directory: Option<String>,
+    },
+}
+
+/// One or more shell commands
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Commands {
+    /// A single command
+    One(String),
+
+    /// Commands run one after another
+    Many(Vec<String>),
+}
+
+impl Commands {
+    /// Returns the commands in order
+    fn iter(&self) -> impl Iterator<Item = &str> {
+        let commands = match self {
+            Commands::One(command) => std::slice::from_ref(command),
+            Commands::Many(commands) => commands.as_slice(),
+        };
+        commands.iter().map(String::as_str)
+    }
+}
+
+impl Session {
+    /// Returns the commands typed into each pane of `window`, in order
+    ///
+    /// Changing into the pane's directory comes first, then the commands run before those of the
+    /// pane. A window without panes has a single one.
+    pub fn panes(&self, window: &Window) -> Vec<Vec<String>> {
+        let panes = match window.panes.as_slice() {
+            [] => &[None],
+            panes => panes,
+        };
+        panes
+            .iter()
+            .map(|pane| {
+                let (own, pane_dir) = match pane {
+                    Some(Pane::Commands(commands)) => (Some(commands), None),
+                    Some(Pane::Pane {
+                        shell_command,
+                        start_directory,
+                    }) => (shell_command.as_ref(), start_directory.as_ref()),
+                    None => (None, None),
+                };
+                let dir = pane_dir
+                    .or(window.start_directory.as_ref())
+                    .or(self.start_directory.as_ref());
+                let cd = dir.map(|dir| format!("cd {}", shell::quote_dir(dir)));
+                cd.into_iter()
+                    .chain(
+                        self.shell_command_before
+                            .iter()
+                            .chain(&window.shell_command_before)
+                            .flat_map(Commands::iter)
+                            .map(str::to_owned),
+                    )
+                    .chain(
+                        own.into_iter()
+                            .flat_map(Commands::iter)
+                            .filter(|typed| !BLANK_PANES.contains(typed))
+                            .map(str::to_owned),
+                    )
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Pane commands tmuxp treats as an empty pane
+const BLANK_PANES: &[&str] = &["blank", "pane"];
+
+/// Returns the name of the tmux session of workspace `name`
+///
+/// tmux doesn't allow `.` and `:` in session names.
+pub fn session_name(name: &str) -> String {
+    name.replace(['.', ':'], "_")
+}
+
+/// Returns a POSIX shell script attaching to the session of `workspace`, creating it if it isn't
+/// running
+///
+/// The script has to run in the workspace directory. New panes run `shell` with `env` set, which
+/// reaches them even when the tmux server was started outside of the workspace. Inside tmux the
+/// client switches to the session instead.
+///
+/// Variables named in `secrets` must be set for the script, they're copied into the session by
+/// tmux's `update-environment` so that their values aren't on the command line.
+pub fn attach_script(
+    workspace: &Workspace,
+    tmux: &workspace::Tmux,
+    shell: &str,
+    env: &[(String, String)],
+    secrets: &[String],
+) -> Result<String> {
+    let name = session_name(&workspace.name);
+    let target = shell::quote(&format!("={name}"));
+
+    let mut create = format!("tmux new-session -d -s {} -c \"$PWD\"", shell::quote(&name));
+    for (key, value) in env {
+        create.push_str(&format!(" -e {}", shell::quote(&format!("{key}={value}"))));
+    }
+    let shell = shell::quote(shell);
+    create.push_str(&format!(" {shell} \\; set-option default-command {shell}"));
+
+    let create = match &tmux.layout {
+        Some(path) => {
+            let commands =
+                layout(Path::new(path)).with_context(|| format!("reading tmux layout {path:?}"))?;
+            format!(
+                "{{ f=$(mktemp) && printf %s {} >\"$f\" && {create} \\; source-file \"$f\"; rm -f \"$f\"; }}",
+                shell::quote(&commands),
+            )
+        }
+        None => create,
+    };
+    let create = match secrets.is_empty() {
+        true => create,
+        false => format!(
+            "{{ for v in {}; do tmux show-options -gv update-environment 2>/dev/null | \
+             grep -qx \"$v\" || tmux set-option -ga update-environment \"$v\" 2>/dev/null; done; \
+             {create}; }}",
+            shell::join(secrets),
+        ),
+    };
+    Ok(format!(
+        "tmux has-session -t {target} 2>/dev/null || {create}; \
+         if [ -n \"$TMUX\" ]; then exec tmux switch-client -t {target}; \
+         else exec tmux attach-session -t {target}; fi"
+    ))
+}
+
+/// Layout file of a workspace
+enum Layout {
+    /// tmux commands, as read by `tmux source-file`
+    Commands(String),
+
+    /// tmuxp session file
+    Session(Session),
+}
+
+/// Reads the layout file at `path`
+fn read_layout(path: &Path) -> Result<Layout> {
+    let path = config::dir_path()?.join(path);
+    let buf = fs::read_to_string(&path).with_context(|| format!("reading {path:?}"))?;
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    if !matches!(extension, Some("yaml" | "yml")) {
+        return Ok(Layout::Commands(buf));
+    }
+    serde_yaml::from_str(&buf)
+        .with_context(|| format!("parsing {path:?}"))
+        .map(Layout::Session)
+}
+
+/// Reads the layout file at `path` and returns it as tmux commands
+fn layout(path: &Path) -> Result<String> {
+    match read_layout(path)? {
+        Layout::Commands(commands) => Ok(commands),
+        Layout::Session(session) => Ok(commands(&session)
+            .iter()
+            .map(|command| join(command))
+            .collect::<Vec<_>>()
+            .join("\n")),
+    }
+}
+
+/// Returns the tmux commands building the windows and panes of `session` in a new session
+fn commands(session: &Session) -> Vec<Vec<String>> {
+    let mut commands = Vec::new();
+    let mut command =
+        |args: &[&str]| commands.push(args.iter().map(|arg| arg.to_string()).collect());
+    for (index, window) in session.windows.iter().enumerate() {
+        match (&window.window_name, index) {
+            (Some(name), 0) => command(&["rename-window", name]),
+            (None, 0) => {}
+            (Some(name), _) => command(&["new-window", "-n", name]),
+            (None, _) => command(&["new-window"]),
+        }
+        let layout = window.layout.as_deref().unwrap_or("tiled");
+        for (index, typed) in session.panes(window).iter().enumerate() {
+            if index > 0 {
+                command(&["split-window"]);
+                // Rearrange after each split, so there's room for the next pane.
+                command(&["select-layout", layout]);
+            }
+            for typed in typed {
+                command(&["send-keys", "-l", typed]);
+                command(&["send-keys", "Enter"]);
+            }
+        }
+        command(&["select-layout", layout]);
+    }
+    if session.windows.len() > 1 {
+        command(&["select-window", "-t", "{start}"]);
+    }
+    commands
+}
+
+/// tmuxp session file exported from a workspace
+#[derive(Debug, Serialize)]
+struct Export {
+    /// Name of the tmux session
+    session_name: String,
+
+    /// Workspace directory
+    start_directory: String,
+
+    /// Variables set in the session
+    environment: BTreeMap<String, String>,
+
+    /// tmux options of the session
+    options: BTreeMap<&'static str, String>,
+
+    /// Commands run in every pane before its own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shell_command_before: Option<Commands>,
+
+    /// Windows of the session, in order
+    windows: Vec<Window>,
+}
+
+/// Reads the `tmux.layout` of `workspace` if it's a tmuxp file
+///
+/// Other layouts can't be converted, they're reported and ignored.
+pub fn tmuxp_layout(workspace: &Workspace) -> Result<Option<Session>> {
+    let layout = workspace
+        .tmux
+        .as_ref()
+        .and_then(|tmux| tmux.layout.as_ref());
+    let Some(path) = layout else {
+        return Ok(None);
+    };
+    match read_layout(Path::new(path)).with_context(|| format!("reading tmux layout {path:?}"))? {
+        Layout::Session(session) => Ok(Some(session)),
+        Layout::Commands(_) => {
+            eprintln!("WARN tmux layout {path:?} is not a tmuxp file, using a single window");
+            Ok(None)
+        }
+    }
+}
+
+/// Returns a tmuxp session file recreating the tmux session of `workspace`
+///
+/// The session starts in `dir` and its panes run `shell` with `env` set. Layouts which aren't
+/// tmuxp files can't be converted, the session gets a single window instead.
+pub fn export(
+    workspace: &Workspace,
+    dir: String,
+    shell: String,
+    env: Vec<(String, String)>,
+) -> Result<String> {
+    let session = tmuxp_layout(workspace)?;
+    let Session {
+        start_directory,
+        shell_command_before,
+        mut windows,
+    } = session.unwrap_or(Session {
+        start_directory: None,
+        shell_command_before: None,
+        windows: Vec::new(),
+    });
+    if windows.is_empty() {
+        windows.push(Window::default());
+    }
+    for (index, window) in windows.iter_mut().enumerate() {
+        // tmuxp requires every window to be named.
+        window
+            .window_name
+            .get_or_insert_with(|| (index + 1).to_string());
+        if window.panes.is_empty() {
+            window.panes.push(None);
+        }
+        // Both are relative to the workspace directory, which tmuxp starts the session in.
+        if window.start_directory.is_none() {
+            window.start_directory.clone_from(&start_directory);
+        }
+    }
+
+    let export = Export {
+        session_name: session_name(&workspace.name),
+        start_directory: dir,
+        environment: env.into_iter().collect(),
+        options: BTreeMap::from([("default-command", shell)]),
+        shell_command_before,
+        windows,
+    };
+    serde_yaml::to_string(&export).context("serializing tmuxp session")
+}
+
+/// Quotes each argument for tmux's command parser and joins them into a single command
+fn join(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes `arg` so tmux's command parser treats it as a single argument
+fn quote(arg: &str) -> String {
+    let is_plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || "_-./=:,+@%".contains(ch));
+    if is_plain {
+        return arg.to_owned();
+    }
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for ch in arg.chars() {
+        match ch {
+            '\\' | '"' | '$' => {
+                quoted.push('\\');
+                quoted.push(ch);
+            }
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,47 @@
+//! URIs of workspace directories for file managers and other tools
+//!
+//! Remote directories are `sftp://[user@]host[:port]/dir`, which Nautilus, Dolphin and rclone
+//! understand, and local directories are `file:///dir`.
+
+use std::path::Path;
+
+use crate::workspace;
+
+/// Returns the `sftp://` URI of absolute directory `dir` on `ssh`'s host
+pub fn sftp(ssh: &workspace::Ssh, dir: &str) -> String {
+    let user = ssh
+        .user
+        .as_ref()
+        .map(|user| format!("{}@", encode(user)))
+        .unwrap_or_default();
+    let port = ssh.port.map(|port| format!(":{port}")).unwrap_or_default();
+    format!("sftp://{user}{}{port}{}", ssh.host, encode_path(dir))
+}
+
+/// Returns the `file://` URI of absolute local directory `dir`
+pub fn file(dir: &Path) -> String {
+    let dir = dir.to_string_lossy();
+    // Windows paths like `C:\dir` become `file:///C:/dir`.
+    let dir = dir.replace('\\', "/");
+    let slash = if dir.starts_with('/') { "" } else { "/" };
+    format!("file://{slash}{}", encode_path(&dir))
+}
+
+/// Percent-encodes each segment of `path`, keeping the `/` between them
+fn encode_path(path: &str) -> String {
+    path.split('/').map(encode).collect::<Vec<_>>().join("/")
+}
+
+/// Percent-encodes everything in `value` except unreserved URL characters
+pub fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,43 @@
+//! VS Code Remote-SSH
+//!
+//! VS Code doesn't run in a terminal, so a remote workspace edited with `code` isn't opened by
+//! running `code` on the remote host. The local `code` connects to the host itself, given the
+//! remote authority `ssh-remote+<host>` and the absolute path of the remote directory.
+
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::workspace;
+
+/// Returns whether editor command `command` runs VS Code
+pub fn is_code(command: &str) -> bool {
+    command.split_whitespace().next().is_some_and(|program| {
+        Path::new(program)
+            .file_stem()
+            .is_some_and(|stem| stem == "code" || stem == "code-insiders")
+    })
+}
+
+/// Returns the remote authority VS Code connects to `ssh`'s host with
+///
+/// Remote-SSH only takes a port in its hex encoded JSON form of the authority, hosts without one
+/// use the readable `user@host`.
+pub fn authority(ssh: &workspace::Ssh) -> String {
+    let Some(port) = ssh.port else {
+        return match &ssh.user {
+            Some(user) => format!("ssh-remote+{user}@{}", ssh.host),
+            None => format!("ssh-remote+{}", ssh.host),
+        };
+    };
+    let mut host = json!({ "hostName": ssh.host, "port": port });
+    if let Some(user) = &ssh.user {
+        host["user"] = json!(user);
+    }
+    let hex = host
+        .to_string()
+        .bytes()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("ssh-remote+{hex}")
+}
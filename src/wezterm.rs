@@ -0,0 +1,130 @@
+//! WezTerm launch definitions
+//!
+//! `export-wezterm` writes a Lua module for the WezTerm config describing how to open a
+//! workspace. It returns a table with:
+//!
+//! - `terminal` spawn command of the workspace shell
+//! - `editor` spawn command of the workspace editor, missing for editors which don't run in a
+//!   terminal
+//! - `action` switching to a WezTerm workspace named after the workspace, spawning the shell when
+//!   it doesn't exist yet
+//!
+//! Remote workspaces are spawned in WezTerm's `SSH:<host>` domain, which WezTerm defines for the
+//! hosts in the ssh config.
+
+use crate::shell;
+
+/// Command WezTerm spawns, a `SpawnCommand` in its config
+#[derive(Debug)]
+pub struct Spawn {
+    /// Label shown in WezTerm's launcher
+    pub label: String,
+
+    /// Program and its arguments
+    pub args: Vec<String>,
+
+    /// Working directory, WezTerm's default if not set
+    pub cwd: Option<String>,
+
+    /// Environment variables set for the program
+    pub env: Vec<(String, String)>,
+
+    /// Name of the multiplexer domain the program runs in, the default domain if not set
+    pub domain: Option<String>,
+}
+
+impl Spawn {
+    /// Returns the spawn command as a Lua table
+    fn lua(&self) -> String {
+        let mut lua = String::from("{\n");
+        let mut field = |key: &str, value: String| {
+            lua.push_str(&format!("  {key} = {value},\n"));
+        };
+        field("label", string(&self.label));
+        field("args", list(self.args.iter().map(|arg| string(arg))));
+        if let Some(cwd) = &self.cwd {
+            field("cwd", string(cwd));
+        }
+        if !self.env.is_empty() {
+            let env = self
+                .env
+                .iter()
+                .map(|(key, value)| format!("[{}] = {}", string(key), string(value)));
+            field("set_environment_variables", list(env));
+        }
+        if let Some(domain) = &self.domain {
+            field("domain", format!("{{ DomainName = {} }}", string(domain)));
+        }
+        lua.push('}');
+        lua
+    }
+}
+
+/// Returns the domain WezTerm defines for ssh host `host`
+pub fn ssh_domain(host: &str) -> String {
+    format!("SSH:{host}")
+}
+
+/// Returns the arguments running `script` on a remote host with `env` exported
+///
+/// WezTerm can only set environment variables in an ssh domain if the server accepts them, so
+/// they're exported instead.
+pub fn remote_args(env: &[(String, String)], script: &str) -> Vec<String> {
+    let exports = env
+        .iter()
+        .map(|(key, value)| format!("export {key}={}; ", shell::quote(value)))
+        .collect::<String>();
+    vec![
+        "sh".to_owned(),
+        "-c".to_owned(),
+        format!("{exports}{script}"),
+    ]
+}
+
+/// Returns the Lua module opening workspace `name` with `terminal` and `editor`
+pub fn module(name: &str, terminal: &Spawn, editor: Option<&Spawn>) -> String {
+    let mut lua = format!(
+        "-- Opens workspace {name:?}, generated by `workspacectl export-wezterm`\n\
+         --\n\
+         -- Bind `action` to a key to switch to the workspace, for example\n\
+         -- {{ key = 'w', mods = 'LEADER', action = require('workspace').action }}\n\
+         local wezterm = require 'wezterm'\n\
+         \n\
+         local M = {{}}\n\
+         \n\
+         M.terminal = {}\n",
+        terminal.lua(),
+    );
+    if let Some(editor) = editor {
+        lua.push_str(&format!("\nM.editor = {}\n", editor.lua()));
+    }
+    lua.push_str(&format!(
+        "\nM.action = wezterm.action.SwitchToWorkspace {{\n  name = {},\n  spawn = M.terminal,\n}}\n\
+         \n\
+         return M\n",
+        string(name),
+    ));
+    lua
+}
+
+/// Returns a Lua list of `items`
+fn list(items: impl Iterator<Item = String>) -> String {
+    format!("{{ {} }}", items.collect::<Vec<_>>().join(", "))
+}
+
+/// Quotes `value` as a Lua string literal
+fn string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        match ch {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            ch if ch.is_ascii_control() => quoted.push_str(&format!("\\{:03}", ch as u8)),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,118 @@
+//! Windows opened for workspaces by `start`, so `close` can close them again
+//!
+//! Stored in the cache as a log with one `<name>\t<pid>\t<program>` entry per line, where the
+//! process is the one spawned to open the window. Terminals which hand the window off to an
+//! already running instance and exit, like Windows Terminal or the macOS AppleScript backends,
+//! leave nothing behind to close.
+
+use anyhow::Result;
+
+use crate::cache::{self, Key, Transaction};
+use crate::process;
+
+#[derive(Debug)]
+pub struct Entry {
+    /// Name of the workspace the window belongs to
+    pub name: String,
+
+    /// Process id of the spawned process
+    pub pid: u32,
+
+    /// Program of the spawned process, checked before closing so a reused pid isn't killed
+    pub program: String,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<Entry> {
+        let mut fields = line.splitn(3, '\t');
+        Some(Entry {
+            name: fields.next()?.to_owned(),
+            pid: fields.next()?.parse().ok()?,
+            program: fields.next()?.to_owned(),
+        })
+    }
+
+    fn line(&self) -> String {
+        format!("{}\t{}\t{}", self.name, self.pid, self.program)
+    }
+}
+
+/// Record that a window of workspace `name` was opened by process `pid` running `program`
+pub fn record(name: &str, pid: u32, program: &str) -> Result<()> {
+    let entry = Entry {
+        name: name.to_owned(),
+        pid,
+        program: program.to_owned(),
+    };
+    cache::append(Key::Windows, &entry.line())
+}
+
+/// Read all recorded windows
+fn read() -> Result<Vec<Entry>> {
+    let entries = cache::read_lines(Key::Windows)?
+        .into_iter()
+        .filter_map(|line| {
+            let entry = Entry::parse(&line);
+            if entry.is_none() {
+                eprintln!("WARN ignoring malformed window entry {line:?}");
+            }
+            entry
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Closes the recorded windows of workspace `name` and forgets them in `transaction`
+///
+/// Returns how many windows were still open.
+pub fn close(transaction: &mut Transaction, name: &str) -> Result<usize> {
+    let (closing, rest): (Vec<_>, Vec<_>) =
+        read()?.into_iter().partition(|entry| entry.name == name);
+    let mut closed = 0;
+    for entry in &closing {
+        if process::terminate(entry.pid, &entry.program)? {
+            closed += 1;
+        }
+    }
+    let lines = rest.iter().map(Entry::line).collect::<Vec<_>>();
+    transaction.write_lines(Key::Windows, &lines);
+    Ok(closed)
+}
+
+/// Rewrite window entries of workspace `old` to `new` in `transaction`
+pub fn rename(transaction: &mut Transaction, old: &str, new: &str) -> Result<()> {
+    let mut entries = read()?;
+    if entries.iter().all(|entry| entry.name != old) {
+        return Ok(());
+    }
+    for entry in &mut entries {
+        if entry.name == old {
+            entry.name = new.to_owned();
+        }
+    }
+    let lines = entries.iter().map(Entry::line).collect::<Vec<_>>();
+    transaction.write_lines(Key::Windows, &lines);
+    Ok(())
+}
+
+/// Keeps only the window entries for which `keep` returns `true` in `transaction`
+///
+/// Returns the removed entries.
+pub fn retain(
+    transaction: &mut Transaction,
+    mut keep: impl FnMut(&Entry) -> Result<bool>,
+) -> Result<Vec<Entry>> {
+    let mut kept = Vec::new();
+    let mut removed = Vec::new();
+    for entry in read()? {
+        if keep(&entry)? {
+            kept.push(entry.line());
+        } else {
+            removed.push(entry);
+        }
+    }
+    if !removed.is_empty() {
+        transaction.write_lines(Key::Windows, &kept);
+    }
+    Ok(removed)
+}
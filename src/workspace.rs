@@ -2,25 +2,88 @@
 //!
 //! The database is located in the platform configuration directory for `workspacectl`. For example
 //! `~/.config/workspacectl` on Linux.
+//!
+//! Definitions are TOML files by default but JSON and YAML are read as well, the format is keyed
+//! by the file extension. Edits which preserve formatting are only supported for TOML. Any of these
+//! can be encrypted, see [`age`].
 
+use std::ffi::OsString;
 use std::fs;
-use std::io::Write;
+use std::io::{ErrorKind, Write};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{anyhow, ensure, Context, Result};
 use atomicwrites::AtomicFile;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use toml::Value;
+use toml_edit::{Document, Item, TableLike};
 use walkdir::WalkDir;
 
 use crate::cache::{self, Key};
-use crate::config;
+use crate::parse::Format;
+use crate::{age, config, migrate, parse, project, prompt, session};
 
 mod data;
 pub use data::*;
 
+impl Workspace {
+    /// Returns the root directory of a local workspace
+    ///
+    /// Relative directories are relative to the user's home directory.
+    pub fn local_dir(&self) -> Result<PathBuf> {
+        let home = dirs::home_dir().context("could not determine user home directory")?;
+        Ok(home.join(&self.dir))
+    }
+}
+
+/// Canonicalizes `path` into a form other programs understand
+///
+/// On Windows `canonicalize` returns verbatim `\\?\` paths, which neither `wt.exe` nor the home
+/// directory prefix match, so the prefix is removed.
+pub fn canonicalize(path: &Path) -> Result<PathBuf> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("canonicalize path {path:?}"))?;
+    if cfg!(windows) {
+        if let Some(path) = path.to_str().and_then(|path| path.strip_prefix(r"\\?\")) {
+            return Ok(PathBuf::from(path));
+        }
+    }
+    Ok(path)
+}
+
+impl HostKey {
+    /// Returns the value of the `StrictHostKeyChecking` ssh option implementing this policy
+    pub fn ssh_option(self) -> &'static str {
+        match self {
+            HostKey::Strict => "yes",
+            HostKey::AcceptNew => "accept-new",
+            HostKey::Ask => "ask",
+        }
+    }
+}
+
+impl parse::Schema for Workspace {
+    fn known_keys(path: &[String]) -> &'static [&'static str] {
+        match path {
+            [] => parse::fields::<Workspace>(),
+            [table] if table == "ssh" => parse::fields::<Ssh>(),
+            [table] if table == "editor" => parse::fields::<Editor>(),
+            [table] if table == "shell" => parse::fields::<Shell>(),
+            [table] if table == "git" => parse::fields::<Git>(),
+            [table] if table == "hooks" => parse::fields::<Hooks>(),
+            [table] if table == "tmux" => parse::fields::<Tmux>(),
+            [table] if table == "nix" => parse::fields::<Nix>(),
+            [table] if table == "python" => parse::fields::<Python>(),
+            _ => &[],
+        }
+    }
+}
+
 /// Returns path to the directory used to store workspace definition files
 fn dir_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir().context("could not determine user config directory")?;
-    Ok(config_dir.join("workspacectl/workspaces"))
+    Ok(config::dir_path()?.join("workspaces"))
 }
 
 /// Characters forbidden in workspace names
@@ -34,14 +97,42 @@ const FORBIDDEN_CHARACTERS: &[char] = &[
     '<', '>', ':', '"', '|', '?', '*', // Windows forbidden characters
 ];
 
-/// Returns path to the file used to store a particular workspace definition
+/// Names reserved for files `workspacectl` keeps next to the workspace definitions
+const RESERVED_NAMES: &[&str] = &["config"];
+
+/// Device names which cannot be used as file names on Windows, with or without an extension
+const WINDOWS_DEVICE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Returns path to the file used to store a particular workspace definition in `format`
 ///
 /// Checks all the preconditions for workspace name
-fn file_path(name: &str) -> Result<PathBuf> {
+fn file_path(name: &str, format: Format) -> Result<PathBuf> {
+    ensure!(
+        !RESERVED_NAMES.contains(&name),
+        "workspace name {name:?} is reserved, pick a different name",
+    );
     ensure!(
         !name.starts_with('.'),
         "workspace name cannot start with a '.'",
     );
+    if let Some(extension) = extension(name) {
+        ensure!(
+            Format::from_extension(extension).is_none(),
+            "workspace name cannot end with '.{extension}', the extension is added automatically",
+        );
+    }
+    for segment in name.split(['/', '\\']) {
+        let stem = segment.split('.').next().unwrap_or(segment);
+        ensure!(
+            !WINDOWS_DEVICE_NAMES
+                .iter()
+                .any(|device| stem.eq_ignore_ascii_case(device)),
+            "workspace name cannot contain the Windows device name {segment:?}",
+        );
+    }
     ensure!(
         !name.contains(|ch: char| ch.is_ascii_control()),
         "workspace name cannot contain ascii control characters",
@@ -56,24 +147,159 @@ fn file_path(name: &str) -> Result<PathBuf> {
         "workspace name must be a relative path, got {name:?}",
     );
     let dir = dir_path()?;
-    Ok(dir.join(name).with_extension("toml"))
+    // Not `with_extension`, that would replace anything after a `.` in the name.
+    let mut path = dir.join(name).into_os_string();
+    path.push(".");
+    path.push(format.extensions()[0]);
+    Ok(path.into())
+}
+
+/// Returns the extension of the last segment of `name`, if any
+fn extension(name: &str) -> Option<&str> {
+    let (_, extension) = name.rsplit(['/', '\\']).next()?.rsplit_once('.')?;
+    Some(extension)
+}
+
+/// Appends `.age` to `path`, see [`age`]
+fn encrypted_path(path: PathBuf) -> PathBuf {
+    let mut path = path.into_os_string();
+    path.push(".");
+    path.push(age::EXTENSION);
+    path.into()
+}
+
+/// File storing a workspace definition
+struct File {
+    path: PathBuf,
+    format: Format,
+    /// Whether the file is encrypted, see [`age`]
+    encrypted: bool,
+}
+
+/// Finds the file storing workspace definition `name`
+///
+/// Returns `None` if there's no definition with that name. It's an error for more than one file
+/// to define the same workspace.
+///
+/// Only the paths a definition of `name` can have are looked at, the workspaces directory is never
+/// walked, so resolving a workspace stays fast however many there are.
+fn find_file(name: &str) -> Result<Option<File>> {
+    // Validate the name once up front.
+    let base = file_path(name, Format::Toml)?.with_extension("");
+    let mut found = Vec::new();
+    for format in Format::ALL {
+        for extension in format.extensions() {
+            let mut path = base.clone().into_os_string();
+            path.push(".");
+            path.push(extension);
+            let path = PathBuf::from(path);
+            for (path, encrypted) in [(path.clone(), false), (encrypted_path(path), true)] {
+                if path.is_file() {
+                    found.push(File {
+                        path,
+                        format,
+                        encrypted,
+                    });
+                }
+            }
+        }
+    }
+    match found.len() {
+        0 | 1 => Ok(found.pop()),
+        _ => Err(anyhow!(
+            "workspace {name:?} is defined by more than one file, remove all but one of {:?}",
+            found.iter().map(|file| &file.path).collect::<Vec<_>>(),
+        )),
+    }
 }
 
 /// Read workspace definition for workspace with name `name`
+///
+/// Only a missing workspace lists the other workspaces, to suggest similar names.
 pub fn read(name: &str) -> Result<Workspace> {
-    if name == "~" {
-        return home();
+    match name {
+        "~" => return home(),
+        SCRATCH => return scratch(),
+        _ => {}
     }
 
-    let path = file_path(name)?;
-    let buf =
-        fs::read_to_string(&path).with_context(|| format!("reading workspace file at {path:?}"))?;
-    let mut workspace = toml::from_str::<Workspace>(&buf)
+    let workspace = project::fill(read_own(name)?)?;
+    let mut workspace = config::fill_defaults(workspace)?;
+    // Overwrite the `String::default()` generated by serde, filling in the defaults goes through
+    // serde too so it has to come after.
+    workspace.name = name.to_owned();
+    Ok(workspace)
+}
+
+/// Read workspace definition `name` as it's written, without defaults from the project or global
+/// config
+pub fn read_own(name: &str) -> Result<Workspace> {
+    if name == "~" || name == SCRATCH {
+        return Ok(Workspace {
+            editor: None,
+            shell: None,
+            ..read(name)?
+        });
+    }
+
+    let (File { path, format, .. }, buf) = read_file(name)?;
+    // Migrations rewrite TOML, other formats are read as they are.
+    let buf = match format {
+        Format::Toml => migrate::upgrade(buf, migrate::Kind::Workspace)
+            .with_context(|| format!("upgrading workspace file at {path:?}"))?,
+        Format::Json | Format::Yaml => buf,
+    };
+    let mut workspace = parse::file::<Workspace>(&path, &buf, format)
         .with_context(|| format!("parsing workspace file at {path:?}"))?;
-    // Overwrite the `String::default()` generated by serde.
-    workspace.name.push_str(name);
+    workspace.name = name.to_owned();
+    Ok(workspace)
+}
+
+/// Returns the file storing workspace definition `name` and its contents
+///
+/// Encrypted files are decrypted.
+fn read_file(name: &str) -> Result<(File, String)> {
+    let file = find_file(name)?.ok_or_else(|| not_found(name))?;
+    if file.encrypted {
+        let buf = age::decrypt(&file.path)?;
+        return Ok((file, buf));
+    }
+    match fs::read_to_string(&file.path) {
+        Ok(buf) => Ok((file, buf)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Err(not_found(name)),
+        Err(err) => {
+            let path = &file.path;
+            Err(err).with_context(|| format!("reading workspace file at {path:?}"))
+        }
+    }
+}
 
-    config::fill_defaults(workspace)
+/// Maximum number of suggestions offered when a workspace is not found
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Builds the error for a missing workspace, suggesting the closest existing names
+fn not_found(name: &str) -> anyhow::Error {
+    // Allow roughly one typo per three characters, but always at least two.
+    let max_distance = (name.chars().count() / 3).max(2);
+    let mut candidates = list()
+        .into_iter()
+        .map(|candidate| (strsim::levenshtein(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+    candidates.sort();
+    let suggestions = candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| format!("{candidate:?}"))
+        .collect::<Vec<_>>();
+    match suggestions.as_slice() {
+        [] => anyhow!("workspace {name:?} does not exist"),
+        [one] => anyhow!("workspace {name:?} does not exist, did you mean {one}?"),
+        many => anyhow!(
+            "workspace {name:?} does not exist, did you mean one of {}?",
+            many.join(", "),
+        ),
+    }
 }
 
 /// Returns a virtual workspace for the home directory
@@ -83,21 +309,46 @@ fn home() -> Result<Workspace> {
         .to_str()
         .context("home directory path is not valid utf-8")?
         .to_owned();
+    unregistered("~", home)
+}
+
+/// Name of the scratch workspace, an unregistered workspace for the directory given to `scratch`
+pub const SCRATCH: &str = "~scratch";
+
+/// Returns the scratch workspace
+fn scratch() -> Result<Workspace> {
+    let dir = cache::read(Key::Scratch)
+        .context("there's no scratch workspace, create one with `scratch [DIR]`")?;
+    unregistered(SCRATCH, dir)
+}
+
+/// Returns workspace `name` in `dir` which doesn't have a definition file
+///
+/// It only has the defaults from the global config.
+fn unregistered(name: &str, dir: String) -> Result<Workspace> {
     let (editor, shell) = config::read()?
         .map(|config| (config.editor, config.shell))
         .unwrap_or_default();
     Ok(Workspace {
-        name: "~".to_owned(),
-        dir: home,
-        ssh: None,
+        name: name.to_owned(),
+        dir,
         editor,
         shell,
+        ..Default::default()
     })
 }
 
-/// Create a new workspace definition
-pub fn create(workspace: &Workspace) -> Result<()> {
-    let path = file_path(&workspace.name)?;
+/// Create a new workspace definition written in `format`, optionally encrypted
+pub fn create(workspace: &Workspace, format: Format, encrypt: bool) -> Result<()> {
+    ensure!(
+        !exists(&workspace.name)?,
+        "workspace {:?} already exists",
+        workspace.name,
+    );
+    let mut path = file_path(&workspace.name, format)?;
+    if encrypt {
+        path = encrypted_path(path);
+    }
 
     // Create parent directory when we are creating a new workspace.
     let parent = path.parent().unwrap_or_else(|| {
@@ -106,12 +357,18 @@ pub fn create(workspace: &Workspace) -> Result<()> {
     fs::create_dir_all(parent)
         .with_context(|| format!("could not create parent directory for workspace at {path:?}"))?;
 
-    let buf = toml::to_string_pretty(workspace).unwrap_or_else(|error| {
+    let buf = parse::to_string(workspace, format).unwrap_or_else(|error| {
         panic!("workspace config should always be serializable but it wasn't.\nerror={error}\nconfig={workspace:#?}\n")
     });
+    let buf = if encrypt {
+        age::encrypt(&buf)?
+    } else {
+        buf.into_bytes()
+    };
     AtomicFile::new(&path, atomicwrites::DisallowOverwrite)
-        .write(|file| file.write_all(buf.as_bytes()))
+        .write(|file| file.write_all(&buf))
         .with_context(|| format!("atomically write workspace file at {path:?}"))?;
+    record_change()?;
     println!(
         "created workspace {name:?} at {path:?}",
         name = &workspace.name,
@@ -123,19 +380,86 @@ pub fn create(workspace: &Workspace) -> Result<()> {
 ///
 /// List is sorted by file name.
 pub fn list() -> Vec<String> {
-    let dir = match dir_path() {
-        Ok(dir) => dir,
+    collect(names(|_| true))
+}
+
+/// File with gitignore rules for paths in the workspaces directory which aren't workspaces
+const IGNORE_FILE: &str = ".wsignore";
+
+/// Returns the rules of the [`IGNORE_FILE`] in workspaces directory `dir`
+///
+/// Rules which can't be read are reported and ignore nothing.
+fn ignore_rules(dir: &Path) -> Gitignore {
+    let path = dir.join(IGNORE_FILE);
+    let mut builder = GitignoreBuilder::new(dir);
+    match builder.add(&path) {
+        None => {}
+        Some(err)
+            if err
+                .io_error()
+                .is_some_and(|err| err.kind() == ErrorKind::NotFound) =>
+        {
+            return Gitignore::empty();
+        }
+        Some(err) => eprintln!("WARN reading ignore rules {path:?}: {err}"),
+    }
+    builder.build().unwrap_or_else(|err| {
+        eprintln!("WARN reading ignore rules {path:?}: {err}");
+        Gitignore::empty()
+    })
+}
+
+/// Sorts streamed workspace names and removes duplicates, reporting an error as an empty list
+fn collect(names: Result<impl Iterator<Item = String>>) -> Vec<String> {
+    let mut names = match names {
+        Ok(names) => names.collect::<Vec<_>>(),
         Err(err) => {
             eprintln!("ERR  reading workspace list: {err}");
             return Vec::new();
         }
     };
-    WalkDir::new(&dir)
-        .sort_by_file_name()
+    // The same name in several formats is reported when the workspace is read.
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Streams the names of workspace definitions, only descending into directories for which
+/// `descend` returns `true`
+///
+/// `descend` is passed directory paths relative to the workspaces directory, like group names.
+/// Names come in walk order and a name defined in several formats comes once per format.
+///
+/// The workspaces directory itself may be a symlink. Symlinked group directories are followed
+/// unless `list.follow_symlinks` is disabled in the global config, except for those pointing back
+/// into the workspaces directory, whose definitions are already listed under their real names.
+///
+/// Hidden files and directories, like `.git` or `.trash`, and paths matching the gitignore rules in
+/// [`IGNORE_FILE`] at the top of the workspaces directory are skipped.
+fn names(mut descend: impl FnMut(&str) -> bool) -> Result<impl Iterator<Item = String>> {
+    let dir = dir_path()?;
+    let follow = config::read()?
+        .and_then(|config| config.list)
+        .and_then(|list| list.follow_symlinks)
+        .unwrap_or(true);
+    // A missing directory has no workspaces, walking it reports that.
+    let canonical = dir.canonicalize().unwrap_or_else(|_| dir.clone());
+    let rules = ignore_rules(&dir);
+    let root = dir.clone();
+    let encrypted_suffix = format!(".{}", age::EXTENSION);
+    let names = WalkDir::new(&dir)
+        .follow_links(follow)
         .into_iter()
-        .filter_entry(|entry| {
+        .filter_entry(move |entry| {
+            if entry.depth() > 0 {
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                let is_dir = entry.file_type().is_dir();
+                if hidden || rules.matched(entry.path(), is_dir).is_ignore() {
+                    return false;
+                }
+            }
             // Filter out invalid names of directories recursively
-            entry
+            let valid = entry
                 .file_name()
                 .to_str()
                 .map(|name| {
@@ -162,30 +486,409 @@ pub fn list() -> Vec<String> {
                         entry.path(),
                     );
                     false
-                })
+                });
+            if !valid || entry.depth() == 0 || !entry.file_type().is_dir() {
+                return valid;
+            }
+            if entry.path_is_symlink() {
+                let inside = entry
+                    .path()
+                    .canonicalize()
+                    .is_ok_and(|target| target.starts_with(&canonical));
+                if inside {
+                    let path = entry.path();
+                    tracing::debug!(?path, "ignoring symlink into the workspaces directory");
+                    return false;
+                }
+            }
+            let relative = entry
+                .path()
+                .strip_prefix(&root)
+                .expect("all files must be within the base directory");
+            // Paths with invalid utf-8 were filtered out already.
+            descend(relative.to_str().unwrap_or_default())
         })
         .filter_map(|res| match res {
             // Filter out IO errors
             Ok(entry) => Some(entry),
+            Err(err) if err.loop_ancestor().is_some() => {
+                let path = err.path().expect("loops have a path");
+                tracing::debug!(?path, "ignoring symlink loop");
+                None
+            }
             Err(err) => {
                 eprintln!("WARN encountered an error while gathering workspace list: {err}");
                 None
             }
         })
-        .filter(|entry| entry.path().is_file())
-        .filter_map(|entry| {
+        // The file type comes with the directory listing, only symlinks need another stat.
+        .filter(|entry| {
+            entry.file_type().is_file() || (entry.path_is_symlink() && entry.path().is_file())
+        })
+        .filter_map(move |entry| {
             entry
                 .path()
                 .strip_prefix(&dir)
                 .expect("all files must be within the base directory")
                 .to_str()
-                .and_then(|name| name.strip_suffix(".toml"))
+                .map(|name| name.strip_suffix(&encrypted_suffix).unwrap_or(name))
+                .and_then(|name| {
+                    let extension = extension(name)?;
+                    Format::from_extension(extension)?;
+                    name.strip_suffix(extension)?.strip_suffix('.')
+                })
                 .map(|s| s.to_owned())
+        });
+    Ok(names)
+}
+
+/// Set `key` to `value` in the definition of workspace `name`
+///
+/// `key` is a `.` separated path into the definition, missing tables along the way are created. The
+/// file is edited as written on disk, defaults from the global config are not merged in and
+/// comments and formatting are preserved.
+pub fn set(name: &str, key: &str, value: toml_edit::Value) -> Result<()> {
+    update(name, |document| set_key(document, key, value))
+        .with_context(|| format!("setting {key:?}"))?;
+    println!("set {key:?} in workspace {name:?}");
+    Ok(())
+}
+
+/// Sets `.` separated `key` in `document` to `value`, creating the tables leading to it
+///
+/// Comments around an existing value are kept.
+pub fn set_key(document: &mut Document, key: &str, mut value: toml_edit::Value) -> Result<()> {
+    // Let the surrounding document decide how the value is spaced.
+    value.decor_mut().clear();
+    let (parents, last) = split_key(key);
+    let mut cursor = document.as_table_mut() as &mut dyn TableLike;
+    for segment in parents {
+        cursor = cursor
+            .entry(segment)
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+            .with_context(|| format!("cannot set {key:?}, {segment:?} is not a table"))?;
+    }
+    match cursor.get_mut(last) {
+        // Replace the value in place to keep the comments around it.
+        Some(Item::Value(existing)) => {
+            *value.decor_mut() = existing.decor().clone();
+            *existing = value;
+        }
+        Some(item) => *item = toml_edit::value(value),
+        None => {
+            cursor.insert(last, toml_edit::value(value));
+        }
+    }
+    Ok(())
+}
+
+/// Splits a `.` separated key into the parent tables and the last segment
+fn split_key(key: &str) -> (Vec<&str>, &str) {
+    match key.rsplit_once('.') {
+        Some((parents, last)) => (parents.split('.').collect(), last),
+        None => (Vec::new(), key),
+    }
+}
+
+/// Edit the definition of workspace `name` as written on disk
+///
+/// The file is edited through [`toml_edit`] so user comments and formatting survive. The edited
+/// definition is checked to still be valid before it's written back. Only TOML definitions can be
+/// edited.
+fn update(name: &str, edit: impl FnOnce(&mut Document) -> Result<()>) -> Result<()> {
+    let (
+        File {
+            path,
+            format,
+            encrypted,
+        },
+        buf,
+    ) = read_file(name)?;
+    ensure!(
+        format == Format::Toml,
+        "only TOML workspace definitions can be edited, edit {path:?} by hand",
+    );
+    let mut document = buf
+        .parse::<Document>()
+        .with_context(|| format!("parsing workspace file at {path:?}"))?;
+    edit(&mut document)?;
+
+    let buf = document.to_string();
+    parse::check::<Workspace>(&path, &buf)
+        .context("edit results in an invalid workspace definition")?;
+    let buf = if encrypted {
+        age::encrypt(&buf)?
+    } else {
+        buf.into_bytes()
+    };
+    AtomicFile::new(&path, atomicwrites::AllowOverwrite)
+        .write(|file| file.write_all(&buf))
+        .with_context(|| format!("atomically write workspace file at {path:?}"))?;
+    record_change()
+}
+
+/// Opens the definition of workspace `name` in `editor` and saves it once it's valid
+///
+/// The definition is edited in a hidden draft next to it, which isn't listed as a workspace. An
+/// invalid draft is opened again if the user wants to fix it, or else kept and edited the next
+/// time. Returns `true` if the definition changed.
+///
+/// Only TOML definitions can be edited, and encrypted ones can't be as the draft would be written
+/// decrypted.
+pub fn edit(name: &str, editor: &str) -> Result<bool> {
+    let (
+        File {
+            path,
+            format,
+            encrypted,
+        },
+        original,
+    ) = read_file(name)?;
+    ensure!(
+        format == Format::Toml,
+        "only TOML workspace definitions can be edited, edit {path:?} by hand",
+    );
+    ensure!(
+        !encrypted,
+        "workspace {name:?} is encrypted, editing it would write it decrypted to disk",
+    );
+    let file_name = path.file_name().expect("workspace file has a name");
+    let mut draft_name = OsString::from(".");
+    draft_name.push(Path::new(file_name).with_extension("draft.toml"));
+    let draft = path.with_file_name(draft_name);
+    let buf = match fs::read_to_string(&draft) {
+        Ok(buf) => {
+            eprintln!("INFO continuing the unsaved edit in {draft:?}");
+            buf
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => original.clone(),
+        Err(err) => return Err(err).with_context(|| format!("reading draft at {draft:?}")),
+    };
+    fs::write(&draft, &buf).with_context(|| format!("writing draft at {draft:?}"))?;
+    let buf = prompt::edit(
+        editor,
+        &draft,
+        &format!("workspace {name:?}"),
+        |path, buf| {
+            let buf = migrate::upgrade(buf.to_owned(), migrate::Kind::Workspace)
+                .with_context(|| format!("upgrading workspace file at {path:?}"))?;
+            parse::check_strict::<Workspace>(path, &buf)
+        },
+    )?;
+    let changed = buf != original;
+    if changed {
+        AtomicFile::new(&path, atomicwrites::AllowOverwrite)
+            .write(|file| file.write_all(buf.as_bytes()))
+            .with_context(|| format!("atomically write workspace file at {path:?}"))?;
+        record_change()?;
+    }
+    fs::remove_file(&draft).with_context(|| format!("removing draft at {draft:?}"))?;
+    Ok(changed)
+}
+
+/// Locks the definition of workspace `name` against modification, or unlocks it unless `locked`
+pub fn set_locked(name: &str, locked: bool) -> Result<()> {
+    update(name, |document| {
+        match locked {
+            true => set_key(document, "locked", toml_edit::Value::from(true))?,
+            false => drop(document.remove("locked")),
+        }
+        Ok(())
+    })
+}
+
+/// Returns `true` if the definition of workspace `name` is locked against modification
+///
+/// Only looks at the `locked` key so even definitions which fail to parse can be checked.
+pub fn is_locked(name: &str) -> Result<bool> {
+    let (File { path, format, .. }, buf) = read_file(name)?;
+    let value = parse::value(&path, &buf, format)
+        .with_context(|| format!("parsing workspace file at {path:?}"))?;
+    Ok(value.get("locked").and_then(Value::as_bool) == Some(true))
+}
+
+/// Group holding archived workspaces
+pub const ARCHIVE: &str = "archive";
+
+/// Returns `true` if workspace `name` is in the archive group
+pub fn is_archived(name: &str) -> bool {
+    name.strip_prefix(ARCHIVE)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Returns `true` if `pattern` contains glob metacharacters
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// List all workspace definitions matching a glob `pattern`
+///
+/// `*` and `?` don't match across `/`, use `**` to match any number of groups. Archived workspaces
+/// only match patterns starting with the archive group.
+pub fn matching(pattern: &str) -> Result<Vec<String>> {
+    let include_archived = is_archived(pattern);
+    let glob =
+        glob::Pattern::new(pattern).with_context(|| format!("parsing glob pattern {pattern:?}"))?;
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    // Directories are matched against the pattern's leading components, up to the first `**`
+    // which can match any number of them, to skip groups which can't contain a match.
+    let components = pattern.split('/').collect::<Vec<_>>();
+    let depth = (!pattern.contains("**")).then_some(components.len());
+    let groups = components[..components.len() - 1]
+        .iter()
+        .map_while(|component| {
+            (!component.contains("**"))
+                .then(|| glob::Pattern::new(component).ok())
+                .flatten()
         })
-        .collect()
+        .collect::<Vec<_>>();
+    let descend = |dir: &str| {
+        if !include_archived && dir == ARCHIVE {
+            return false;
+        }
+        let dirs = dir.split('/').collect::<Vec<_>>();
+        if depth.is_some_and(|depth| dirs.len() >= depth) {
+            return false;
+        }
+        dirs.iter()
+            .zip(&groups)
+            .all(|(dir, group)| group.matches_with(dir, options))
+    };
+    Ok(collect(names(descend))
+        .into_iter()
+        .filter(|name| include_archived || !is_archived(name))
+        .filter(|name| glob.matches_with(name, options))
+        .collect())
+}
+
+/// Returns when the definition of workspace `name` was created
+///
+/// Falls back to the last modification time on platforms and filesystems which don't record
+/// creation time.
+pub fn created(name: &str) -> Result<SystemTime> {
+    let File { path, .. } = find_file(name)?.ok_or_else(|| not_found(name))?;
+    let metadata = fs::metadata(&path).with_context(|| format!("reading metadata of {path:?}"))?;
+    metadata
+        .created()
+        .or_else(|_| metadata.modified())
+        .with_context(|| format!("reading creation time of {path:?}"))
+}
+
+/// Returns a token which changes whenever `workspacectl` creates, changes, moves or removes a
+/// definition
+///
+/// Reading it is a single small read, unlike walking the definitions. Definitions changed by hand
+/// aren't noticed until the next change through `workspacectl` or `cache clear`.
+pub fn generation() -> Result<String> {
+    match cache::read(Key::Generation) {
+        Ok(generation) => Ok(generation),
+        Err(_) => record_change().and_then(|()| cache::read(Key::Generation)),
+    }
+}
+
+/// Records a change to the definitions by writing a new [`generation`]
+///
+/// The token is unique rather than counted up, so concurrent changes can't end up with the token
+/// an index was built with.
+fn record_change() -> Result<()> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let generation = format!("{nanos}-{}", std::process::id());
+    cache::write(Key::Generation, generation).context("recording change to workspace definitions")
+}
+
+/// Upgrade the definition of workspace `name` on disk to the current schema version
+///
+/// Returns the version the definition was upgraded from, or `None` if it was already current.
+/// Definitions not written in TOML and encrypted definitions are left alone.
+pub fn upgrade(name: &str, dry_run: bool) -> Result<Option<i64>> {
+    match find_file(name)?.ok_or_else(|| not_found(name))? {
+        File {
+            path,
+            format: Format::Toml,
+            encrypted: false,
+        } => migrate::rewrite(&path, migrate::Kind::Workspace, dry_run),
+        _ => Ok(None),
+    }
+}
+
+/// Returns `true` if the definition of workspace `name` is encrypted
+pub fn is_encrypted(name: &str) -> Result<bool> {
+    Ok(find_file(name)?.is_some_and(|file| file.encrypted))
+}
+
+/// Returns `true` if a workspace definition with name `name` exists
+pub fn exists(name: &str) -> Result<bool> {
+    Ok(find_file(name)?.is_some())
+}
+
+/// Move workspace definition `old` to the name `new`
+///
+/// Never overwrites an existing definition. Group directories left empty by the move are removed.
+/// The definition keeps its format.
+pub fn rename(old: &str, new: &str) -> Result<()> {
+    let File {
+        path: old_path,
+        format,
+        encrypted,
+    } = find_file(old)?.ok_or_else(|| not_found(old))?;
+    let mut new_path = file_path(new, format)?;
+    if encrypted {
+        new_path = encrypted_path(new_path);
+    }
+    ensure!(!exists(new)?, "workspace {new:?} already exists");
+
+    let parent = new_path.parent().unwrap_or_else(|| {
+        panic!("workspace file path should always have a parent.\npath={new_path:?}\n")
+    });
+    fs::create_dir_all(parent).with_context(|| {
+        format!("could not create parent directory for workspace at {new_path:?}")
+    })?;
+    atomicwrites::move_atomic(&old_path, &new_path)
+        .with_context(|| format!("atomically move workspace file {old_path:?} to {new_path:?}"))?;
+    remove_empty_parents(&old_path)?;
+    record_change()?;
+    println!("moved workspace {old:?} to {new:?}");
+    Ok(())
+}
+
+/// Remove the definition of workspace `name`
+///
+/// Group directories left empty are removed.
+pub fn remove(name: &str) -> Result<()> {
+    let File { path, .. } = find_file(name)?.ok_or_else(|| not_found(name))?;
+    fs::remove_file(&path).with_context(|| format!("removing workspace file {path:?}"))?;
+    remove_empty_parents(&path)?;
+    record_change()
+}
+
+/// Removes empty group directories between `path` and the workspace directory
+fn remove_empty_parents(path: &Path) -> Result<()> {
+    let dir = dir_path()?;
+    for parent in path.ancestors().skip(1) {
+        if parent == dir || !parent.starts_with(&dir) {
+            break;
+        }
+        let is_empty = fs::read_dir(parent)
+            .with_context(|| format!("reading group directory {parent:?}"))?
+            .next()
+            .is_none();
+        if !is_empty {
+            break;
+        }
+        fs::remove_dir(parent)
+            .with_context(|| format!("removing empty group directory {parent:?}"))?;
+    }
+    Ok(())
 }
 
 pub fn current() -> Result<Workspace> {
-    let name = cache::read(Key::Current).context("get current workspace name")?;
+    let name = session::current().context("get current workspace name")?;
     read(&name).context("read current workspace definition")
 }
@@ -1,11 +1,14 @@
 use serde_derive::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Workspace {
     /// Name of the workspace is definied in the file name
     #[serde(skip)]
     pub name: String,
 
+    /// Schema version of the definition file
+    pub version: Option<i64>,
+
     /// Root directory for workspace
     pub dir: String,
 
@@ -17,14 +20,78 @@ pub struct Workspace {
 
     /// Shell configuration
     pub shell: Option<Shell>,
+
+    /// Git repository in the workspace directory
+    pub git: Option<Git>,
+
+    /// Commands run when the workspace is opened or closed
+    pub hooks: Option<Hooks>,
+
+    /// tmux session the terminal is attached to, see [`crate::tmux`]
+    pub tmux: Option<Tmux>,
+
+    /// Nix environment shells are started in, see [`crate::nix`]
+    pub nix: Option<Nix>,
+
+    /// Python environment of terminals and editors, see [`crate::python`]
+    pub python: Option<Python>,
+
+    /// Free-form tags for organizing and searching workspaces
+    pub tags: Option<Vec<String>>,
+
+    /// Free-form notes about the workspace
+    pub notes: Option<String>,
+
+    /// Locked workspaces can't be modified without passing `--unlock`
+    pub locked: Option<bool>,
+
+    /// Workspaces opened before this one whenever it's opened
+    pub depends_on: Option<Vec<String>>,
+
+    /// Seconds non-interactive commands may take, at least 1, overrides the global `timeout`
+    pub timeout: Option<u64>,
+
+    /// Locale of shells, editors and commands, exported as `LANG` and `LC_ALL`
+    ///
+    /// Takes precedence over `ssh.locale` in remote sessions.
+    pub locale: Option<String>,
+
+    /// Time zone of shells, editors and commands, exported as `TZ`
+    pub tz: Option<String>,
+
+    /// Terminal multiplexer terminals attach to, overrides the global `multiplexer`
+    ///
+    /// Without one set anywhere, terminals attach to tmux if there's a `[tmux]` table.
+    pub multiplexer: Option<Multiplexer>,
+
+    /// Whether `.workspacectl.toml` in `dir` is merged in, see [`crate::project`], `true` if not
+    /// set
+    pub project_config: Option<bool>,
 }
 
 /// SSH connection options
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Ssh {
-    /// The ssh command. Defaults to `ssh`
+    /// Client connecting to the host, `openssh` if not set
+    pub kind: Option<SshKind>,
+
+    /// The ssh command. Defaults to `ssh`, or `tsh ssh` for Teleport
+    ///
+    /// Can be a wrapper with arguments, like `tsh ssh` or `gcloud compute ssh`. Wrappers which
+    /// don't take ssh's arguments need `args`.
     pub command: Option<String>,
 
+    /// Arguments of `command` selecting the destination, replacing ssh's `-l`, `-p`, `-i` and host
+    ///
+    /// The placeholders `{{host}}`, `{{user}}`, `{{port}}` and `{{identity_file}}` are replaced by
+    /// the settings of this table, arguments with a placeholder which isn't set are left out. The
+    /// options `workspacectl` passes to ssh, like `-t` and `-o ConnectTimeout=30`, go where an
+    /// argument is `{{options}}` and are left out without one. The remote command comes last.
+    ///
+    /// For example `args = ["{{host}}", "--zone=europe-west1-b", "--", "{{options}}"]` with
+    /// `command = "gcloud compute ssh"`.
+    pub args: Option<Vec<String>>,
+
     /// Destination `user`
     ///
     /// Passed as the `-l` option to the `ssh` command if present.
@@ -42,18 +109,203 @@ pub struct Ssh {
 
     /// Identity file
     ///
-    /// Passed as the `-i` option to the `ssh` command if present.
+    /// Passed as the `-i` option to the `ssh` command if present. Not used with Teleport, which
+    /// authenticates with the certificates of `tsh login`.
     pub identity_file: Option<String>,
+
+    /// Host key verification policy, overrides the global `ssh.host_key`
+    pub host_key: Option<HostKey>,
+
+    /// Terminfo handling in remote sessions, overrides the global `ssh.terminfo`
+    pub terminfo: Option<Terminfo>,
+
+    /// `TERM` used with `terminfo = "term"`, overrides the global `ssh.term`
+    pub term: Option<String>,
+
+    /// Locale of remote sessions, overrides the global `ssh.locale`
+    pub locale: Option<String>,
+
+    /// Environment variables passed to remote sessions with ssh's `SendEnv`
+    ///
+    /// Values come from the workspace secrets or the environment `workspacectl` runs in. Servers
+    /// which don't `AcceptEnv` them get the variables exported from a private file copied over
+    /// before the session starts, never from the remote command line.
+    pub send_env: Option<Vec<String>>,
+}
+
+/// Client connecting to a remote workspace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SshKind {
+    /// OpenSSH's `ssh` and `scp`
+    Openssh,
+
+    /// Teleport's `tsh ssh` and `tsh scp`, for hosts behind a Teleport proxy
+    ///
+    /// Teleport verifies hosts and times out itself, so the host key policy, the connection
+    /// timeout and `send_env` don't apply. Connecting needs a session from `tsh login`.
+    Teleport,
+}
+
+/// How `ssh` verifies the host key of the remote machine
+///
+/// Without a policy set the user's ssh config decides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostKey {
+    /// Only connect to hosts whose key is already known
+    Strict,
+
+    /// Remember keys of new hosts, refuse changed keys
+    AcceptNew,
+
+    /// Ask before connecting to a new host
+    Ask,
+}
+
+/// How remote sessions get a terminfo entry for the local terminal
+///
+/// Without one the remote session uses the local `TERM`, which the remote may not know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Terminfo {
+    /// Connect with kitty's ssh kitten, which copies the terminfo over
+    Kitten,
+
+    /// Set `TERM` to a value the remote knows, `xterm-256color` unless `term` is set
+    Term,
+
+    /// Install the local terminfo entry on the remote when it's missing
+    Push,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Editor {
     /// Editor command
+    ///
+    /// Remote workspaces edited with `code` are opened in the local VS Code over Remote-SSH, see
+    /// [`crate::vscode`].
     pub command: String,
+
+    /// Window title, defaults to the command and workspace directory
+    pub title: Option<String>,
+
+    /// Run the command without a terminal window, for GUI editors of local workspaces
+    ///
+    /// The command is run by the shell with the workspace directory as the last argument, for
+    /// example `open -a 'Visual Studio Code'` on macOS.
+    pub gui: Option<bool>,
+
+    /// How the editor is run when it isn't a program in a terminal window, takes precedence over
+    /// `gui`
+    pub kind: Option<EditorKind>,
+
+    /// Product code of the IDE opened with `kind = "jetbrains-gateway"`, for example `IU` for
+    /// IntelliJ IDEA Ultimate or `CL` for CLion
+    pub product: Option<String>,
+}
+
+/// Editors which are run in their own way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EditorKind {
+    /// A new frame of the Emacs server, opened by the command which should be `emacsclient`
+    ///
+    /// The server is started if it isn't running. Remote workspaces are opened over TRAMP.
+    Emacs,
+
+    /// The IDE `product` on the host of a remote workspace, connected to with JetBrains Gateway
+    ///
+    /// The command is run by the shell with the `jetbrains-gateway://` URL as the last argument,
+    /// for example `xdg-open` or `open`.
+    JetbrainsGateway,
+}
+
+/// Shell commands run on workspace events, see [`crate::hooks`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hooks {
+    /// Run after the workspace is opened
+    pub on_open: Option<Vec<String>>,
+
+    /// Run when the workspace is closed
+    pub on_close: Option<Vec<String>>,
+
+    /// Run before a terminal window of the workspace opens
+    pub on_terminal: Option<Vec<String>>,
+
+    /// Run before an editor of the workspace opens
+    pub on_editor: Option<Vec<String>>,
+}
+
+/// tmux session of the workspace
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tmux {
+    /// Layout file applied when the session is created, relative to the config directory
+    pub layout: Option<String>,
+}
+
+/// Terminal multiplexer the terminals of a workspace attach to, see [`crate::tmux`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Multiplexer {
+    /// A tmux session named after the workspace, on its host for remote workspaces
+    Tmux,
+
+    /// A plain shell in every terminal
+    None,
+}
+
+/// Nix environment of the workspace
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Nix {
+    /// Flake reference of the `nix develop` environment, `.` if not set
+    pub flake: Option<String>,
+
+    /// How the environment is entered, `develop` if not set
+    pub command: Option<NixCommand>,
+}
+
+/// Command entering a Nix environment
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NixCommand {
+    /// `nix develop` with the flake
+    #[default]
+    Develop,
+
+    /// `nix-shell` with the `shell.nix` or `default.nix` of the workspace directory
+    NixShell,
+}
+
+/// Python environment of the workspace
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Python {
+    /// Virtualenv directory activated in terminals and editors, relative to the workspace directory
+    pub venv: Option<String>,
+
+    /// Name of the conda environment terminals and editors run in
+    pub conda: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Git {
+    /// Branch the repository should be on, checked when the workspace is opened
+    pub branch: Option<String>,
+
+    /// Git UI opened by `git-ui`, overrides the global `git.ui`
+    pub ui: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Shell {
-    /// Shell command
-    pub command: String,
+    /// Shell command, the detected shell of the remote user or the login shell of the local user
+    /// if not set
+    pub command: Option<String>,
+
+    /// Window title, defaults to the terminal's own title
+    pub title: Option<String>,
+
+    /// Start the shell as a login shell, `true` for remote workspaces and `false` for local ones if
+    /// not set
+    pub login: Option<bool>,
 }
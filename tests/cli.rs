@@ -0,0 +1,762 @@
+//! End-to-end tests of `wsctl` in a sandbox
+//!
+//! Every test gets its own temporary home, config, cache and state directories, so the real ones
+//! are never touched.
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use tempfile::TempDir;
+
+/// Temporary directories `wsctl` runs in
+struct Sandbox {
+    root: TempDir,
+
+    /// Extra environment variables `wsctl` runs with
+    env: Vec<(String, String)>,
+}
+
+impl Sandbox {
+    fn new() -> Sandbox {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("home")).unwrap();
+        Sandbox {
+            root,
+            env: Vec::new(),
+        }
+    }
+
+    /// Runs `wsctl` with environment variable `key` set to `value`
+    fn env(mut self, key: &str, value: &str) -> Sandbox {
+        self.env.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.root.path().join(path)
+    }
+
+    /// Creates directory `path` to create a workspace in
+    fn dir(&self, path: &str) -> PathBuf {
+        let dir = self.path(path);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Runs `wsctl` with `args`, writing `stdin` to its input
+    fn run_with_input(&self, args: &[&str], stdin: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_wsctl"))
+            .args(args)
+            .env("HOME", self.path("home"))
+            .env("WORKSPACECTL_CONFIG_DIR", self.path("config"))
+            .env("WORKSPACECTL_CACHE_DIR", self.path("cache"))
+            .env("WORKSPACECTL_STATE_DIR", self.path("state"))
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(stdin.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Runs `wsctl` with `args` and returns its output, failing the test if it fails
+    fn run(&self, args: &[&str]) -> String {
+        let output = self.run_with_input(args, "");
+        assert!(
+            output.status.success(),
+            "wsctl {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr),
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Runs `wsctl` with `args` and returns its error output, failing the test if it succeeds
+    fn fail(&self, args: &[&str]) -> String {
+        let output = self.run_with_input(args, "");
+        assert!(!output.status.success(), "wsctl {args:?} succeeded");
+        String::from_utf8(output.stderr).unwrap()
+    }
+
+    /// Returns the workspaces `list` prints with `args`
+    fn list(&self, args: &[&str]) -> Vec<String> {
+        let args = [&["list"], args].concat();
+        self.run(&args).lines().map(str::to_owned).collect()
+    }
+}
+
+fn str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn create_and_list() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("projects/foo");
+    sandbox.run(&["new", str(&dir)]);
+    sandbox.run(&["new", str(&dir), "group/bar"]);
+
+    assert!(sandbox.path("config/workspaces/foo.toml").is_file());
+    assert_eq!(sandbox.list(&[]), ["~", "foo", "group/bar"]);
+    assert_eq!(sandbox.list(&["group/*"]), ["group/bar"]);
+}
+
+#[test]
+fn create_in_group() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("projects/foo");
+    sandbox.run(&["new", str(&dir), "--group", "work/acme/"]);
+    assert_eq!(sandbox.list(&[]), ["~", "work/acme/foo"]);
+}
+
+#[test]
+fn concurrent_opens_keep_history() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| sandbox.run(&["open", "foo"]));
+        }
+    });
+    let history = fs::read_to_string(sandbox.path("cache/history")).unwrap();
+    assert_eq!(history.lines().count(), 8, "{history}");
+}
+
+#[test]
+fn delete_forgets_workspace() {
+    let sandbox = Sandbox::new();
+    let foo = sandbox.dir("foo");
+    let bar = sandbox.dir("bar");
+    sandbox.run(&["new", str(&foo)]);
+    sandbox.run(&["new", str(&bar)]);
+    sandbox.run(&["open", "bar"]);
+    sandbox.run(&["open", "foo"]);
+    for key in ["nvim/foo", "status/foo", "projects/foo"] {
+        let path = sandbox.path(&format!("cache/{key}"));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+    }
+
+    let err = sandbox.fail(&["delete", "foo"]);
+    assert!(err.contains("without a terminal"), "{err}");
+    sandbox.run(&["delete", "foo", "--force"]);
+
+    let list = sandbox.run(&["list"]);
+    assert!(!list.contains("foo"), "{list}");
+    let history = fs::read_to_string(sandbox.path("cache/history")).unwrap();
+    assert!(history.ends_with("\tbar\n"), "{history}");
+    assert!(!history.contains("foo"), "{history}");
+    assert!(!sandbox.path("cache/current").exists());
+    for key in ["nvim/foo", "status/foo", "projects/foo"] {
+        assert!(!sandbox.path(&format!("cache/{key}")).exists(), "{key}");
+    }
+    let err = sandbox.fail(&["delete", "foo", "--force"]);
+    assert!(err.contains("does not exist"), "{err}");
+}
+
+#[test]
+fn delete_by_pattern() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("foo");
+    for name in ["group/bar", "group/baz", "foo"] {
+        sandbox.run(&["new", str(&dir), name]);
+    }
+
+    let err = sandbox.fail(&["delete", "group/*"]);
+    assert!(err.contains("without a terminal"), "{err}");
+    let output = sandbox.run_with_input(&["delete", "group/*", "--dry-run"], "");
+    assert!(output.status.success());
+    let preview = String::from_utf8(output.stderr).unwrap();
+    assert!(preview.contains("deleting 2 workspace(s):"), "{preview}");
+    assert!(
+        preview.contains("    group/bar\n    group/baz\n"),
+        "{preview}"
+    );
+    assert_eq!(sandbox.list(&[]), ["~", "foo", "group/bar", "group/baz"]);
+
+    let out = sandbox.run(&["--yes", "delete", "group/*"]);
+    assert!(out.contains("deleted workspace \"group/baz\""), "{out}");
+    assert_eq!(sandbox.list(&[]), ["~", "foo"]);
+    assert!(!sandbox.path("config/workspaces/group").exists());
+}
+
+#[test]
+fn delete_without_keyring() {
+    let sandbox = Sandbox::new().env("DBUS_SESSION_BUS_ADDRESS", "unix:path=/nonexistent");
+    let foo = sandbox.dir("foo");
+    sandbox.run(&["new", str(&foo)]);
+    let err = sandbox.fail(&["secret", "list", "foo"]);
+    assert!(err.contains("keyring"), "{err}");
+
+    // Without a keyring there are no secrets to remove, so deleting goes ahead.
+    let out = sandbox.run(&["delete", "foo", "--force"]);
+    assert!(out.contains("deleted workspace \"foo\""), "{out}");
+    let list = sandbox.run(&["list"]);
+    assert!(!list.contains("foo"), "{list}");
+}
+
+#[test]
+fn edit_keeps_invalid_draft() {
+    let sandbox = Sandbox::new();
+    // The editor replaces the draft with the contents of `next`.
+    let next = sandbox.path("next");
+    let editor = sandbox.path("editor");
+    fs::write(&editor, format!("#!/bin/sh\ncp '{}' \"$1\"\n", str(&next))).unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+    let sandbox = sandbox.env("VISUAL", str(&editor));
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    let path = sandbox.path("config/workspaces/foo.toml");
+    let draft = sandbox.path("config/workspaces/.foo.draft.toml");
+    let original = fs::read_to_string(&path).unwrap();
+
+    fs::write(&next, "dir = [").unwrap();
+    let err = sandbox.fail(&["edit", "foo"]);
+    assert!(err.contains("not saved, the draft is kept"), "{err}");
+    assert_eq!(fs::read_to_string(&path).unwrap(), original);
+    assert_eq!(fs::read_to_string(&draft).unwrap(), "dir = [");
+
+    let edited = format!("{original}tz = \"UTC\"\n");
+    fs::write(&next, &edited).unwrap();
+    assert_eq!(sandbox.run(&["edit", "foo"]), "saved workspace \"foo\"\n");
+    assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+    assert!(!draft.exists());
+}
+
+#[test]
+fn gc_forgets_closed_terminals() {
+    let sandbox = Sandbox::new().env("WORKSPACECTL_SESSION", "dev_build");
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    sandbox.run(&["open", "foo"]);
+    let terminals = sandbox.path("cache/terminals");
+    assert!(terminals.join("env-dev_build").is_file());
+    fs::write(terminals.join("tty-%2Fdev%2Fpts%2F999999"), "foo").unwrap();
+    fs::write(terminals.join("dev_pts_1"), "foo").unwrap();
+
+    let printed = sandbox.run(&["gc"]);
+    assert!(
+        printed.contains(
+            "removed currently open workspace of ended session \"tty-%2Fdev%2Fpts%2F999999\""
+        ),
+        "{printed}"
+    );
+    assert!(!terminals.join("tty-%2Fdev%2Fpts%2F999999").exists());
+    assert!(!terminals.join("dev_pts_1").exists());
+    assert!(terminals.join("env-dev_build").exists());
+}
+
+#[test]
+fn session_names_stay_in_cache() {
+    let sandbox = Sandbox::new().env("WORKSPACECTL_SESSION", "..");
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    sandbox.run(&["open", "foo"]);
+    let session = sandbox.path("cache/terminals/env-%2E.");
+    assert_eq!(fs::read_to_string(session).unwrap(), "foo\n");
+}
+
+#[test]
+fn gc_forgets_deleted_workspaces() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    for key in ["status/foo", "status/group/gone", "projects/group/gone"] {
+        let path = sandbox.path(&format!("cache/{key}"));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "{}").unwrap();
+    }
+
+    let printed = sandbox.run(&["gc"]);
+    assert!(
+        printed.contains("removed cached status of deleted workspace \"group/gone\""),
+        "{printed}"
+    );
+    assert!(
+        printed.contains("removed cached project config of deleted workspace \"group/gone\""),
+        "{printed}"
+    );
+    assert!(!sandbox.path("cache/status/group/gone").exists());
+    assert!(!sandbox.path("cache/projects/group/gone").exists());
+    assert!(sandbox.path("cache/status/foo").exists());
+}
+
+#[test]
+fn host_names_stay_in_cache() {
+    let sandbox = Sandbox::new();
+    let hosts = sandbox.dir("cache/hosts");
+    fs::write(hosts.join("%2E."), "{}").unwrap();
+    fs::write(hosts.join("a%2Fb"), "{}").unwrap();
+    sandbox.run(&["cache", "clear", ".."]);
+    assert!(!hosts.join("%2E.").exists());
+    assert!(sandbox.path("cache").is_dir());
+
+    let printed = sandbox.run(&["gc"]);
+    assert!(
+        printed.contains("removed detected properties of unused host \"a/b\""),
+        "{printed}"
+    );
+    assert!(!hosts.join("a%2Fb").exists());
+}
+
+#[test]
+fn lock_and_unlock() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    assert_eq!(sandbox.run(&["lock", "foo"]), "locked workspace \"foo\"\n");
+    let err = sandbox.fail(&["set", "foo", "tz", "\"UTC\""]);
+    assert!(err.contains("is locked, pass --unlock"), "{err}");
+    assert_eq!(
+        sandbox.run(&["unlock", "foo"]),
+        "unlocked workspace \"foo\"\n"
+    );
+    sandbox.run(&["set", "foo", "tz", "\"UTC\""]);
+}
+
+#[test]
+fn create_existing_fails() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    let err = sandbox.fail(&["new", str(&dir)]);
+    assert!(err.contains("already exists"), "{err}");
+}
+
+#[test]
+fn open_sets_current() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    sandbox.run(&["open", "foo"]);
+
+    let info = sandbox.run(&["info", "--porcelain"]);
+    assert!(info.lines().any(|line| line == "name\tfoo"), "{info}");
+    let list = sandbox.run(&["list", "--porcelain"]);
+    let fields = list.trim_end().split('\t').collect::<Vec<_>>();
+    assert_eq!(fields[..3], ["foo", "0", "0"]);
+    assert!(!fields[3].is_empty(), "open wasn't recorded: {list}");
+}
+
+#[test]
+fn open_missing_suggests() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    let err = sandbox.fail(&["open", "fo"]);
+    assert!(err.contains("did you mean \"foo\""), "{err}");
+}
+
+#[test]
+fn move_and_archive() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+
+    let output = sandbox.run_with_input(&["move", "foo", "group/foo"], "y\n");
+    assert!(output.status.success());
+    assert_eq!(sandbox.list(&[]), ["~", "group/foo"]);
+
+    let output = sandbox.run_with_input(&["archive", "group/foo"], "y\n");
+    assert!(output.status.success());
+    assert_eq!(sandbox.list(&[]), ["~"]);
+    assert_eq!(sandbox.list(&["--all"]), ["~", "archive/group/foo"]);
+}
+
+#[test]
+fn ignored_files_are_not_listed() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("foo");
+    sandbox.run(&["new", str(&dir)]);
+    let workspaces = sandbox.path("config/workspaces");
+    fs::copy(workspaces.join("foo.toml"), workspaces.join("foo.bak.toml")).unwrap();
+    fs::copy(workspaces.join("foo.toml"), workspaces.join(".hidden.toml")).unwrap();
+    assert_eq!(sandbox.list(&[]), ["~", "foo", "foo.bak"]);
+
+    fs::write(workspaces.join(".wsignore"), "*.bak.toml\n").unwrap();
+    assert_eq!(sandbox.list(&[]), ["~", "foo"]);
+}
+
+/// Returns a sandbox with open workspace `foo` in the home directory and kitty as the terminal
+fn kitty_sandbox() -> Sandbox {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("home/foo");
+    sandbox.run(&["new", str(&dir)]);
+    sandbox.run(&["set", "foo", "shell.command", "\"zsh\""]);
+    sandbox.run(&["set", "foo", "editor.command", "\"nvim\""]);
+    fs::write(
+        sandbox.path("config/config.toml"),
+        "[terminal]\nbackend = \"kitty\"\n",
+    )
+    .unwrap();
+    sandbox.run(&["open", "foo"]);
+    sandbox
+}
+
+#[test]
+fn print_terminal_command() {
+    let sandbox = kitty_sandbox();
+    assert_eq!(sandbox.run(&["--print-cmd", "terminal"]), "kitty zsh\n");
+    assert_eq!(sandbox.run(&["--print-cmd", "terminal", "--here"]), "zsh\n");
+}
+
+#[test]
+fn print_terminal_multiplexer() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["config", "set", "multiplexer", "tmux"]);
+    let printed = sandbox.run(&["--print-cmd", "terminal", "--here"]);
+    assert!(printed.contains("tmux attach-session -t =foo"), "{printed}");
+    sandbox.run(&["set", "foo", "multiplexer", "none"]);
+    assert_eq!(sandbox.run(&["--print-cmd", "terminal", "--here"]), "zsh\n");
+}
+
+#[test]
+fn print_editor_command() {
+    let sandbox = kitty_sandbox();
+    let printed = sandbox.run(&["--print-cmd", "editor"]);
+    assert!(
+        printed.starts_with("kitty --title 'nvim foo' nvim --listen "),
+        "{printed}"
+    );
+    assert!(printed.ends_with(" .\n"), "{printed}");
+    assert!(!sandbox.path("cache/nvim/foo").exists());
+}
+
+#[test]
+fn print_editor_command_with_arguments() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "editor.command", "\"nvim -u NONE\""]);
+    let printed = sandbox.run(&["--print-cmd", "editor"]);
+    assert!(
+        printed.starts_with("kitty --title 'nvim -u NONE foo' nvim --listen "),
+        "{printed}"
+    );
+    assert!(printed.ends_with(" -u NONE .\n"), "{printed}");
+}
+
+#[test]
+fn print_emacs_command_with_arguments() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "editor.command", "\"emacsclient -s work\""]);
+    sandbox.run(&["set", "foo", "editor.kind", "\"emacs\""]);
+    let dir = sandbox.path("home/foo");
+    assert_eq!(
+        sandbox.run(&["--print-cmd", "editor"]),
+        format!("emacsclient -s work -c -a '' {}\n", str(&dir)),
+    );
+}
+
+#[test]
+fn print_gui_editor_command() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "editor.command", "\"code --new-window\""]);
+    sandbox.run(&["set", "foo", "editor.gui", "true"]);
+    let dir = fs::canonicalize(sandbox.path("home/foo")).unwrap();
+    assert_eq!(
+        sandbox.run(&["--print-cmd", "editor"]),
+        format!("sh -c 'code --new-window {}'\n", str(&dir)),
+    );
+}
+
+#[test]
+fn print_editor_at_without_running_nvim() {
+    let sandbox = kitty_sandbox();
+    let printed = sandbox.run(&["--print-cmd", "editor", "--at", "src/main.rs:12"]);
+    assert!(
+        printed.starts_with("kitty --title 'nvim foo' nvim --listen "),
+        "{printed}"
+    );
+    assert!(printed.ends_with(" +12 src/main.rs\n"), "{printed}");
+    assert!(!sandbox.path("cache/nvim/foo").exists());
+}
+
+#[test]
+fn print_remote_terminal_command() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "ssh.host", "\"example.com\""]);
+    sandbox.run(&["set", "foo", "dir", "\"src/foo\""]);
+    assert_eq!(
+        sandbox.run(&["--print-cmd", "terminal", "--here"]),
+        "ssh -t -o ConnectTimeout=30 example.com 'export WORKSPACECTL_NAME=foo; export WORKSPACECTL_DIR=src/foo; export WORKSPACECTL_HOST=example.com; cd src/foo; exec zsh --login'\n",
+    );
+}
+
+#[test]
+fn print_remote_terminal_home_dir() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "ssh.host", "\"example.com\""]);
+    sandbox.run(&["set", "foo", "dir", "\"~/my src\""]);
+    let printed = sandbox.run(&["--print-cmd", "terminal", "--here"]);
+    assert!(
+        printed.contains(r"cd ~/'\''my src'\''; exec zsh"),
+        "{printed}"
+    );
+}
+
+#[test]
+fn zero_timeout_fails() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "ssh.host", "\"example.com\""]);
+    sandbox.run(&["set", "foo", "timeout", "0"]);
+    let err = sandbox.fail(&["--print-cmd", "terminal", "--here"]);
+    assert!(err.contains("timeout must be at least 1 second"), "{err}");
+}
+
+#[test]
+fn print_remote_terminal_destination() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "ssh.host", "\"example.com\""]);
+    sandbox.run(&["set", "foo", "ssh.user", "\"alice\""]);
+    sandbox.run(&["set", "foo", "ssh.port", "2222"]);
+    sandbox.run(&["set", "foo", "ssh.command", "\"/opt/ssh\""]);
+    let printed = sandbox.run(&["--print-cmd", "terminal", "--here"]);
+    assert!(
+        printed.starts_with("/opt/ssh -t -o ConnectTimeout=30 -l alice -p 2222 example.com "),
+        "{printed}"
+    );
+}
+
+#[test]
+fn print_remote_terminal_teleport() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "ssh.host", "\"example.com\""]);
+    sandbox.run(&["set", "foo", "ssh.kind", "\"teleport\""]);
+    sandbox.run(&["set", "foo", "ssh.port", "2222"]);
+    sandbox.run(&["set", "foo", "ssh.identity_file", "\"~/.ssh/id\""]);
+    // tsh isn't installed here, printing mustn't check the Teleport session.
+    let printed = sandbox.run(&["--print-cmd", "terminal", "--here"]);
+    assert!(
+        printed.starts_with("tsh ssh -t -p 2222 example.com '"),
+        "{printed}"
+    );
+}
+
+#[test]
+fn print_remote_terminal_wrapper() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "ssh.host", "\"example.com\""]);
+    sandbox.run(&["set", "foo", "ssh.user", "\"alice\""]);
+    sandbox.run(&["set", "foo", "ssh.command", "\"tsh ssh\""]);
+    sandbox.run(&[
+        "set",
+        "foo",
+        "ssh.args",
+        r#"["{{options}}", "--login={{user}}", "--port={{port}}", "{{host}}"]"#,
+    ]);
+    let printed = sandbox.run(&["--print-cmd", "terminal", "--here"]);
+    assert!(
+        printed.starts_with("tsh ssh -t -o ConnectTimeout=30 --login=alice example.com "),
+        "{printed}"
+    );
+}
+
+#[test]
+fn print_remote_send_env() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "ssh.host", "\"example.com\""]);
+    sandbox.run(&["set", "foo", "ssh.send_env", "[\"HOME\"]"]);
+    let printed = sandbox.run(&["--print-cmd", "terminal", "--here"]);
+    assert!(printed.contains(" -o SendEnv=HOME "), "{printed}");
+    assert!(printed.contains("<secrets>"), "{printed}");
+    assert!(!printed.contains(str(&sandbox.path("home"))), "{printed}");
+}
+
+#[test]
+fn exec_local() {
+    let sandbox = kitty_sandbox();
+    let printed = sandbox.run(&["exec", "--", "sh", "-c", "echo $WORKSPACECTL_NAME; pwd"]);
+    assert_eq!(
+        printed,
+        format!("foo\n{}\n", str(&sandbox.path("home/foo")))
+    );
+    let err = sandbox.fail(&["exec", "--", "sh", "-c", "exit 3"]);
+    assert_eq!(err, "");
+}
+
+#[test]
+fn print_exec_remote() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "ssh.host", "\"example.com\""]);
+    let printed = sandbox.run(&["--print-cmd", "exec", "--", "make", "test"]);
+    assert!(printed.starts_with("ssh "), "{printed}");
+    assert!(printed.contains(" example.com '"), "{printed}");
+    assert!(
+        printed.contains("export WORKSPACECTL_NAME=foo; "),
+        "{printed}"
+    );
+    assert!(printed.contains("make test"), "{printed}");
+    assert!(!printed.contains(" -t "), "{printed}");
+    let printed = sandbox.run(&["--print-cmd", "exec", "--tty", "--", "htop"]);
+    assert!(printed.contains(" -t example.com "), "{printed}");
+}
+
+#[test]
+fn env_resolved() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "tz", "\"Europe/Prague\""]);
+    assert!(sandbox.run(&["env"]).contains("\nTZ=Europe/Prague\n"));
+    let resolved = sandbox.run(&["env", "--resolved"]);
+    assert!(
+        resolved.contains("\n+ WORKSPACECTL_NAME=foo\n"),
+        "{resolved}"
+    );
+    assert!(resolved.contains("\n  PATH="), "{resolved}");
+}
+
+#[test]
+fn print_open_keeps_state() {
+    let sandbox = kitty_sandbox();
+    let dir = sandbox.dir("home/bar");
+    sandbox.run(&["new", str(&dir)]);
+    sandbox.run(&["--print-cmd", "open", "bar"]);
+    let info = sandbox.run(&["info", "--porcelain"]);
+    assert!(info.lines().any(|line| line == "name\tfoo"), "{info}");
+    let history = fs::read_to_string(sandbox.path("cache/history")).unwrap();
+    assert!(!history.contains("\tbar"), "{history}");
+    let events = fs::read_to_string(sandbox.path("cache/events")).unwrap_or_default();
+    assert!(!events.contains("\"bar\""), "{events}");
+}
+
+#[test]
+fn print_hook_command() {
+    let sandbox = kitty_sandbox();
+    fs::write(
+        sandbox.path("config/config.toml"),
+        "[hooks]\non_open = [\"touch opened\"]\n",
+    )
+    .unwrap();
+    assert_eq!(
+        sandbox.run(&["--print-cmd", "open", "foo"]),
+        "sh -c 'touch opened'\n"
+    );
+    assert!(!sandbox.path("home/foo/opened").exists());
+}
+
+#[test]
+fn print_terminal_hook_command() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["open", "foo"]);
+    sandbox.run(&["set", "foo", "hooks.on_terminal", "[\"touch terminal\"]"]);
+    let printed = sandbox.run(&["--print-cmd", "terminal"]);
+    assert!(printed.starts_with("sh -c 'touch terminal'\n"), "{printed}");
+}
+
+#[test]
+fn print_session_editor_hook_command() {
+    let sandbox = kitty_sandbox();
+    sandbox.run(&["set", "foo", "hooks.on_terminal", "[\"touch terminal\"]"]);
+    sandbox.run(&["set", "foo", "hooks.on_editor", "[\"touch editor\"]"]);
+    let printed = sandbox.run(&["--print-cmd", "start", "foo", "--via-session"]);
+    assert!(
+        printed.starts_with("sh -c 'touch terminal'\nsh -c 'touch editor'\nkitty --session "),
+        "{printed}"
+    );
+}
+
+#[test]
+fn info_lists_hooks() {
+    let sandbox = kitty_sandbox();
+    fs::write(
+        sandbox.path("config/config.toml"),
+        "[hooks]\non_open = [\"touch opened\"]\n",
+    )
+    .unwrap();
+    sandbox.run(&["set", "foo", "hooks.on_open", "[\"make\"]"]);
+    sandbox.run(&["set", "foo", "hooks.on_editor", "[\"touch editor\"]"]);
+    let info = sandbox.run(&["info", "foo"]);
+    assert!(
+        info.contains("on_open:     touch opened\n             make\n"),
+        "{info}"
+    );
+    assert!(info.contains("on_editor:   touch editor\n"), "{info}");
+    assert!(!info.contains("on_terminal:"), "{info}");
+}
+
+#[test]
+fn cat_resolve_origin() {
+    let sandbox = kitty_sandbox();
+    fs::write(sandbox.path("config/config.toml"), "timeout = 5\n").unwrap();
+    let resolved = sandbox.run(&["cat", "--resolve", "--origin", "foo"]);
+    for value in [
+        r#""command":{"origin":"workspace","value":"zsh"}"#,
+        r#""timeout":{"origin":"config","value":5}"#,
+        r#""git_ui":{"origin":"default","value":"lazygit"}"#,
+    ] {
+        assert!(resolved.contains(value), "{value} missing in {resolved}");
+    }
+}
+
+#[test]
+fn project_config_precedence() {
+    let sandbox = kitty_sandbox();
+    fs::write(
+        sandbox.path("home/foo/.workspacectl.toml"),
+        "[shell]\ncommand = \"fish\"\nlogin = true\n",
+    )
+    .unwrap();
+    let output = sandbox.run_with_input(&["cat", "--resolve", "foo"], "");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("until it's allowed"), "{stderr}");
+    let unallowed = String::from_utf8_lossy(&output.stdout);
+    assert!(unallowed.contains(r#""login":false"#), "{unallowed}");
+    sandbox.run(&["project", "allow", "foo"]);
+
+    let resolved = sandbox.run(&["cat", "--resolve", "--origin", "foo"]);
+    for value in [
+        r#""command":{"origin":"workspace","value":"zsh"}"#,
+        r#""login":{"origin":"project","value":true}"#,
+    ] {
+        assert!(resolved.contains(value), "{value} missing in {resolved}");
+    }
+    let ignored = sandbox.run(&["--no-project-config", "cat", "--resolve", "foo"]);
+    assert!(ignored.contains(r#""login":false"#), "{ignored}");
+
+    fs::write(
+        sandbox.path("home/foo/.workspacectl.toml"),
+        "[shell]\ncommand = \"fish\"\nlogin = true\nargs = [\"-c\", \"x\"]\n",
+    )
+    .unwrap();
+    let changed = sandbox.run(&["cat", "--resolve", "foo"]);
+    assert!(changed.contains(r#""login":false"#), "{changed}");
+}
+
+#[test]
+fn list_json() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir("home/foo");
+    sandbox.run(&["new", str(&dir)]);
+    sandbox.run(&["open", "foo"]);
+    let list = sandbox.run(&["list", "--json"]);
+    let expected = format!(
+        r#"[{{"current":true,"dir":{:?},"host":null,"name":"foo"}}]"#,
+        str(&dir),
+    );
+    assert_eq!(list.trim_end(), expected);
+}
+
+#[test]
+fn config_set_and_get() {
+    let sandbox = Sandbox::new();
+    sandbox.run(&["config", "set", "list.sort", "recent"]);
+    assert_eq!(sandbox.run(&["config", "get", "list.sort"]), "recent\n");
+    let shown = sandbox.run(&["config", "show", "--origin"]);
+    for value in [
+        r#""sort":{"origin":"config","value":"recent"}"#,
+        r#""ui":{"origin":"default","value":"lazygit"}"#,
+        r#""multiplexer":{"origin":"default","value":null}"#,
+    ] {
+        assert!(shown.contains(value), "{value} missing in {shown}");
+    }
+    let err = sandbox.fail(&["config", "set", "list.sortt", "recent"]);
+    assert!(err.contains("did you mean \"sort\""), "{err}");
+}